@@ -0,0 +1,74 @@
+use futures_util::future::{AbortHandle, Abortable, Aborted};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager};
+use tracing::info;
+
+// ==================== 进行中请求登记表 ====================
+
+/// 进行中请求的登记表（Tauri 托管状态）
+///
+/// 以前端传入的 request_id / channel_id 为键保存中止句柄，
+/// 中止时会直接丢弃正在执行的 future，底层 reqwest 连接随之关闭。
+#[derive(Clone, Default)]
+pub struct RequestRegistry {
+    handles: Arc<Mutex<HashMap<String, (u64, AbortHandle)>>>,
+    next_generation: Arc<AtomicU64>,
+}
+
+impl RequestRegistry {
+    /// 从 AppHandle 中取出登记表
+    pub fn from_app(app_handle: &AppHandle) -> Self {
+        app_handle.state::<RequestRegistry>().inner().clone()
+    }
+
+    /// 以可中止的方式执行 future；request_id 为空时直接执行
+    pub async fn run<F: Future>(&self, request_id: Option<&str>, future: F) -> Result<F::Output, Aborted> {
+        let Some(request_id) = request_id else {
+            return Ok(future.await);
+        };
+
+        let (handle, registration) = AbortHandle::new_pair();
+        // 同一个 id 可能被复用，用代数区分，避免结束时误删后来登记的请求
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        self.handles
+            .lock()
+            .unwrap()
+            .insert(request_id.to_string(), (generation, handle));
+
+        let result = Abortable::new(future, registration).await;
+
+        let mut handles = self.handles.lock().unwrap();
+        if handles.get(request_id).map(|(g, _)| *g) == Some(generation) {
+            handles.remove(request_id);
+        }
+        result
+    }
+
+    /// 中止指定请求，返回是否找到该请求
+    pub fn cancel(&self, request_id: &str) -> bool {
+        match self.handles.lock().unwrap().remove(request_id) {
+            Some((_, handle)) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// ==================== Tauri 命令 ====================
+
+/// 取消进行中的请求（流式请求传 channel_id），并推送 cancelled://{request_id}
+#[tauri::command]
+pub fn cancel_request(app_handle: AppHandle, request_id: String) -> bool {
+    let cancelled = RequestRegistry::from_app(&app_handle).cancel(&request_id);
+    info!(request_id = %request_id, found = cancelled, "cancel_request");
+
+    if cancelled {
+        let _ = app_handle.emit::<()>(&format!("cancelled://{}", request_id), ());
+    }
+    cancelled
+}
@@ -0,0 +1,469 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::{stream, StreamExt};
+use http_body_util::BodyExt;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Body, Request, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+use tauri::{AppHandle, Manager};
+use tracing::{debug, info, warn};
+
+use crate::error::ApiError;
+use crate::logging::{redact, redact_secrets};
+
+// 录制 / 回放模式与目录，可通过环境变量在启动时指定，如 NEXTLEMON_CASSETTE=replay
+const CASSETTE_MODE_ENV: &str = "NEXTLEMON_CASSETTE";
+const CASSETTE_DIR_ENV: &str = "NEXTLEMON_CASSETTE_DIR";
+
+// 这些请求头 / 响应头的值不写入录制文件
+const SENSITIVE_HEADERS: [&str; 6] = [
+    "authorization",
+    "x-api-key",
+    "x-goog-api-key",
+    "api-key",
+    "cookie",
+    "set-cookie",
+];
+
+// ==================== 模式与配置 ====================
+
+/// 外部请求的录制 / 回放模式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CassetteMode {
+    // 正常发送请求
+    #[default]
+    Off,
+    // 正常发送请求，并把脱敏后的请求与响应写入录制目录
+    Record,
+    // 不访问网络，从录制目录中返回匹配的响应
+    Replay,
+}
+
+impl CassetteMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" | "" => Some(CassetteMode::Off),
+            "record" => Some(CassetteMode::Record),
+            "replay" => Some(CassetteMode::Replay),
+            _ => None,
+        }
+    }
+}
+
+/// configure_cassette 参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CassetteConfig {
+    pub mode: CassetteMode,
+    pub dir: Option<String>, // 录制目录，未传时沿用当前目录
+}
+
+#[derive(Default)]
+struct CassetteState {
+    mode: CassetteMode,
+    dir: Option<PathBuf>,
+    // 相同请求的出现次数，用于区分轮询等重复请求的各次响应
+    sequence: HashMap<String, u32>,
+}
+
+// 发送请求的函数没有 AppHandle，因此使用进程级状态
+static CASSETTE: LazyLock<Mutex<CassetteState>> = LazyLock::new(Mutex::default);
+
+/// 初始化录制状态：读取环境变量，默认目录为 app_data_dir/cassettes
+///
+/// 在 setup 中调用一次。
+pub fn init_cassette(app: &AppHandle) {
+    let dir = std::env::var(CASSETTE_DIR_ENV)
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| app.path().app_data_dir().ok().map(|dir| dir.join("cassettes")));
+
+    let mode = match std::env::var(CASSETTE_MODE_ENV) {
+        Ok(value) => CassetteMode::parse(&value).unwrap_or_else(|| {
+            warn!("Unknown {} value {:?}, cassette disabled", CASSETTE_MODE_ENV, value);
+            CassetteMode::Off
+        }),
+        Err(_) => CassetteMode::Off,
+    };
+    if mode != CassetteMode::Off {
+        info!(mode = ?mode, dir = ?dir, "Cassette enabled");
+    }
+
+    let mut state = CASSETTE.lock().unwrap();
+    state.mode = mode;
+    state.dir = dir;
+}
+
+/// 切换录制 / 回放模式，返回实际使用的录制目录
+#[tauri::command]
+pub fn configure_cassette(config: CassetteConfig) -> Result<Option<String>, ApiError> {
+    info!(mode = ?config.mode, dir = ?config.dir, "configure_cassette");
+    let mut state = CASSETTE.lock().unwrap();
+    if let Some(dir) = config.dir.filter(|dir| !dir.is_empty()) {
+        state.dir = Some(PathBuf::from(dir));
+    }
+    if config.mode != CassetteMode::Off && state.dir.is_none() {
+        return Err(ApiError::invalid_request("未指定录制目录"));
+    }
+    state.mode = config.mode;
+    // 切换模式后重新计数，回放从每个请求的第一次响应开始
+    state.sequence.clear();
+    Ok(state.dir.as_ref().map(|dir| dir.to_string_lossy().into_owned()))
+}
+
+// ==================== 录制文件 ====================
+
+// 录制文件：{dir}/{请求指纹}-{序号}.json
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CassetteEntry {
+    recorded_at: String,
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+// 脱敏后的请求，仅供查看，匹配只依据指纹
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecordedRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Option<String>, // 已省略 base64 数据；二进制请求体只记录大小
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecordedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    // 为 true 时 chunks 是 base64 编码的二进制数据（视频、图片等）
+    binary: bool,
+    // 按到达顺序保存的响应分块，流式响应回放时逐块返回
+    chunks: Vec<String>,
+}
+
+/// 按当前模式发送请求：录制模式下记录响应，回放模式下不访问网络
+pub async fn send_with_cassette(builder: RequestBuilder) -> Result<Response, reqwest::Error> {
+    if CASSETTE.lock().unwrap().mode == CassetteMode::Off {
+        return builder.send().await;
+    }
+
+    let (client, request) = builder.build_split();
+    let mut request = request?;
+    buffer_body(&mut request).await?;
+    let key = fingerprint(&request);
+    let (mode, dir, seq) = {
+        let mut state = CASSETTE.lock().unwrap();
+        let seq = state.sequence.entry(key.clone()).or_insert(0);
+        let current = *seq;
+        *seq += 1;
+        (state.mode, state.dir.clone(), current)
+    };
+    let Some(dir) = dir.filter(|_| mode != CassetteMode::Off) else {
+        return client.execute(request).await;
+    };
+
+    let recorded_request = RecordedRequest::from_request(&request);
+    match mode {
+        CassetteMode::Replay => Ok(replay(&dir, &key, seq, &recorded_request).await),
+        _ => {
+            let response = client.execute(request).await?;
+            Ok(record(response, dir.join(format!("{}-{}.json", key, seq)), recorded_request))
+        }
+    }
+}
+
+// multipart 等流式请求体先读入内存，之后指纹、录制与实际发送都使用同一份字节
+async fn buffer_body(request: &mut Request) -> Result<(), reqwest::Error> {
+    if request.body().is_none_or(|body| body.as_bytes().is_some()) {
+        return Ok(());
+    }
+    let Some(body) = request.body_mut().take() else {
+        return Ok(());
+    };
+    let bytes = body.collect().await?.to_bytes();
+    *request.body_mut() = Some(Body::from(bytes));
+    Ok(())
+}
+
+// 请求指纹：方法 + 脱敏后的 URL + 请求体（不含请求头，避免 Key 影响匹配）
+fn fingerprint(request: &Request) -> String {
+    let mut hash = Fnv64::default();
+    hash.write(request.method().as_str().as_bytes());
+    hash.write(redact_secrets(request.url().as_str()).as_bytes());
+    if let Some(body) = request.body().and_then(|body| body.as_bytes()) {
+        match multipart_boundary(request.headers()) {
+            // multipart 边界每次随机生成，替换为固定值后再计算
+            Some(boundary) => {
+                for (index, part) in split_bytes(body, boundary.as_bytes()).enumerate() {
+                    if index > 0 {
+                        hash.write(b"--boundary");
+                    }
+                    hash.write(part);
+                }
+            }
+            None => hash.write(body),
+        }
+    }
+    format!("{:016x}", hash.0)
+}
+
+fn multipart_boundary(headers: &HeaderMap) -> Option<String> {
+    let content_type = headers.get(reqwest::header::CONTENT_TYPE)?.to_str().ok()?;
+    if !content_type.starts_with("multipart/") {
+        return None;
+    }
+    content_type
+        .split(';')
+        .find_map(|param| param.trim().strip_prefix("boundary="))
+        .map(|boundary| boundary.trim_matches('"').to_string())
+        .filter(|boundary| !boundary.is_empty())
+}
+
+// 按分隔符切分字节序列（分隔符本身不包含在结果中）
+fn split_bytes<'a>(
+    mut bytes: &'a [u8],
+    separator: &'a [u8],
+) -> impl Iterator<Item = &'a [u8]> + 'a {
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        match bytes.windows(separator.len()).position(|window| window == separator) {
+            Some(index) => {
+                let part = &bytes[..index];
+                bytes = &bytes[index + separator.len()..];
+                Some(part)
+            }
+            None => {
+                done = true;
+                Some(bytes)
+            }
+        }
+    })
+}
+
+// FNV-1a：指纹需要在不同版本间保持稳定，不能使用 DefaultHasher
+struct Fnv64(u64);
+
+impl Default for Fnv64 {
+    fn default() -> Self {
+        Fnv64(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv64 {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+impl RecordedRequest {
+    fn from_request(request: &Request) -> Self {
+        RecordedRequest {
+            method: request.method().to_string(),
+            url: redact_secrets(request.url().as_str()).into_owned(),
+            headers: redact_headers(request.headers()),
+            // 含图片等二进制内容的请求体（如 multipart 上传）只记录大小
+            body: request
+                .body()
+                .and_then(|body| body.as_bytes())
+                .map(|body| match std::str::from_utf8(body) {
+                    Ok(text) => redact(text).into_owned(),
+                    Err(_) => format!("<{} bytes binary body>", body.len()),
+                }),
+        }
+    }
+}
+
+fn redact_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                "***".to_string()
+            } else {
+                redact_secrets(&String::from_utf8_lossy(value.as_bytes())).into_owned()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+// 边转发边记录响应分块，响应体读取完毕后写入录制文件；调用方中途放弃读取时不会生成录制
+fn record(response: Response, path: PathBuf, request: RecordedRequest) -> Response {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let recorded_headers = redact_headers(&headers);
+    let chunks: Arc<Mutex<Vec<Vec<u8>>>> = Arc::default();
+
+    let collected = chunks.clone();
+    let body = response
+        .bytes_stream()
+        .map(move |chunk| {
+            if let Ok(bytes) = &chunk {
+                collected.lock().unwrap().push(bytes.to_vec());
+            }
+            chunk
+        })
+        .chain(
+            stream::once(async move {
+                let chunks = std::mem::take(&mut *chunks.lock().unwrap());
+                let entry = CassetteEntry {
+                    recorded_at: chrono::Utc::now().to_rfc3339(),
+                    request,
+                    response: RecordedResponse::new(status, recorded_headers, chunks),
+                };
+                if let Err(e) = write_entry(&path, &entry).await {
+                    warn!("Failed to write cassette {}: {}", path.display(), e);
+                }
+            })
+            .filter_map(|()| async { None }),
+        );
+
+    build_response(status, &headers, Body::wrap_stream(body))
+}
+
+impl RecordedResponse {
+    fn new(status: StatusCode, headers: Vec<(String, String)>, chunks: Vec<Vec<u8>>) -> Self {
+        let binary_type = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .is_some_and(|(_, value)| is_binary_content_type(value));
+        let text_chunks = if binary_type { None } else { decode_text_chunks(&chunks) };
+        // 录制的分块与原始长度不同（脱敏、重新分块），回放时由 Body 自行决定长度
+        let headers = headers
+            .into_iter()
+            .filter(|(name, _)| {
+                !name.eq_ignore_ascii_case("content-length")
+                    && !name.eq_ignore_ascii_case("transfer-encoding")
+            })
+            .collect();
+        match text_chunks {
+            Some(chunks) => RecordedResponse {
+                status: status.as_u16(),
+                headers,
+                binary: false,
+                chunks: chunks.iter().map(|chunk| redact_secrets(chunk).into_owned()).collect(),
+            },
+            None => RecordedResponse {
+                status: status.as_u16(),
+                headers,
+                binary: true,
+                chunks: chunks.into_iter().map(|chunk| STANDARD.encode(chunk)).collect(),
+            },
+        }
+    }
+
+    fn chunks(&self) -> Vec<Result<Vec<u8>, std::io::Error>> {
+        self.chunks
+            .iter()
+            .map(|chunk| {
+                if self.binary {
+                    STANDARD
+                        .decode(chunk)
+                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+                } else {
+                    Ok(chunk.clone().into_bytes())
+                }
+            })
+            .collect()
+    }
+}
+
+fn is_binary_content_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    const BINARY_TYPES: [&str; 4] = [
+        "application/octet-stream",
+        "application/pdf",
+        "application/zip",
+        "application/gzip",
+    ];
+    ["image/", "video/", "audio/", "font/"]
+        .iter()
+        .any(|prefix| mime.starts_with(prefix))
+        || BINARY_TYPES.contains(&mime.as_str())
+}
+
+// 按 UTF-8 解码各分块：被分块截断的多字节字符移到下一块；整体不是有效 UTF-8 时返回 None
+fn decode_text_chunks(chunks: &[Vec<u8>]) -> Option<Vec<String>> {
+    let mut texts = Vec::with_capacity(chunks.len());
+    let mut pending: Vec<u8> = Vec::new();
+    for chunk in chunks {
+        pending.extend_from_slice(chunk);
+        let valid = match std::str::from_utf8(&pending) {
+            Ok(_) => pending.len(),
+            // error_len 为 None 表示末尾是不完整的字符，其余情况是无效字节
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return None,
+        };
+        let rest = pending.split_off(valid);
+        texts.push(String::from_utf8(std::mem::replace(&mut pending, rest)).ok()?);
+    }
+    pending.is_empty().then_some(texts)
+}
+
+async fn write_entry(path: &Path, entry: &CassetteEntry) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await.map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_vec_pretty(entry).map_err(|e| e.to_string())?;
+    tokio::fs::write(path, json).await.map_err(|e| e.to_string())?;
+    debug!("Cassette recorded: {}", path.display());
+    Ok(())
+}
+
+// 回放第 seq 次出现的请求；录制次数不足时使用最后一次（如轮询任务状态）
+async fn replay(dir: &Path, key: &str, seq: u32, request: &RecordedRequest) -> Response {
+    for index in (0..=seq).rev() {
+        let path = dir.join(format!("{}-{}.json", key, index));
+        let Ok(content) = tokio::fs::read(&path).await else {
+            continue;
+        };
+        match serde_json::from_slice::<CassetteEntry>(&content) {
+            Ok(entry) => {
+                debug!("Cassette replayed: {}", path.display());
+                let status = StatusCode::from_u16(entry.response.status).unwrap_or(StatusCode::OK);
+                let headers = entry
+                    .response
+                    .headers
+                    .iter()
+                    .filter_map(|(name, value)| {
+                        let name = HeaderName::try_from(name.as_str()).ok()?;
+                        let value = HeaderValue::try_from(value.as_str()).ok()?;
+                        Some((name, value))
+                    })
+                    .collect();
+                return build_response(status, &headers, Body::wrap_stream(stream::iter(entry.response.chunks())));
+            }
+            Err(e) => warn!("Invalid cassette {}: {}", path.display(), e),
+        }
+    }
+
+    // 没有匹配的录制时返回错误响应，而不是访问网络
+    warn!(method = %request.method, url = %request.url, "No cassette for request {}", key);
+    let body = serde_json::json!({
+        "error": {
+            "message": format!("回放模式下没有找到匹配的录制：{} {}（{}）", request.method, request.url, key),
+        }
+    });
+    let mut headers = HeaderMap::new();
+    headers.insert(reqwest::header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    build_response(StatusCode::NOT_IMPLEMENTED, &headers, Body::from(body.to_string()))
+}
+
+fn build_response(status: StatusCode, headers: &HeaderMap, body: Body) -> Response {
+    let mut response = http::Response::new(body);
+    *response.status_mut() = status;
+    *response.headers_mut() = headers.clone();
+    Response::from(response)
+}
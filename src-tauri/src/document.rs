@@ -0,0 +1,54 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+
+// ==================== 文档文本提取 ====================
+
+// 单个文档提取出的文本上限（字符），避免超长 PDF 撑爆上下文
+const MAX_EXTRACTED_CHARS: usize = 200_000;
+
+/// 是否为 PDF 文档
+pub fn is_pdf(mime_type: &str) -> bool {
+    mime_type.eq_ignore_ascii_case("application/pdf")
+}
+
+/// 是否为可直接按 UTF-8 读取的文本类文件
+pub fn is_text(mime_type: &str) -> bool {
+    let mime_type = mime_type.to_ascii_lowercase();
+    mime_type.starts_with("text/")
+        || matches!(
+            mime_type.as_str(),
+            "application/json" | "application/xml" | "application/x-yaml" | "application/yaml"
+        )
+}
+
+/// 从 base64 编码的 PDF 或文本文件中提取纯文本
+///
+/// 返回的文本带有文件名标题，便于模型区分多个附件；超过上限时截断。
+pub fn extract_text(data: &str, mime_type: &str, file_name: Option<&str>) -> Result<String, String> {
+    let bytes = BASE64
+        .decode(data)
+        .map_err(|e| format!("附件 Base64 解码失败: {}", e))?;
+
+    let text = if is_pdf(mime_type) {
+        // pdf-extract 遇到不规范的 PDF 可能 panic，这里兜底转为错误
+        std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(&bytes))
+            .map_err(|_| "PDF 解析失败".to_string())?
+            .map_err(|e| format!("PDF 文本提取失败: {}", e))?
+    } else if is_text(mime_type) {
+        String::from_utf8_lossy(&bytes).into_owned()
+    } else {
+        return Err(format!("不支持从 {} 提取文本", mime_type));
+    };
+
+    let text = text.trim();
+    if text.is_empty() {
+        // 扫描版 PDF 没有文本层
+        return Err("未能从文档中提取到文本（可能是扫描件）".to_string());
+    }
+
+    let text = match text.char_indices().nth(MAX_EXTRACTED_CHARS) {
+        Some((end, _)) => format!("{}\n…（内容过长，已截断）", &text[..end]),
+        None => text.to_string(),
+    };
+
+    Ok(format!("[附件: {}]\n{}", file_name.unwrap_or("未命名文档"), text))
+}
@@ -0,0 +1,188 @@
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// ==================== 统一错误模型 ====================
+
+/// 机器可读的错误类别，前端据此决定提示文案与处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidApiKey,      // API Key 无效或缺失
+    PermissionDenied,   // 无权访问该模型或资源
+    QuotaExceeded,      // 余额不足或配额用尽（重试无效）
+    RateLimited,        // 请求过于频繁（稍后可重试）
+    InvalidRequest,     // 请求参数错误
+    NotFound,           // 模型或任务不存在
+    ContentFiltered,    // 提示词或生成内容被安全策略拦截
+    OutputTruncated,    // 输出达到 token 上限且没有可用内容
+    Timeout,            // 请求超时
+    Network,            // 无法连接到服务（包括本地 OCR 等服务未启动）
+    ServiceUnavailable, // 服务端错误或过载
+    MalformedResponse,  // 响应无法解析或缺少必要内容
+    ProviderError,      // 供应商返回的其他错误
+    Cancelled,          // 请求已被用户取消
+    Storage,            // 本地文件读写失败
+}
+
+impl ErrorCode {
+    // 该类错误重试后是否可能成功
+    fn default_retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::RateLimited | ErrorCode::Timeout | ErrorCode::Network | ErrorCode::ServiceUnavailable
+        )
+    }
+}
+
+/// 所有命令共用的错误类型
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub status: Option<u16>,                 // HTTP 状态码（有响应时）
+    pub provider_error_type: Option<String>, // 供应商返回的错误类型，如 insufficient_quota、RESOURCE_EXHAUSTED
+    pub retryable: bool,
+    pub message: String, // 面向用户的错误描述
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            status: None,
+            provider_error_type: None,
+            retryable: code.default_retryable(),
+            message: message.into(),
+        }
+    }
+
+    pub fn cancelled() -> Self {
+        Self::new(ErrorCode::Cancelled, "请求已取消")
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidRequest, message)
+    }
+
+    pub fn malformed(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::MalformedResponse, message)
+    }
+
+    pub fn storage(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Storage, message)
+    }
+
+    pub fn with_provider_type(mut self, provider_error_type: Option<String>) -> Self {
+        self.provider_error_type = provider_error_type;
+        self
+    }
+
+    /// 替换错误描述，保留错误类别
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    /// 在错误描述前加上所处步骤，如「OCR 服务调用失败: ...」
+    pub fn context(mut self, step: &str) -> Self {
+        self.message = format!("{}: {}", step, self.message);
+        self
+    }
+
+    /// 网络层错误（请求未得到响应）
+    pub fn from_request_error(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::new(ErrorCode::Timeout, "请求超时，请稍后重试")
+        } else if error.is_connect() {
+            Self::new(ErrorCode::Network, "无法连接到服务器，请检查网络")
+        } else {
+            Self::new(ErrorCode::Network, format!("请求失败: {}", error))
+        }
+    }
+
+    /// 非 2xx 响应：按状态码与供应商错误体归类
+    pub fn from_response(status: StatusCode, body: &str) -> Self {
+        let detail = ProviderErrorDetail::parse(body);
+        let code = classify(status, &detail);
+        Self {
+            code,
+            status: Some(status.as_u16()),
+            provider_error_type: detail.error_type,
+            retryable: code.default_retryable(),
+            message: format!("API 返回错误 ({}): {}", status, body),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+// ==================== 供应商错误体解析 ====================
+
+// 各协议错误体的公共部分：
+// OpenAI  {"error": {"message", "type", "code"}}
+// Claude  {"type": "error", "error": {"type", "message"}}
+// Gemini  {"error": {"code", "message", "status"}}
+#[derive(Debug, Default)]
+struct ProviderErrorDetail {
+    error_type: Option<String>,
+    message: String,
+}
+
+impl ProviderErrorDetail {
+    fn parse(body: &str) -> Self {
+        let Ok(value) = serde_json::from_str::<serde_json::Value>(body) else {
+            return Self {
+                error_type: None,
+                message: body.to_string(),
+            };
+        };
+        let error = value.get("error").unwrap_or(&value);
+        let field = |name: &str| error.get(name).and_then(|v| v.as_str()).map(|s| s.to_string());
+
+        Self {
+            // OpenAI 的 code 比 type 更具体（如 insufficient_quota、invalid_api_key）
+            error_type: field("code").or_else(|| field("status")).or_else(|| field("type")),
+            message: field("message").unwrap_or_else(|| body.to_string()),
+        }
+    }
+
+    fn mentions(&self, keywords: &[&str]) -> bool {
+        let haystack = format!("{} {}", self.error_type.as_deref().unwrap_or(""), self.message).to_lowercase();
+        keywords.iter().any(|keyword| haystack.contains(keyword))
+    }
+}
+
+fn classify(status: StatusCode, detail: &ProviderErrorDetail) -> ErrorCode {
+    // Gemini 的无效 Key 以 400 返回
+    if detail.mentions(&["api_key_invalid", "invalid_api_key", "api key not valid", "incorrect api key"]) {
+        return ErrorCode::InvalidApiKey;
+    }
+    if detail.mentions(&["content_policy", "content_filter", "content management policy"]) {
+        return ErrorCode::ContentFiltered;
+    }
+
+    match status.as_u16() {
+        401 => ErrorCode::InvalidApiKey,
+        402 => ErrorCode::QuotaExceeded,
+        403 if detail.mentions(&["quota", "billing", "balance", "余额"]) => ErrorCode::QuotaExceeded,
+        403 => ErrorCode::PermissionDenied,
+        404 => ErrorCode::NotFound,
+        408 | 504 => ErrorCode::Timeout,
+        // 429 同时用于限流与配额用尽，后者重试无效
+        429 if detail.mentions(&["insufficient_quota", "quota", "billing", "balance", "余额"]) => {
+            ErrorCode::QuotaExceeded
+        }
+        429 => ErrorCode::RateLimited,
+        400 | 413 | 422 => ErrorCode::InvalidRequest,
+        // 529 为 Claude 的过载状态码
+        500..=503 | 529 => ErrorCode::ServiceUnavailable,
+        _ => ErrorCode::ProviderError,
+    }
+}
@@ -1,19 +1,31 @@
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
 use tauri::{AppHandle, Emitter};
-use futures_util::StreamExt;
+use tracing::{debug, info, warn};
+
+use crate::cancellation::RequestRegistry;
+use crate::error::{ApiError, ErrorCode};
+use crate::http::{send_with_retry, HttpClientManager, Idempotency, RetryPolicy, TimeoutKey};
+use crate::llm::{emit_stream_cancelled, spawn_stream_pump, ProviderProtocol, UsageScope};
+use crate::usage::{record_usage, TokenUsage};
+use crate::logging::{log_preview, request_key};
+use crate::sse::StreamEvent;
+use crate::media::{prepare_input_images, ImageTarget, InputImage};
+use crate::vault::resolve_api_key;
 
 // Lemon API 流式请求参数
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LemonStreamParams {
     pub base_url: String,
-    pub api_key: String,
+    #[serde(default)]
+    pub api_key: String, // 未使用凭据库时由前端直接传入
+    pub provider_id: Option<String>, // 凭据库中的供应商 ID，优先于 api_key
     pub model: String,
     pub prompt: String,
     pub input_images: Option<Vec<String>>,
     pub channel_id: String, // 用于区分不同的 SSE 频道
+    pub retry: Option<RetryPolicy>, // 重试策略（仅重试建立连接阶段）
+    pub canvas_id: Option<String>,  // 用量记账归属的画布
 }
 
 // 简单的 OpenAI 格式请求体（Lemon API 兼容）
@@ -23,6 +35,8 @@ struct OpenAIRequest {
     messages: Vec<OpenAIMessage>,
     temperature: f64,
     stream: bool,
+    // 要求在流的最后返回用量，否则流式调用无法记账
+    stream_options: serde_json::Value,
 }
 
 #[derive(Debug, Serialize)]
@@ -53,20 +67,20 @@ struct OpenAIImageUrl {
 
 // Rust Command: Lemon API 流式生成
 #[tauri::command]
-pub async fn lemon_stream_generation(app_handle: AppHandle, params: LemonStreamParams) -> Result<(), String> {
-    println!("[Rust] lemon_stream_generation called, channel_id: {}", params.channel_id);
-
-    // 构建消息内容
-    let content = if let Some(images) = &params.input_images {
+#[tracing::instrument(name = "request", skip_all, fields(request_id = %params.channel_id))]
+pub async fn lemon_stream_generation(app_handle: AppHandle, mut params: LemonStreamParams) -> Result<(), ApiError> {
+    info!(model = %params.model, "lemon_stream_generation called");
+    params.api_key = resolve_api_key(&app_handle, params.provider_id.as_deref(), &params.api_key).await?;
+
+    // 构建消息内容（按实际内容识别图片格式，并按 Chat Completions 的限制缩放、压缩）
+    let mut warnings = Vec::new();
+    let content = if let Some(images) = params.input_images.take() {
+        let prepared = prepare_input_images(images, ImageTarget::OpenAIChat).await?;
+        warnings = prepared.warnings;
         let mut parts = vec![OpenAIContentPart::Text { text: params.prompt.clone() }];
-        for img in images {
-            let url = if img.starts_with("data:") {
-                img.clone()
-            } else {
-                format!("data:image/png;base64,{}", img)
-            };
+        for image in prepared.images {
             parts.push(OpenAIContentPart::ImageUrl {
-                image_url: OpenAIImageUrl { url }
+                image_url: OpenAIImageUrl { url: image.to_data_url() }
             });
         }
         OpenAIMessageContent::MultiPart(parts)
@@ -82,55 +96,61 @@ pub async fn lemon_stream_generation(app_handle: AppHandle, params: LemonStreamP
         }],
         temperature: 0.7,
         stream: true,
+        stream_options: serde_json::json!({ "include_usage": true }),
     };
 
     let url = format!("{}/v1/chat/completions", params.base_url.trim_end_matches('/'));
     
-    // 创建客户端
-    let client = Client::builder()
-        .timeout(Duration::from_secs(600))
-        .build()
-        .map_err(|e| e.to_string())?;
-
-    // 发起请求
-    let response = client.post(&url)
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", params.api_key))
-        .json(&request_body)
-        .send()
-        .await
-        .map_err(|e| format!("Network request failed: {}", e))?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let err_text = response.text().await.unwrap_or_default();
-        return Err(format!("API Error ({}): {}", status, err_text));
-    }
+    // 使用共享 HTTP 客户端
+    let http = HttpClientManager::from_app(&app_handle);
+    let client = http.client();
+    let timeout = http.timeout(TimeoutKey::LemonStream);
+
+    // 发起请求（发送阶段同样可以通过 channel_id 取消）
+    let registry = RequestRegistry::from_app(&app_handle);
+    let policy = params.retry.clone().unwrap_or_default();
+    let send = async {
+        let outcome = send_with_retry(&policy, Idempotency::Billed, "Lemon stream", || {
+            client.post(&url)
+                .timeout(timeout)
+                .header("Content-Type", "application/json")
+                .header("Authorization", format!("Bearer {}", params.api_key))
+                .json(&request_body)
+        })
+        .await;
+        debug!("Stream request attempts: {}", outcome.attempts);
+        let response = outcome.result.map_err(|e| ApiError::from_request_error(&e))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let err_text = response.text().await.unwrap_or_default();
+            return Err(ApiError::from_response(status, &err_text));
+        }
+        Ok(response)
+    };
 
-    // 处理流
-    let mut stream = response.bytes_stream();
-    let channel_id = params.channel_id.clone();
-    
-    // 使用 tokio spawn 异步处理流，不阻塞当前命令返回
-    tauri::async_runtime::spawn(async move {
-        while let Some(chunk_result) = stream.next().await {
-            match chunk_result {
-                Ok(chunk) => {
-                    if let Ok(text) = String::from_utf8(chunk.to_vec()) {
-                         // 直接将原始 chunk 文本发送给前端，由前端解析 SSE
-                        let _ = app_handle.emit::<String>(&format!("stream://{}", channel_id), text);
-                    }
-                },
-                Err(e) => {
-                    println!("[Rust] Stream error: {}", e);
-                    let _ = app_handle.emit::<String>(&format!("stream-error://{}", channel_id), e.to_string());
-                    break;
-                }
-            }
+    let response = match registry.run(Some(&params.channel_id), send).await {
+        Ok(result) => result?,
+        Err(_) => {
+            emit_stream_cancelled(&app_handle, &params.channel_id);
+            return Err(ApiError::cancelled());
         }
-        // 发送完成信号
-        let _ = app_handle.emit::<()>(&format!("stream-done://{}", channel_id), ());
-    });
+    };
+
+    // 输入图片的调整提示先于正文推送
+    for warning in warnings {
+        let _ = app_handle.emit::<StreamEvent>(
+            &format!("stream://{}", params.channel_id),
+            StreamEvent::Warning { message: warning },
+        );
+    }
+
+    // 在后台任务中解析 SSE 并推送类型化事件，不阻塞当前命令返回
+    let scope = UsageScope {
+        canvas_id: params.canvas_id,
+        model: params.model,
+    };
+    spawn_stream_pump(app_handle, ProviderProtocol::Openai.adapter(), params.channel_id, scope, true, response);
 
     Ok(())
 }
@@ -146,6 +166,9 @@ pub struct GeminiRequest {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Content {
+    // 对话角色："user" 或 "model"（单轮请求可省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
     pub parts: Vec<Part>,
 }
 
@@ -154,6 +177,33 @@ pub struct Content {
 pub enum Part {
     Text { text: String },
     InlineData { inline_data: InlineData },
+    FunctionCall {
+        #[serde(rename = "functionCall")]
+        function_call: FunctionCall,
+    },
+    FunctionResponse {
+        #[serde(rename = "functionResponse")]
+        function_response: FunctionResponse,
+    },
+}
+
+// 模型发起的函数调用
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FunctionCall {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub args: serde_json::Value,
+}
+
+// 回传给模型的函数执行结果
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FunctionResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    pub response: serde_json::Value,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -169,6 +219,8 @@ pub struct GenerationConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub response_modalities: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub image_config: Option<ImageConfig>,
 }
 
@@ -185,12 +237,59 @@ pub struct ImageConfig {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GeminiResponse {
     pub candidates: Option<Vec<Candidate>>,
+    #[serde(rename = "usageMetadata")]
+    pub usage_metadata: Option<UsageMetadata>,
+    #[serde(rename = "promptFeedback")]
+    pub prompt_feedback: Option<PromptFeedback>,
     pub error: Option<GeminiError>,
 }
 
+// 提示词反馈：提示词本身被拦截时不会返回任何候选
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptFeedback {
+    pub block_reason: Option<String>,
+    pub block_reason_message: Option<String>,
+    pub safety_ratings: Option<Vec<SafetyRating>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetyRating {
+    pub category: String,
+    pub probability: Option<String>,
+    pub blocked: Option<bool>,
+}
+
+// Token 用量（输出包含思考部分）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageMetadata {
+    pub prompt_token_count: Option<u64>,
+    pub candidates_token_count: Option<u64>,
+    pub cached_content_token_count: Option<u64>,
+    pub thoughts_token_count: Option<u64>,
+}
+
+impl UsageMetadata {
+    pub fn to_usage(&self) -> TokenUsage {
+        TokenUsage {
+            input_tokens: self.prompt_token_count.unwrap_or(0),
+            output_tokens: self.candidates_token_count.unwrap_or(0)
+                + self.thoughts_token_count.unwrap_or(0),
+            cached_tokens: self.cached_content_token_count.unwrap_or(0),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Candidate {
     pub content: Option<CandidateContent>,
+    pub finish_reason: Option<String>,
+    pub finish_message: Option<String>,
+    pub safety_ratings: Option<Vec<SafetyRating>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -202,397 +301,372 @@ pub struct CandidateContent {
 #[serde(rename_all = "camelCase")]
 pub struct ResponsePart {
     pub text: Option<String>,
+    // 为 true 时 text 是思考摘要而非正文
+    pub thought: Option<bool>,
     pub inline_data: Option<InlineData>,
+    pub function_call: Option<FunctionCall>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GeminiError {
-    pub message: String,
-    pub code: Option<i32>,
-}
+// ==================== 拦截原因 ====================
 
-// 前端调用的参数
-#[derive(Debug, Deserialize)]
+// 正常结束的 finishReason，其余均视为被截断或拦截
+const NORMAL_FINISH_REASONS: [&str; 2] = ["STOP", "FINISH_REASON_UNSPECIFIED"];
+
+/// 拦截发生的阶段
+#[derive(Debug, Clone, Copy, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct GeminiRequestParams {
-    pub base_url: String,
-    pub api_key: String,
-    pub model: String,
-    pub prompt: String,
-    pub input_images: Option<Vec<String>>, // base64 图片数据
-    pub aspect_ratio: Option<String>,
-    pub image_size: Option<String>,
+pub enum BlockStage {
+    Prompt,    // 提示词被拦截（promptFeedback.blockReason）
+    Candidate, // 生成内容被拦截或截断（candidate.finishReason）
 }
 
-// 前端返回的结果
-#[derive(Debug, Serialize)]
+/// 生成被拦截的结构化原因
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct GeminiResult {
-    pub success: bool,
-    pub image_data: Option<String>,
-    pub text: Option<String>,
-    pub error: Option<String>,
+pub struct BlockInfo {
+    pub stage: BlockStage,
+    pub reason: String,           // 原始原因，如 SAFETY、RECITATION、MAX_TOKENS、IMAGE_SAFETY、BLOCKLIST
+    pub category: Option<String>, // 触发拦截的安全类别，如 HARM_CATEGORY_HARASSMENT
+    pub message: Option<String>,  // API 附带的说明
 }
 
-// Tauri 命令：发送 Gemini API 请求
-#[tauri::command]
-pub async fn gemini_generate_content(params: GeminiRequestParams) -> GeminiResult {
-    println!("[Rust] gemini_generate_content called");
-    println!("[Rust] base_url: {}", params.base_url);
-    println!("[Rust] model: {}", params.model);
-    println!("[Rust] input_images count: {}", params.input_images.as_ref().map(|v| v.len()).unwrap_or(0));
-
-    // 构建请求体
-    let mut parts: Vec<Part> = vec![Part::Text { text: params.prompt }];
-
-    // 添加输入图片
-    if let Some(images) = params.input_images {
-        println!("[Rust] Adding {} images to request", images.len());
-        for image_data in images {
-            parts.push(Part::InlineData {
-                inline_data: InlineData {
-                    mime_type: "image/png".to_string(),
-                    data: image_data,
-                },
-            });
-        }
+impl BlockInfo {
+    /// 转换为统一错误：安全类拦截归为 ContentFiltered
+    pub fn to_api_error(&self) -> ApiError {
+        let code = match self.reason.as_str() {
+            "MAX_TOKENS" => ErrorCode::OutputTruncated,
+            "MALFORMED_FUNCTION_CALL" => ErrorCode::MalformedResponse,
+            "SAFETY" | "IMAGE_SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" => {
+                ErrorCode::ContentFiltered
+            }
+            _ => ErrorCode::ProviderError,
+        };
+        ApiError::new(code, self.describe()).with_provider_type(Some(self.reason.clone()))
     }
 
-    let request_body = GeminiRequest {
-        contents: vec![Content { parts }],
-        generation_config: Some(GenerationConfig {
-            response_modalities: Some(vec!["IMAGE".to_string()]),
-            image_config: Some(ImageConfig {
-                aspect_ratio: params.aspect_ratio,
-                image_size: params.image_size,
-            }),
-        }),
-    };
+    /// 面向用户的错误描述
+    pub fn describe(&self) -> String {
+        let reason = match self.reason.as_str() {
+            "SAFETY" => "触发安全策略",
+            "IMAGE_SAFETY" => "生成的图片触发安全策略",
+            "RECITATION" => "内容与受版权保护的资料过于相似",
+            "MAX_TOKENS" => "输出达到最大 token 数，内容被截断",
+            "BLOCKLIST" => "包含被屏蔽的词语",
+            "PROHIBITED_CONTENT" => "包含禁止生成的内容",
+            "SPII" => "包含敏感个人信息",
+            "LANGUAGE" => "不支持的语言",
+            "MALFORMED_FUNCTION_CALL" => "模型生成的函数调用格式无效",
+            _ => "生成被中止",
+        };
+        let stage = match self.stage {
+            BlockStage::Prompt => "提示词被拦截",
+            BlockStage::Candidate => "生成结果被拦截",
+        };
 
-    // 构建 URL
-    let url = format!(
-        "{}/models/{}:generateContent?key={}",
-        params.base_url.trim_end_matches('/'),
-        params.model,
-        params.api_key
-    );
-    println!("[Rust] Request URL (without key): {}/models/{}:generateContent", params.base_url.trim_end_matches('/'), params.model);
-
-    // 创建 HTTP 客户端，设置较长的超时时间（10分钟）
-    println!("[Rust] Creating HTTP client with 600s timeout...");
-    let client = match Client::builder()
-        .timeout(Duration::from_secs(600))
-        .build()
-    {
-        Ok(c) => c,
-        Err(e) => {
-            println!("[Rust] Failed to create HTTP client: {}", e);
-            return GeminiResult {
-                success: false,
-                image_data: None,
-                text: None,
-                error: Some(format!("创建 HTTP 客户端失败: {}", e)),
-            }
+        let mut text = format!("{}：{}（{}", stage, reason, self.reason);
+        if let Some(category) = &self.category {
+            text.push_str(&format!("，{}", category));
         }
-    };
-
-    // 发送请求
-    println!("[Rust] Sending POST request...");
-    let start_time = std::time::Instant::now();
-
-    let response = match client
-        .post(&url)
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await
-    {
-        Ok(r) => {
-            println!("[Rust] Response received in {:?}", start_time.elapsed());
-            r
-        },
-        Err(e) => {
-            println!("[Rust] Request failed after {:?}: {}", start_time.elapsed(), e);
-            let error_msg = if e.is_timeout() {
-                "请求超时，请稍后重试".to_string()
-            } else if e.is_connect() {
-                "无法连接到服务器，请检查网络".to_string()
-            } else {
-                format!("请求失败: {}", e)
-            };
-            return GeminiResult {
-                success: false,
-                image_data: None,
-                text: None,
-                error: Some(error_msg),
-            };
+        text.push('）');
+        if let Some(message) = &self.message {
+            text.push_str(&format!("：{}", message));
         }
-    };
-
-    // 检查 HTTP 状态码
-    let status = response.status();
-    println!("[Rust] HTTP status: {}", status);
-    if !status.is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        println!("[Rust] Error response: {}", error_text);
-        return GeminiResult {
-            success: false,
-            image_data: None,
-            text: None,
-            error: Some(format!("API 返回错误 ({}): {}", status, error_text)),
-        };
+        text
     }
+}
 
-    // 先获取响应文本，再解析 JSON
-    println!("[Rust] Getting response text...");
-    let response_text = match response.text().await {
-        Ok(t) => t,
-        Err(e) => {
-            println!("[Rust] Failed to get response text: {}", e);
-            return GeminiResult {
-                success: false,
-                image_data: None,
-                text: None,
-                error: Some(format!("获取响应失败: {}", e)),
-            };
-        }
-    };
-
-    println!("[Rust] Response text length: {} bytes", response_text.len());
-    // 打印前 500 个字符用于调试
-    let preview = if response_text.len() > 500 {
-        format!("{}...(truncated)", &response_text[..500])
-    } else {
-        response_text.clone()
-    };
-    println!("[Rust] Response preview: {}", preview);
-
-    // 解析 JSON
-    println!("[Rust] Parsing JSON...");
-    let gemini_response: GeminiResponse = match serde_json::from_str(&response_text) {
-        Ok(r) => r,
-        Err(e) => {
-            println!("[Rust] Failed to parse JSON: {}", e);
-            println!("[Rust] JSON error location: line {}, column {}", e.line(), e.column());
-            return GeminiResult {
-                success: false,
-                image_data: None,
-                text: None,
-                error: Some(format!("解析响应失败: {}", e)),
-            };
-        }
+// 找出触发拦截的安全类别：优先取 blocked 标记，其次取概率最高的一项
+fn offending_category(ratings: Option<&Vec<SafetyRating>>) -> Option<String> {
+    let ratings = ratings?;
+    let rank = |probability: Option<&str>| match probability {
+        Some("HIGH") => 3,
+        Some("MEDIUM") => 2,
+        Some("LOW") => 1,
+        _ => 0,
     };
+    ratings
+        .iter()
+        .find(|rating| rating.blocked == Some(true))
+        .or_else(|| {
+            ratings
+                .iter()
+                .filter(|rating| rank(rating.probability.as_deref()) >= 2)
+                .max_by_key(|rating| rank(rating.probability.as_deref()))
+        })
+        .map(|rating| rating.category.clone())
+}
 
-    // 检查 API 错误
-    if let Some(err) = gemini_response.error {
-        println!("[Rust] API error: {}", err.message);
-        return GeminiResult {
-            success: false,
-            image_data: None,
-            text: None,
-            error: Some(err.message),
-        };
-    }
-
-    // 提取结果
-    let mut image_data: Option<String> = None;
-    let mut text: Option<String> = None;
-
-    if let Some(candidates) = gemini_response.candidates {
-        if let Some(candidate) = candidates.first() {
-            if let Some(content) = &candidate.content {
-                if let Some(parts) = &content.parts {
-                    for part in parts {
-                        if let Some(inline) = &part.inline_data {
-                            image_data = Some(inline.data.clone());
-                        }
-                        if let Some(t) = &part.text {
-                            text = Some(t.clone());
-                        }
-                    }
-                }
-            }
+impl Candidate {
+    /// 非正常结束时返回结构化原因
+    pub fn block_info(&self) -> Option<BlockInfo> {
+        let reason = self.finish_reason.as_deref()?;
+        if NORMAL_FINISH_REASONS.contains(&reason) {
+            return None;
         }
+        Some(BlockInfo {
+            stage: BlockStage::Candidate,
+            reason: reason.to_string(),
+            category: offending_category(self.safety_ratings.as_ref()),
+            message: self.finish_message.clone(),
+        })
     }
+}
 
-    println!("[Rust] Result: has_image={}, has_text={}", image_data.is_some(), text.is_some());
-
-    if image_data.is_none() && text.is_none() {
-        return GeminiResult {
-            success: false,
-            image_data: None,
-            text: None,
-            error: Some("API 未返回有效内容".to_string()),
-        };
+impl GeminiResponse {
+    /// 提示词被拦截的原因
+    pub fn prompt_block(&self) -> Option<BlockInfo> {
+        let feedback = self.prompt_feedback.as_ref()?;
+        let reason = feedback.block_reason.as_deref()?;
+        Some(BlockInfo {
+            stage: BlockStage::Prompt,
+            reason: reason.to_string(),
+            category: offending_category(feedback.safety_ratings.as_ref()),
+            message: feedback.block_reason_message.clone(),
+        })
     }
+}
 
-    GeminiResult {
-        success: true,
-        image_data,
-        text,
-        error: None,
-    }
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeminiError {
+    pub message: String,
+    pub code: Option<i32>,
+    pub status: Option<String>, // 如 INVALID_ARGUMENT、RESOURCE_EXHAUSTED
 }
 
-// 文件数据结构（用于LLM内容生成）
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct FileData {
-    pub data: String,      // base64 编码的文件数据
-    pub mime_type: String, // 文件MIME类型
-    pub file_name: Option<String>, // 文件名（可选）
+impl GeminiError {
+    pub fn to_api_error(&self) -> ApiError {
+        ApiError::new(ErrorCode::ProviderError, self.message.clone()).with_provider_type(self.status.clone())
+    }
 }
 
-// LLM 文本生成请求参数
+// 前端调用的参数
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct LLMRequestParams {
+pub struct GeminiRequestParams {
     pub base_url: String,
-    pub api_key: String,
+    #[serde(default)]
+    pub api_key: String, // 未使用凭据库时由前端直接传入
+    pub provider_id: Option<String>, // 凭据库中的供应商 ID，优先于 api_key
     pub model: String,
     pub prompt: String,
-    pub system_prompt: Option<String>,
-    pub output_format: Option<String>, // "text" or "json"
-    pub temperature: Option<f64>,
-    pub max_tokens: Option<i32>,
-    pub files: Option<Vec<FileData>>, // 文件数据（PDF、图片等）
-    pub response_json_schema: Option<serde_json::Value>, // 结构化输出的 JSON Schema
+    pub input_images: Option<Vec<String>>, // base64 图片数据
+    pub aspect_ratio: Option<String>,
+    pub image_size: Option<String>,
+    pub candidate_count: Option<u32>, // 一次生成的候选数量（多张变体）
+    pub request_id: Option<String>, // 用于 cancel_request 取消请求
+    pub retry: Option<RetryPolicy>, // 重试策略（按供应商配置，默认重试 3 次）
+    pub canvas_id: Option<String>,  // 用量记账归属的画布
 }
 
-// LLM 文本生成结果
-#[derive(Debug, Serialize)]
+// 生成的单张图片
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct LLMResult {
-    pub success: bool,
-    pub content: Option<String>,
-    pub error: Option<String>,
+pub struct GeneratedImage {
+    pub data: String, // base64 图片数据
+    pub mime_type: String,
 }
 
-// LLM 专用请求体
+// 单个候选的生成结果
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct LLMRequest {
-    pub contents: Vec<Content>,
+pub struct CandidateResult {
+    pub images: Vec<GeneratedImage>,
+    pub text: Option<String>, // 该候选所有正文部分拼接
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<String>, // 该候选的思考摘要（thought 部分），不计入 text
+    pub finish_reason: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub generation_config: Option<LLMGenerationConfig>,
+    pub block: Option<BlockInfo>, // 该候选被拦截或截断的原因
 }
 
+// 前端返回的结果
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct LLMGenerationConfig {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub response_mime_type: Option<String>,
+pub struct GeminiResult {
+    pub success: bool,
+    pub image_data: Option<String>, // 第一张图片（兼容旧版前端）
+    pub text: Option<String>,       // 第一个候选的文本
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub response_schema: Option<serde_json::Value>,
+    pub thinking: Option<String>, // 第一个候选的思考摘要
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<GeneratedImage>, // 所有候选生成的全部图片
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<CandidateResult>,
+    pub error: Option<ApiError>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub temperature: Option<f64>,
+    pub block: Option<BlockInfo>, // 被安全策略等拦截时的结构化原因
+    pub attempts: u32, // 实际发送请求的次数（含重试）
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub max_output_tokens: Option<i32>,
+    pub usage: Option<TokenUsage>, // Token 与图片用量
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>, // 输入图片被缩放或重新压缩等提示
 }
 
-// Tauri 命令：LLM 文本生成
-#[tauri::command]
-pub async fn gemini_generate_text(params: LLMRequestParams) -> LLMResult {
-    println!("[Rust] gemini_generate_text called");
-    println!("[Rust] base_url: {}", params.base_url);
-    println!("[Rust] model: {}", params.model);
-    println!("[Rust] files count: {}", params.files.as_ref().map(|v| v.len()).unwrap_or(0));
-
-    // 构建请求内容
-    let prompt_text = if let Some(system_prompt) = &params.system_prompt {
-        if !system_prompt.is_empty() {
-            format!("系统指令：{}\n\n用户请求：{}", system_prompt, params.prompt)
-        } else {
-            params.prompt.clone()
+impl GeminiResult {
+    // 失败结果，附带已发送的请求次数
+    pub(crate) fn failure(error: ApiError, attempts: u32) -> Self {
+        GeminiResult {
+            success: false,
+            image_data: None,
+            text: None,
+            thinking: None,
+            images: Vec::new(),
+            candidates: Vec::new(),
+            error: Some(error),
+            block: None,
+            attempts,
+            usage: None,
+            warnings: Vec::new(),
         }
-    } else {
-        params.prompt.clone()
+    }
+}
+
+// 提取候选的图片与文本；thought 部分（思考摘要与草稿图）不计入正文和生成结果
+fn candidate_result(candidate: Candidate) -> CandidateResult {
+    let block = candidate.block_info();
+    let finish_reason = candidate.finish_reason;
+    let mut images: Vec<GeneratedImage> = Vec::new();
+    let mut text_parts: Vec<String> = Vec::new();
+    let mut thought_parts: Vec<String> = Vec::new();
+    for part in candidate.content.and_then(|content| content.parts).unwrap_or_default() {
+        let thought = part.thought == Some(true);
+        if let Some(inline) = part.inline_data {
+            if thought {
+                debug!(mime_type = %inline.mime_type, "Skip thought image");
+            } else {
+                images.push(GeneratedImage {
+                    data: inline.data,
+                    mime_type: inline.mime_type,
+                });
+            }
+        }
+        if let Some(t) = part.text {
+            if thought {
+                thought_parts.push(t);
+            } else {
+                text_parts.push(t);
+            }
+        }
+    }
+    CandidateResult {
+        images,
+        text: if text_parts.is_empty() { None } else { Some(text_parts.join("")) },
+        thinking: if thought_parts.is_empty() { None } else { Some(thought_parts.join("")) },
+        finish_reason,
+        block,
+    }
+}
+
+// Tauri 命令：发送 Gemini API 请求（可通过 request_id 取消）
+#[tauri::command]
+#[tracing::instrument(name = "request", skip_all, fields(request_id = %request_key(params.request_id.as_deref())))]
+pub async fn gemini_generate_content(app_handle: AppHandle, mut params: GeminiRequestParams) -> GeminiResult {
+    params.api_key = match resolve_api_key(&app_handle, params.provider_id.as_deref(), &params.api_key).await {
+        Ok(api_key) => api_key,
+        Err(e) => return GeminiResult::failure(e, 0),
     };
 
-    // 构建 parts：先添加文本，再添加文件
-    let mut parts: Vec<Part> = vec![Part::Text { text: prompt_text }];
-
-    // 添加文件（PDF、图片等）
-    if let Some(files) = &params.files {
-        println!("[Rust] Adding {} files to request", files.len());
-        for file in files {
-            println!("[Rust] Adding file: mime_type={}, name={:?}", file.mime_type, file.file_name);
-            parts.push(Part::InlineData {
-                inline_data: InlineData {
-                    mime_type: file.mime_type.clone(),
-                    data: file.data.clone(),
-                },
-            });
+    let registry = RequestRegistry::from_app(&app_handle);
+    let request_id = params.request_id.clone();
+
+    let http = HttpClientManager::from_app(&app_handle);
+    let canvas_id = params.canvas_id.clone();
+    let model = params.model.clone();
+
+    match registry.run(request_id.as_deref(), generate_content(http, params)).await {
+        Ok(result) => {
+            if let Some(usage) = &result.usage {
+                record_usage(&app_handle, canvas_id.as_deref(), "Gemini", &model, usage);
+            }
+            result
+        }
+        Err(_) => {
+            info!("gemini_generate_content cancelled");
+            GeminiResult::failure(ApiError::cancelled(), 0)
         }
     }
+}
 
-    let request_body = LLMRequest {
-        contents: vec![Content { parts }],
-        generation_config: Some(LLMGenerationConfig {
-            response_mime_type: if params.response_json_schema.is_some() || params.output_format.as_deref() == Some("json") {
-                Some("application/json".to_string())
-            } else {
-                None
+async fn generate_content(http: HttpClientManager, mut params: GeminiRequestParams) -> GeminiResult {
+    info!(
+        model = %params.model,
+        input_images = params.input_images.as_ref().map(|v| v.len()).unwrap_or(0),
+        "gemini_generate_content called"
+    );
+
+    // 按实际内容识别输入图片格式，并按 Gemini 的限制缩放、压缩
+    let prepared = match prepare_input_images(params.input_images.take().unwrap_or_default(), ImageTarget::Gemini).await {
+        Ok(prepared) => prepared,
+        Err(e) => return GeminiResult::failure(e, 0),
+    };
+
+    let mut result = send_content(http, params, prepared.images).await;
+    result.warnings = prepared.warnings;
+    result
+}
+
+async fn send_content(http: HttpClientManager, params: GeminiRequestParams, input_images: Vec<InputImage>) -> GeminiResult {
+    // 构建请求体
+    let mut parts: Vec<Part> = vec![Part::Text { text: params.prompt }];
+
+    // 添加输入图片
+    for image in input_images {
+        parts.push(Part::InlineData {
+            inline_data: InlineData {
+                mime_type: image.mime_type().to_string(),
+                data: image.to_base64(),
             },
-            response_schema: params.response_json_schema,
-            temperature: params.temperature,
-            max_output_tokens: params.max_tokens,
+        });
+    }
+
+    let request_body = GeminiRequest {
+        contents: vec![Content { role: None, parts }],
+        generation_config: Some(GenerationConfig {
+            response_modalities: Some(vec!["IMAGE".to_string()]),
+            candidate_count: params.candidate_count,
+            image_config: Some(ImageConfig {
+                aspect_ratio: params.aspect_ratio,
+                image_size: params.image_size,
+            }),
         }),
     };
 
-    // 构建 URL
+    // 构建 URL（Key 通过 x-goog-api-key 头发送，不出现在 URL 中）
     let url = format!(
-        "{}/models/{}:generateContent?key={}",
+        "{}/models/{}:generateContent",
         params.base_url.trim_end_matches('/'),
-        params.model,
-        params.api_key
+        params.model
     );
-    println!("[Rust] Request URL (without key): {}/models/{}:generateContent", params.base_url.trim_end_matches('/'), params.model);
-
-    // 创建 HTTP 客户端
-    let client = match Client::builder()
-        .timeout(Duration::from_secs(300))
-        .build()
-    {
-        Ok(c) => c,
-        Err(e) => {
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(format!("创建 HTTP 客户端失败: {}", e)),
-            }
-        }
-    };
+    debug!("Request URL: {}", url);
+
+    // 使用共享 HTTP 客户端，设置较长的超时时间（默认 10 分钟）
+    let client = http.client();
+    let timeout = http.timeout(TimeoutKey::GeminiImage);
 
     // 发送请求
-    println!("[Rust] Sending LLM request...");
     let start_time = std::time::Instant::now();
 
-    let response = match client
-        .post(&url)
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await
-    {
+    // 图片生成按次计费，仅在请求确定未被处理时重试
+    let policy = params.retry.clone().unwrap_or_default();
+    let outcome = send_with_retry(&policy, Idempotency::Billed, "Gemini", || {
+        client
+            .post(&url)
+            .timeout(timeout)
+            .header("Content-Type", "application/json")
+            .header("x-goog-api-key", &params.api_key)
+            .json(&request_body)
+    })
+    .await;
+    let attempts = outcome.attempts;
+
+    let response = match outcome.result {
         Ok(r) => {
-            println!("[Rust] LLM response received in {:?}", start_time.elapsed());
+            info!(status = %r.status(), "Response received in {:?}", start_time.elapsed());
             r
         },
         Err(e) => {
-            println!("[Rust] LLM request failed: {}", e);
-            let error_msg = if e.is_timeout() {
-                "请求超时，请稍后重试".to_string()
-            } else if e.is_connect() {
-                "无法连接到服务器，请检查网络".to_string()
-            } else {
-                format!("请求失败: {}", e)
-            };
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(error_msg),
-            };
+            warn!("Request failed after {:?}: {}", start_time.elapsed(), e);
+            return GeminiResult::failure(ApiError::from_request_error(&e), attempts);
         }
     };
 
@@ -600,80 +674,127 @@ pub async fn gemini_generate_text(params: LLMRequestParams) -> LLMResult {
     let status = response.status();
     if !status.is_success() {
         let error_text = response.text().await.unwrap_or_default();
-        println!("[Rust] LLM error response: {}", error_text);
-        return LLMResult {
-            success: false,
-            content: None,
-            error: Some(format!("API 返回错误 ({}): {}", status, error_text)),
-        };
+        warn!("Error response: {}", error_text);
+        return GeminiResult::failure(ApiError::from_response(status, &error_text), attempts);
     }
 
-    // 解析响应
+    // 先获取响应文本，再解析 JSON
     let response_text = match response.text().await {
         Ok(t) => t,
         Err(e) => {
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(format!("获取响应失败: {}", e)),
-            };
+            warn!("Failed to get response text: {}", e);
+            let error = ApiError::new(ErrorCode::Network, format!("获取响应失败: {}", e));
+            return GeminiResult::failure(error, attempts);
         }
     };
 
+    // 响应中的图片 base64 会在写入日志时被省略
+    debug!(bytes = response_text.len(), "Response body: {}", log_preview(&response_text));
+
+    // 解析 JSON
     let gemini_response: GeminiResponse = match serde_json::from_str(&response_text) {
         Ok(r) => r,
         Err(e) => {
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(format!("解析响应失败: {}", e)),
-            };
+            warn!("Failed to parse JSON at line {}, column {}: {}", e.line(), e.column(), e);
+            return GeminiResult::failure(ApiError::malformed(format!("解析响应失败: {}", e)), attempts);
         }
     };
 
     // 检查 API 错误
     if let Some(err) = gemini_response.error {
-        return LLMResult {
-            success: false,
-            content: None,
-            error: Some(err.message),
-        };
+        warn!("API error: {}", err.message);
+        return GeminiResult::failure(err.to_api_error(), attempts);
     }
 
-    // 提取文本内容
-    let mut content: Option<String> = None;
-
-    if let Some(candidates) = gemini_response.candidates {
-        if let Some(candidate) = candidates.first() {
-            if let Some(candidate_content) = &candidate.content {
-                if let Some(parts) = &candidate_content.parts {
-                    let mut text_parts: Vec<String> = Vec::new();
-                    for part in parts {
-                        if let Some(t) = &part.text {
-                            text_parts.push(t.clone());
-                        }
-                    }
-                    if !text_parts.is_empty() {
-                        content = Some(text_parts.join(""));
-                    }
-                }
-            }
-        }
-    }
+    // 用量：token 数 + 生成的图片张数（失败时同样记账）
+    let mut usage = gemini_response
+        .usage_metadata
+        .as_ref()
+        .map(|usage| usage.to_usage())
+        .unwrap_or_default();
+
+    // 提示词被拦截时没有任何候选
+    let prompt_block = gemini_response.prompt_block();
+
+    // 提取每个候选的全部图片与文本
+    let candidates: Vec<CandidateResult> = gemini_response
+        .candidates
+        .unwrap_or_default()
+        .into_iter()
+        .map(candidate_result)
+        .collect();
+
+    let images: Vec<GeneratedImage> = candidates
+        .iter()
+        .flat_map(|candidate| candidate.images.iter().cloned())
+        .collect();
+    let image_data = images.first().map(|image| image.data.clone());
+    let text = candidates.iter().find_map(|candidate| candidate.text.clone());
+    let thinking = candidates.iter().find_map(|candidate| candidate.thinking.clone());
+
+    info!(
+        candidates = candidates.len(),
+        images = images.len(),
+        has_text = text.is_some(),
+        "Gemini result"
+    );
+    usage.images = images.len() as u64;
+    let usage = if usage.is_empty() { None } else { Some(usage) };
 
-    if content.is_none() {
-        return LLMResult {
-            success: false,
-            content: None,
-            error: Some("API 未返回有效内容".to_string()),
+    if image_data.is_none() && text.is_none() {
+        // 优先报告提示词拦截，其次报告第一个候选的结束原因
+        let block = prompt_block.or_else(|| candidates.iter().find_map(|candidate| candidate.block.clone()));
+        let error = match &block {
+            Some(block) => {
+                warn!("Generation blocked: {:?}", block);
+                block.to_api_error()
+            }
+            None => ApiError::malformed("API 未返回有效内容"),
+        };
+        return GeminiResult {
+            candidates,
+            block,
+            usage,
+            ..GeminiResult::failure(error, attempts)
         };
     }
 
-    println!("[Rust] LLM result: content length = {}", content.as_ref().map(|c| c.len()).unwrap_or(0));
-
-    LLMResult {
+    GeminiResult {
         success: true,
-        content,
+        image_data,
+        text,
+        thinking,
+        images,
+        candidates,
         error: None,
+        block: None,
+        attempts,
+        usage,
+        warnings: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thought_parts_are_not_returned_as_results() {
+        let candidate: Candidate = serde_json::from_value(serde_json::json!({
+            "content": { "parts": [
+                { "text": "先画草图", "thought": true },
+                { "inlineData": { "mimeType": "image/png", "data": "DRAFT" }, "thought": true },
+                { "text": "完成" },
+                { "inlineData": { "mimeType": "image/png", "data": "FINAL" } }
+            ]},
+            "finishReason": "STOP"
+        }))
+        .unwrap();
+
+        let result = candidate_result(candidate);
+        assert_eq!(result.images.len(), 1);
+        assert_eq!(result.images[0].data, "FINAL");
+        assert_eq!(result.text.as_deref(), Some("完成"));
+        assert_eq!(result.thinking.as_deref(), Some("先画草图"));
     }
 }
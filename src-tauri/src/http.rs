@@ -0,0 +1,468 @@
+use reqwest::{Certificate, Client, Proxy, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+
+use crate::cassette::send_with_cassette;
+use crate::error::ApiError;
+
+// 按 Retry-After 等待的上限（策略的 max_delay_ms 更大时以策略为准），超出时按上限等待后重试
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+// ==================== 共享 HTTP 客户端 ====================
+
+/// HTTP 客户端配置（来自前端设置）
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpClientConfig {
+    pub proxy_url: Option<String>,          // 代理地址（http/https/socks5）
+    pub root_certificates: Vec<String>,     // 额外信任的根证书（PEM 文本）
+    pub accept_invalid_certs: bool,         // 跳过证书校验（仅用于自签名的内网服务）
+    pub user_agent: Option<String>,
+    pub connect_timeout_secs: Option<u64>,
+    // 按服务覆盖请求超时（秒），键见 TimeoutKey
+    pub timeouts: HashMap<String, u64>,
+}
+
+/// 各服务的超时配置键及默认值
+#[derive(Debug, Clone, Copy)]
+pub enum TimeoutKey {
+    Chat,        // 非流式对话（所有协议）
+    ChatStream,  // 流式对话
+    GeminiImage, // Gemini 图片生成
+    LemonStream, // Lemon 流式图片生成
+    OpenAIImage, // OpenAI Images API 图片生成与编辑
+    VideoCreate,
+    VideoStatus,
+    VideoContent,
+    Ocr,         // OCR / 背景修复
+    HealthCheck, // 服务连接测试
+    ModelList,   // 模型列表查询
+}
+
+impl TimeoutKey {
+    fn key(self) -> &'static str {
+        match self {
+            TimeoutKey::Chat => "chat",
+            TimeoutKey::ChatStream => "chatStream",
+            TimeoutKey::GeminiImage => "geminiImage",
+            TimeoutKey::LemonStream => "lemonStream",
+            TimeoutKey::OpenAIImage => "openaiImage",
+            TimeoutKey::VideoCreate => "videoCreate",
+            TimeoutKey::VideoStatus => "videoStatus",
+            TimeoutKey::VideoContent => "videoContent",
+            TimeoutKey::Ocr => "ocr",
+            TimeoutKey::HealthCheck => "healthCheck",
+            TimeoutKey::ModelList => "modelList",
+        }
+    }
+
+    fn default_secs(self) -> u64 {
+        match self {
+            TimeoutKey::Chat => 300,
+            TimeoutKey::ChatStream => 600,
+            TimeoutKey::GeminiImage => 600,
+            TimeoutKey::LemonStream => 600,
+            TimeoutKey::OpenAIImage => 600,
+            TimeoutKey::VideoCreate => 60,
+            TimeoutKey::VideoStatus => 30,
+            TimeoutKey::VideoContent => 300,
+            TimeoutKey::Ocr => 300,
+            TimeoutKey::HealthCheck => 10,
+            TimeoutKey::ModelList => 30,
+        }
+    }
+}
+
+struct ClientState {
+    config: HttpClientConfig,
+    client: Client,
+}
+
+/// 共享 HTTP 客户端（Tauri 托管状态）
+///
+/// 所有命令复用同一个连接池；超时按请求设置，因此不同服务可以共用一个客户端。
+/// 配置变更时重新构建客户端，已发出的请求不受影响。
+#[derive(Clone)]
+pub struct HttpClientManager {
+    state: Arc<RwLock<ClientState>>,
+}
+
+impl Default for HttpClientManager {
+    fn default() -> Self {
+        let config = HttpClientConfig::default();
+        let client = build_client(&config).unwrap_or_else(|e| {
+            warn!("Failed to build default HTTP client: {}", e);
+            Client::new()
+        });
+        Self {
+            state: Arc::new(RwLock::new(ClientState { config, client })),
+        }
+    }
+}
+
+impl HttpClientManager {
+    /// 从 AppHandle 中取出客户端管理器
+    pub fn from_app(app_handle: &AppHandle) -> Self {
+        app_handle.state::<HttpClientManager>().inner().clone()
+    }
+
+    /// 当前客户端（克隆开销很小，共享连接池）
+    pub fn client(&self) -> Client {
+        self.state.read().unwrap().client.clone()
+    }
+
+    /// 指定服务的请求超时
+    pub fn timeout(&self, key: TimeoutKey) -> Duration {
+        let secs = self
+            .state
+            .read()
+            .unwrap()
+            .config
+            .timeouts
+            .get(key.key())
+            .copied()
+            .unwrap_or_else(|| key.default_secs());
+        Duration::from_secs(secs)
+    }
+
+    /// 应用新配置，构建失败时保留原客户端
+    pub fn configure(&self, config: HttpClientConfig) -> Result<(), String> {
+        let client = build_client(&config)?;
+        *self.state.write().unwrap() = ClientState { config, client };
+        Ok(())
+    }
+}
+
+fn build_client(config: &HttpClientConfig) -> Result<Client, String> {
+    let mut builder = Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs.unwrap_or(30)));
+
+    if let Some(proxy_url) = config.proxy_url.as_deref().filter(|url| !url.is_empty()) {
+        let proxy = Proxy::all(proxy_url).map_err(|e| format!("代理地址无效: {}", e))?;
+        builder = builder.proxy(proxy);
+    }
+
+    for pem in &config.root_certificates {
+        let cert = Certificate::from_pem(pem.as_bytes())
+            .map_err(|e| format!("根证书解析失败: {}", e))?;
+        builder = builder.add_root_certificate(cert);
+    }
+
+    if config.accept_invalid_certs {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    if let Some(user_agent) = config.user_agent.as_deref().filter(|ua| !ua.is_empty()) {
+        builder = builder.user_agent(user_agent);
+    }
+
+    builder
+        .build()
+        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))
+}
+
+/// 更新共享 HTTP 客户端配置（设置变更或启动时由前端调用）
+#[tauri::command]
+pub fn configure_http_client(app_handle: AppHandle, config: HttpClientConfig) -> Result<(), ApiError> {
+    info!(
+        proxy = config.proxy_url.is_some(),
+        root_certs = config.root_certificates.len(),
+        timeouts = ?config.timeouts,
+        "configure_http_client"
+    );
+    HttpClientManager::from_app(&app_handle)
+        .configure(config)
+        .map_err(ApiError::invalid_request)
+}
+
+// ==================== 重试策略 ====================
+
+/// 请求重试策略（可由前端按供应商配置，未传时使用默认值）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,  // 最大尝试次数（含首次请求），1 表示不重试
+    pub base_delay_ms: u64, // 首次重试前的等待时间，之后按指数增长
+    pub max_delay_ms: u64,  // 单次等待上限
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 8000,
+        }
+    }
+}
+
+/// 请求是否可以安全地重复发送
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idempotency {
+    // 查询类请求，重复发送不会产生额外副作用
+    Idempotent,
+    // 按次计费的生成请求：超时后服务端可能仍在生成并计费，只在确定未处理或被拒绝时（连接失败、429/502/503）重试
+    Billed,
+    // 创建任务等请求，只在服务端确定未处理时（连接失败、429 限流）重试
+    NonIdempotent,
+}
+
+impl RetryPolicy {
+    // 第 attempt 次失败后的退避时间：指数增长 + 随机抖动
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay_ms
+            .saturating_mul(1u64 << attempt.saturating_sub(1).min(16));
+        let capped = exp.min(self.max_delay_ms);
+        // 在 [capped/2, capped] 区间内抖动，避免多个节点同时重试
+        let jitter = if capped > 1 { random_u64() % (capped / 2 + 1) } else { 0 };
+        Duration::from_millis(capped - jitter)
+    }
+}
+
+// ==================== 带重试的发送 ====================
+
+/// 发送结果及实际尝试次数
+pub struct RetryOutcome {
+    pub result: Result<Response, reqwest::Error>,
+    pub attempts: u32,
+}
+
+/// 按重试策略发送请求
+///
+/// `make_request` 每次尝试都会被调用以构建新的请求（请求体不能跨次复用）。
+/// 重试耗尽或遇到不可重试的错误时，原样返回最后一次的响应或错误，由调用方处理。
+///
+/// 可重试的情况按 `idempotency` 收窄：
+/// - `Idempotent`：连接失败、超时、连接中断，以及 408/429/500/502/503/504
+/// - `Billed`：只有连接未建立，以及 429/502/503。500、504、超时和连接中断时请求可能已被处理并计费，
+///   不会重试，即使还没用完 `max_attempts`
+/// - `NonIdempotent`：只有连接未建立和 429
+///
+/// 响应带 Retry-After 时按其等待，超过上限时按上限等待。
+pub async fn send_with_retry<F>(
+    policy: &RetryPolicy,
+    idempotency: Idempotency,
+    label: &str,
+    make_request: F,
+) -> RetryOutcome
+where
+    F: Fn() -> RequestBuilder,
+{
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+        let result = send_with_cassette(make_request()).await;
+
+        let delay = if attempt >= max_attempts {
+            None
+        } else {
+            match &result {
+                Ok(response) => retry_delay_for_status(policy, idempotency, response, attempt),
+                Err(e) => retry_delay_for_error(policy, idempotency, e, attempt),
+            }
+        };
+
+        let Some(delay) = delay else {
+            return RetryOutcome { result, attempts: attempt };
+        };
+
+        match &result {
+            Ok(response) => warn!(
+                "{} attempt {}/{} got {}, retrying in {:?}",
+                label,
+                attempt,
+                max_attempts,
+                response.status(),
+                delay
+            ),
+            Err(e) => warn!(
+                "{} attempt {}/{} failed: {}, retrying in {:?}",
+                label, attempt, max_attempts, e, delay
+            ),
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+// 根据响应状态码决定是否重试及等待时间
+fn retry_delay_for_status(
+    policy: &RetryPolicy,
+    idempotency: Idempotency,
+    response: &Response,
+    attempt: u32,
+) -> Option<Duration> {
+    let status = response.status();
+    let retryable = match idempotency {
+        Idempotency::Idempotent => matches!(
+            status,
+            StatusCode::REQUEST_TIMEOUT
+                | StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::INTERNAL_SERVER_ERROR
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        ),
+        // 网关错误与过载通常表示请求未交给模型处理
+        Idempotency::Billed => matches!(
+            status,
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE
+        ),
+        // 429 表示请求被限流拒绝，服务端未创建任务
+        Idempotency::NonIdempotent => status == StatusCode::TOO_MANY_REQUESTS,
+    };
+    if !retryable {
+        return None;
+    }
+
+    match parse_retry_after(response) {
+        Some(wait) => {
+            let limit = MAX_RETRY_AFTER.max(Duration::from_millis(policy.max_delay_ms));
+            if wait > limit {
+                warn!("Retry-After {:?} exceeds limit, waiting {:?} instead", wait, limit);
+            }
+            Some(wait.min(limit))
+        }
+        None => Some(policy.backoff(attempt)),
+    }
+}
+
+// 根据网络错误决定是否重试
+fn retry_delay_for_error(
+    policy: &RetryPolicy,
+    idempotency: Idempotency,
+    error: &reqwest::Error,
+    attempt: u32,
+) -> Option<Duration> {
+    let retryable = match idempotency {
+        // 超时、连接被重置等都可能是暂时的
+        Idempotency::Idempotent => error.is_connect() || error.is_timeout() || error.is_request(),
+        // 只有连接未建立时才能确定请求没有到达服务端
+        Idempotency::Billed | Idempotency::NonIdempotent => error.is_connect(),
+    };
+    if retryable {
+        Some(policy.backoff(attempt))
+    } else {
+        None
+    }
+}
+
+// 解析 Retry-After 头：支持秒数与 HTTP 日期两种格式
+fn parse_retry_after(response: &Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+// 抖动用的随机数，不要求密码学强度
+fn random_u64() -> u64 {
+    uuid::Uuid::new_v4().as_u64_pair().0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response_with_retry_after(value: &str) -> Response {
+        let response = http::Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(reqwest::header::RETRY_AFTER, value)
+            .body("")
+            .unwrap();
+        Response::from(response)
+    }
+
+    #[test]
+    fn retry_after_seconds() {
+        let response = response_with_retry_after(" 12 ");
+        assert_eq!(parse_retry_after(&response), Some(Duration::from_secs(12)));
+    }
+
+    #[test]
+    fn retry_after_http_date() {
+        let date = chrono::Utc::now() + chrono::Duration::seconds(30);
+        let response = response_with_retry_after(&date.format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+        let wait = parse_retry_after(&response).expect("HTTP-date should parse");
+        assert!(wait > Duration::from_secs(25) && wait <= Duration::from_secs(30), "{:?}", wait);
+
+        // 已经过去的时间点不需要等待
+        let response = response_with_retry_after("Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse_retry_after(&response), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn retry_after_invalid_or_missing() {
+        assert_eq!(parse_retry_after(&response_with_retry_after("soon")), None);
+        let response = Response::from(http::Response::new(""));
+        assert_eq!(parse_retry_after(&response), None);
+    }
+
+    #[test]
+    fn backoff_stays_within_jitter_bounds() {
+        let policy = RetryPolicy::default();
+        for _ in 0..100 {
+            let first = policy.backoff(1);
+            assert!(first >= Duration::from_millis(250) && first <= Duration::from_millis(500), "{:?}", first);
+            let third = policy.backoff(3);
+            assert!(third >= Duration::from_millis(1000) && third <= Duration::from_millis(2000), "{:?}", third);
+            // 超过上限后封顶，极大的重试次数也不会溢出
+            let capped = policy.backoff(u32::MAX);
+            assert!(capped >= Duration::from_millis(4000) && capped <= Duration::from_millis(8000), "{:?}", capped);
+        }
+    }
+
+    #[test]
+    fn billed_requests_retry_only_unprocessed_statuses() {
+        let policy = RetryPolicy::default();
+        let status = |code: StatusCode| Response::from(http::Response::builder().status(code).body("").unwrap());
+        for code in [StatusCode::TOO_MANY_REQUESTS, StatusCode::BAD_GATEWAY, StatusCode::SERVICE_UNAVAILABLE] {
+            assert!(retry_delay_for_status(&policy, Idempotency::Billed, &status(code), 1).is_some());
+        }
+        for code in [StatusCode::INTERNAL_SERVER_ERROR, StatusCode::GATEWAY_TIMEOUT, StatusCode::REQUEST_TIMEOUT] {
+            assert!(retry_delay_for_status(&policy, Idempotency::Billed, &status(code), 1).is_none());
+            assert!(retry_delay_for_status(&policy, Idempotency::Idempotent, &status(code), 1).is_some());
+        }
+    }
+
+    #[test]
+    fn long_retry_after_is_capped() {
+        let policy = RetryPolicy::default();
+        let response = response_with_retry_after("3600");
+        assert_eq!(
+            retry_delay_for_status(&policy, Idempotency::Billed, &response, 1),
+            Some(MAX_RETRY_AFTER)
+        );
+        let response = response_with_retry_after("5");
+        assert_eq!(
+            retry_delay_for_status(&policy, Idempotency::Billed, &response, 1),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn backoff_without_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay_ms: 0,
+            max_delay_ms: 0,
+        };
+        assert_eq!(policy.backoff(2), Duration::ZERO);
+    }
+}
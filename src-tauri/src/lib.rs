@@ -1,14 +1,35 @@
 mod storage;
 mod gemini;
+mod openai_image;
 mod ocr_inpaint;
 mod llm;
 mod video;
+mod sse;
+mod cancellation;
+mod http;
+mod usage;
+mod document;
+mod media;
+mod error;
+mod vault;
+mod logging;
+mod models;
+mod structured;
+mod cassette;
 
 use storage::*;
 use gemini::*;
+use openai_image::*;
 use ocr_inpaint::*;
 use llm::*;
 use video::*;
+use cancellation::*;
+use http::*;
+use usage::*;
+use vault::*;
+use logging::*;
+use models::*;
+use cassette::*;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -20,6 +41,14 @@ pub fn run() {
         .plugin(tauri_plugin_window_state::Builder::default().build())
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_store::Builder::default().build())
+        .manage(RequestRegistry::default())
+        .manage(HttpClientManager::default())
+        .manage(ModelCache::default())
+        .setup(|app| {
+            init_logging(app.handle());
+            init_cassette(app.handle());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             save_image,
             read_image,
@@ -34,16 +63,41 @@ pub fn run() {
             gemini_generate_content,
             gemini_generate_text,
             lemon_stream_generation,
+            openai_image_generate,
             process_ppt_page,
             test_ocr_connection,
             test_inpaint_connection,
             // LLM 代理命令
+            chat_completion,
             openai_chat_completion,
             claude_chat_completion,
+            chat_completion_stream,
+            claude_chat_completion_stream,
+            gemini_generate_text_stream,
             // 视频服务代理命令
             video_create_task,
             video_get_status,
-            video_get_content
+            video_get_content,
+            // 请求取消
+            cancel_request,
+            // 模型列表与连接测试
+            list_models,
+            test_provider_connection,
+            // 网络设置
+            configure_http_client,
+            configure_cassette,
+            // 用量统计
+            query_usage,
+            get_usage_prices,
+            set_usage_prices,
+            // 凭据管理
+            set_api_key,
+            rotate_api_key,
+            delete_api_key,
+            list_api_keys,
+            // 日志与诊断
+            get_logs,
+            export_diagnostics
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,559 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{
+    collect_attachments, Attachment, ChatAdapter, ChatOutput, ChatRole, FileData, LLMRequestParams,
+    ProviderRequest, ToolCall, ToolChoice,
+};
+use crate::error::{ApiError, ErrorCode};
+use crate::sse::{SseEvent, StreamEvent};
+use crate::usage::TokenUsage;
+
+// Claude Messages API 版本
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+// 结构化输出：强制模型调用该工具，工具参数即为符合 Schema 的 JSON
+const STRUCTURED_OUTPUT_TOOL: &str = "structured_output";
+// input_schema 必须是 object，其他类型的 Schema 包装在 value 字段中
+const STRUCTURED_VALUE_TOOL: &str = "structured_output_value";
+
+// 扩展思考的最低预算
+const MIN_THINKING_BUDGET: u32 = 1024;
+// 当前模型中最大的输出上限（思考 + 正文），更低的型号限制由接口自身报错
+const MAX_OUTPUT_TOKENS: i32 = 128_000;
+
+// ==================== Claude 协议结构 ====================
+
+#[derive(Debug, Serialize)]
+struct ClaudeRequest {
+    model: String,
+    messages: Vec<ClaudeMessage>,
+    max_tokens: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ClaudeTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    // 扩展思考：{"type": "enabled", "budget_tokens": n}
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct ClaudeTool {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    input_schema: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct ClaudeMessage {
+    role: String,
+    content: ClaudeContent,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum ClaudeContent {
+    Text(String),
+    Parts(Vec<ClaudeContentPart>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+enum ClaudeContentPart {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(rename = "image")]
+    Image { source: ClaudeImageSource },
+    // PDF 文档，source 结构与图片相同
+    #[serde(rename = "document")]
+    Document { source: ClaudeImageSource },
+    #[serde(rename = "tool_use")]
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(rename = "tool_result")]
+    ToolResult { tool_use_id: String, content: String },
+}
+
+#[derive(Debug, Serialize)]
+struct ClaudeImageSource {
+    #[serde(rename = "type")]
+    source_type: String,
+    media_type: String,
+    data: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeResponse {
+    content: Option<Vec<ClaudeContentBlock>>,
+    usage: Option<ClaudeUsage>,
+    error: Option<ClaudeError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeContentBlock {
+    // text、thinking 或 tool_use
+    #[serde(rename = "type")]
+    block_type: Option<String>,
+    text: Option<String>,
+    thinking: Option<String>,
+    id: Option<String>,
+    name: Option<String>,
+    input: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeError {
+    message: String,
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+}
+
+impl ClaudeError {
+    fn to_api_error(&self) -> ApiError {
+        ApiError::new(ErrorCode::ProviderError, self.message.clone()).with_provider_type(self.error_type.clone())
+    }
+}
+
+// ==================== Claude 流式事件结构 ====================
+
+// SSE 事件：message_start / content_block_delta / message_delta / message_stop / error
+#[derive(Debug, Deserialize)]
+struct ClaudeStreamEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    // message_start 携带的消息（含输入 token 用量）
+    message: Option<ClaudeStreamMessage>,
+    delta: Option<ClaudeStreamDelta>,
+    // message_delta 携带的累计输出 token 用量
+    usage: Option<ClaudeUsage>,
+    error: Option<ClaudeError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeStreamMessage {
+    usage: Option<ClaudeUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeUsage {
+    // 不含缓存部分的输入 token
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+    cache_read_input_tokens: Option<u64>,
+    cache_creation_input_tokens: Option<u64>,
+}
+
+impl ClaudeUsage {
+    // 统一为包含缓存部分的输入 token 数
+    fn total_input_tokens(&self) -> Option<u64> {
+        self.input_tokens.map(|input| {
+            input
+                + self.cache_read_input_tokens.unwrap_or(0)
+                + self.cache_creation_input_tokens.unwrap_or(0)
+        })
+    }
+
+    fn to_usage(&self) -> TokenUsage {
+        TokenUsage {
+            input_tokens: self.total_input_tokens().unwrap_or(0),
+            output_tokens: self.output_tokens.unwrap_or(0),
+            cached_tokens: self.cache_read_input_tokens.unwrap_or(0),
+            ..Default::default()
+        }
+    }
+
+    fn to_stream_event(&self) -> StreamEvent {
+        StreamEvent::Usage {
+            input_tokens: self.total_input_tokens(),
+            output_tokens: self.output_tokens,
+            cached_tokens: self.cache_read_input_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeStreamDelta {
+    // content_block_delta 的增量类型（text_delta、input_json_delta 等）
+    #[serde(rename = "type")]
+    delta_type: Option<String>,
+    text: Option<String>,
+    thinking: Option<String>,
+    // message_delta 携带的结束原因
+    stop_reason: Option<String>,
+}
+
+// ==================== Claude 适配器 ====================
+
+pub struct ClaudeAdapter;
+
+impl ChatAdapter for ClaudeAdapter {
+    fn name(&self) -> &'static str {
+        "Claude"
+    }
+
+    fn build_request(&self, params: &LLMRequestParams) -> Result<ProviderRequest, String> {
+        build_claude_request(params, false)
+    }
+
+    fn parse_response(&self, response_text: &str) -> Result<ChatOutput, ApiError> {
+        let claude_response: ClaudeResponse = serde_json::from_str(response_text)
+            .map_err(|e| ApiError::malformed(format!("解析响应失败: {}", e)))?;
+
+        // 检查 API 错误
+        if let Some(err) = claude_response.error {
+            return Err(err.to_api_error());
+        }
+
+        // 提取文本与工具调用；结构化输出工具的参数作为 JSON 正文返回
+        let mut text_parts: Vec<String> = Vec::new();
+        let mut thinking_parts: Vec<String> = Vec::new();
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+        let mut structured: Option<(serde_json::Value, ToolCall)> = None;
+        for block in claude_response.content.unwrap_or_default() {
+            match (block.block_type.as_deref(), block.name.as_deref()) {
+                (Some("tool_use"), Some(STRUCTURED_OUTPUT_TOOL | STRUCTURED_VALUE_TOOL)) => {
+                    let input = block.input.unwrap_or_else(|| json!({}));
+                    let value = if block.name.as_deref() == Some(STRUCTURED_VALUE_TOOL) {
+                        input.get("value").cloned().unwrap_or(serde_json::Value::Null)
+                    } else {
+                        input.clone()
+                    };
+                    let call = ToolCall {
+                        id: block.id.unwrap_or_default(),
+                        name: block.name.unwrap_or_default(),
+                        arguments: input,
+                    };
+                    structured = Some((value, call));
+                }
+                (Some("tool_use"), _) => tool_calls.push(ToolCall {
+                    id: block.id.unwrap_or_default(),
+                    name: block.name.unwrap_or_default(),
+                    arguments: block.input.unwrap_or_else(|| json!({})),
+                }),
+                (Some("thinking"), _) => thinking_parts.extend(block.thinking),
+                _ => text_parts.extend(block.text),
+            }
+        }
+
+        let structured_call = structured.map(|(value, call)| {
+            // 强制调用工具时模型可能仍附带说明文字，正文只保留 JSON
+            text_parts = vec![value.to_string()];
+            call
+        });
+
+        if text_parts.is_empty() && tool_calls.is_empty() {
+            return Err(ApiError::malformed("API 未返回有效内容"));
+        }
+
+        Ok(ChatOutput {
+            content: text_parts.join(""),
+            thinking: if thinking_parts.is_empty() { None } else { Some(thinking_parts.join("")) },
+            tool_calls,
+            structured_call,
+            usage: claude_response.usage.as_ref().map(ClaudeUsage::to_usage),
+        })
+    }
+
+    fn build_stream_request(&self, params: &LLMRequestParams) -> Result<ProviderRequest, String> {
+        build_claude_request(params, true)
+    }
+
+    fn parse_stream_event(&self, event: &SseEvent) -> Result<Vec<StreamEvent>, String> {
+        // 网关可能插入非 JSON 的保活数据，直接忽略
+        let stream_event: ClaudeStreamEvent = match serde_json::from_str(&event.data) {
+            Ok(e) => e,
+            Err(e) => {
+                tracing::warn!("Skip malformed Claude stream event {:?}: {}", event.event, e);
+                return Ok(Vec::new());
+            }
+        };
+
+        match stream_event.event_type.as_str() {
+            "message_start" => {
+                let usage = stream_event.message.and_then(|message| message.usage);
+                Ok(usage.map(|usage| usage.to_stream_event()).into_iter().collect())
+            }
+            "content_block_delta" => {
+                let Some(delta) = stream_event.delta else {
+                    return Ok(Vec::new());
+                };
+                match (delta.delta_type.as_deref(), delta.text, delta.thinking) {
+                    (Some("text_delta"), Some(text), _) => Ok(vec![StreamEvent::TextDelta { text }]),
+                    (Some("thinking_delta"), _, Some(text)) => Ok(vec![StreamEvent::ReasoningDelta { text }]),
+                    _ => Ok(Vec::new()),
+                }
+            }
+            "message_delta" => {
+                let mut events = Vec::new();
+                if let Some(usage) = stream_event.usage {
+                    events.push(usage.to_stream_event());
+                }
+                events.push(StreamEvent::Finish {
+                    reason: stream_event.delta.and_then(|delta| delta.stop_reason),
+                });
+                Ok(events)
+            }
+            "error" => Err(stream_event
+                .error
+                .map(|err| err.message)
+                .unwrap_or_else(|| "流式响应返回未知错误".to_string())),
+            // content_block_start/stop、message_stop、ping 不含内容
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+// 构建 Messages API 请求，stream 为 true 时开启 SSE 流式输出
+fn build_claude_request(params: &LLMRequestParams, stream: bool) -> Result<ProviderRequest, String> {
+    let mut warnings: Vec<String> = Vec::new();
+    let document_mode = params.document_mode.unwrap_or_default();
+
+    // 按顺序添加历史对话与本轮用户消息
+    let messages: Vec<ClaudeMessage> = params
+        .turns()
+        .into_iter()
+        .map(|turn| {
+            let content = match turn.role {
+                // 助手消息只支持纯文本，可附带历史工具调用
+                ChatRole::Assistant if turn.tool_calls.is_empty() => {
+                    ClaudeContent::Text(turn.text.to_string())
+                }
+                ChatRole::Assistant => {
+                    let mut parts: Vec<ClaudeContentPart> = Vec::new();
+                    if !turn.text.is_empty() {
+                        parts.push(ClaudeContentPart::Text { text: turn.text.to_string() });
+                    }
+                    for call in turn.tool_calls {
+                        parts.push(ClaudeContentPart::ToolUse {
+                            id: call.id.clone(),
+                            name: call.name.clone(),
+                            input: call.arguments.clone(),
+                        });
+                    }
+                    ClaudeContent::Parts(parts)
+                }
+                // 工具结果以 user 消息中的 tool_result 回传
+                ChatRole::Tool => ClaudeContent::Parts(vec![ClaudeContentPart::ToolResult {
+                    tool_use_id: turn.tool_call_id.unwrap_or_default().to_string(),
+                    content: turn.text.to_string(),
+                }]),
+                ChatRole::User if turn.files.is_empty() => ClaudeContent::Text(turn.text.to_string()),
+                ChatRole::User => {
+                    // 多模态消息：Claude 要求图片和文档在文本之前
+                    let mut parts: Vec<ClaudeContentPart> = Vec::new();
+                    for attachment in collect_attachments(turn.files, document_mode, true, &mut warnings) {
+                        parts.push(match attachment {
+                            Attachment::Image(file) => ClaudeContentPart::Image {
+                                source: base64_source(file),
+                            },
+                            Attachment::Document(file) => ClaudeContentPart::Document {
+                                source: base64_source(file),
+                            },
+                            Attachment::Text(text) => ClaudeContentPart::Text { text },
+                        });
+                    }
+                    if !turn.text.is_empty() {
+                        parts.push(ClaudeContentPart::Text { text: turn.text.to_string() });
+                    }
+                    ClaudeContent::Parts(parts)
+                }
+            };
+
+            ClaudeMessage {
+                role: match turn.role {
+                    ChatRole::User | ChatRole::Tool => "user".to_string(),
+                    ChatRole::Assistant => "assistant".to_string(),
+                },
+                content,
+            }
+        })
+        .collect();
+
+    // 扩展思考的 token 预算（最低 1024）。思考与工具调用同时使用时需回传上一轮的思考块，
+    // 历史消息不保存思考块，因此已有工具调用的对话不开启思考
+    let has_tool_history = params.turns().iter().any(|turn| !turn.tool_calls.is_empty());
+    let mut thinking_budget = params
+        .reasoning
+        .as_ref()
+        .and_then(|r| r.budget_tokens())
+        .filter(|budget| *budget > 0)
+        .map(|budget| budget.max(MIN_THINKING_BUDGET));
+    if thinking_budget.is_some() && has_tool_history {
+        warnings.push("对话中已有工具调用，本次请求未开启扩展思考".to_string());
+        thinking_budget = None;
+    }
+
+    // 思考 token 计入 max_tokens，且 max_tokens 必须大于预算；合计超出输出上限时缩减思考预算
+    let max_tokens = params.max_tokens.unwrap_or(4096);
+    if let Some(budget) = thinking_budget {
+        let room = MAX_OUTPUT_TOKENS.saturating_sub(max_tokens);
+        let allowed = i32::try_from(budget).unwrap_or(i32::MAX).min(room);
+        if allowed < MIN_THINKING_BUDGET as i32 {
+            return Err(format!(
+                "max_tokens（{}）过大：开启扩展思考时至少需要 {} 个思考 token，合计不能超过 {}",
+                max_tokens, MIN_THINKING_BUDGET, MAX_OUTPUT_TOKENS
+            ));
+        }
+        if (allowed as u32) < budget {
+            warnings.push(format!("思考预算与 max_tokens 合计超出输出上限，已从 {} 调整为 {}", budget, allowed));
+            thinking_budget = Some(allowed as u32);
+        }
+    }
+
+    // 工具定义
+    let mut tools: Vec<ClaudeTool> = params
+        .tools
+        .iter()
+        .flatten()
+        .map(|tool| ClaudeTool {
+            name: tool.name.clone(),
+            description: tool.description.clone(),
+            input_schema: tool.parameters.clone(),
+        })
+        .collect();
+    let has_user_tools = !tools.is_empty();
+
+    let mut tool_choice = params.tool_choice.as_ref().map(|choice| match choice {
+        ToolChoice::Auto => json!({ "type": "auto" }),
+        ToolChoice::None => json!({ "type": "none" }),
+        ToolChoice::Required => json!({ "type": "any" }),
+        ToolChoice::Tool { name } => json!({ "type": "tool", "name": name }),
+    });
+
+    // Messages API 没有 JSON Schema 输出参数，改为提供一个以该 Schema 为参数的工具并强制调用。
+    // 流式输出不解析工具调用，仍按普通文本返回
+    if let Some(schema) = params.response_json_schema.as_ref().filter(|_| !stream) {
+        let tool = structured_output_tool(schema);
+        // 同时提供了其他工具时由模型决定先调用哪个工具；开启思考时不能强制调用，由后续 Schema 校验兜底
+        if thinking_budget.is_none() {
+            if !has_user_tools {
+                tool_choice = Some(json!({ "type": "tool", "name": tool.name }));
+            } else if tool_choice.is_none() {
+                tool_choice = Some(json!({ "type": "any" }));
+            }
+        }
+        tools.push(tool);
+    }
+    let tools = if tools.is_empty() { None } else { Some(tools) };
+
+    // 开启思考时只能使用默认温度，且不支持强制调用工具
+    let mut temperature = params.temperature;
+    if thinking_budget.is_some() {
+        if temperature.take().is_some() {
+            warnings.push("开启扩展思考时不支持自定义温度，已忽略 temperature".to_string());
+        }
+        let forced = tool_choice
+            .as_ref()
+            .and_then(|choice| choice["type"].as_str())
+            .is_some_and(|choice_type| choice_type == "any" || choice_type == "tool");
+        if forced {
+            warnings.push("开启扩展思考时不支持强制调用工具，已改为由模型决定".to_string());
+            tool_choice = Some(json!({ "type": "auto" }));
+        }
+    }
+
+    // 构建请求体
+    let request_body = ClaudeRequest {
+        model: params.model.clone(),
+        messages,
+        max_tokens: max_tokens.saturating_add(thinking_budget.map_or(0, |budget| budget as i32)),
+        system: params.system_prompt.clone(),
+        temperature,
+        tools,
+        tool_choice,
+        stream: if stream { Some(true) } else { None },
+        thinking: thinking_budget.map(|budget| json!({ "type": "enabled", "budget_tokens": budget })),
+    };
+
+    let body = serde_json::to_value(&request_body)
+        .map_err(|e| format!("序列化请求失败: {}", e))?;
+
+    Ok(ProviderRequest {
+        url: format!("{}/v1/messages", params.base_url.trim_end_matches('/')),
+        headers: vec![
+            ("x-api-key", params.api_key.clone()),
+            ("anthropic-version", ANTHROPIC_VERSION.to_string()),
+        ],
+        body,
+        warnings,
+    })
+}
+
+// 以输出 Schema 为参数的工具，非 object 类型的 Schema 包装在 value 字段中
+fn structured_output_tool(schema: &serde_json::Value) -> ClaudeTool {
+    let description = Some("以 JSON 形式返回最终结果，参数必须严格符合给定的 Schema".to_string());
+    if schema.get("type").and_then(|t| t.as_str()) == Some("object") {
+        ClaudeTool {
+            name: STRUCTURED_OUTPUT_TOOL.to_string(),
+            description,
+            input_schema: schema.clone(),
+        }
+    } else {
+        ClaudeTool {
+            name: STRUCTURED_VALUE_TOOL.to_string(),
+            description,
+            input_schema: json!({
+                "type": "object",
+                "properties": { "value": schema },
+                "required": ["value"],
+            }),
+        }
+    }
+}
+
+fn base64_source(file: &FileData) -> ClaudeImageSource {
+    ClaudeImageSource {
+        source_type: "base64".to_string(),
+        media_type: file.mime_type.clone(),
+        data: file.data.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thinking_params(max_tokens: i32, budget_tokens: u32) -> LLMRequestParams {
+        serde_json::from_value(json!({
+            "baseUrl": "https://api.example.com",
+            "model": "claude-test",
+            "prompt": "你好",
+            "maxTokens": max_tokens,
+            "reasoning": { "budgetTokens": budget_tokens },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn thinking_budget_is_added_to_max_tokens() {
+        let request = build_claude_request(&thinking_params(4096, 8192), false).unwrap();
+        assert_eq!(request.body["max_tokens"], 4096 + 8192);
+        assert_eq!(request.body["thinking"]["budget_tokens"], 8192);
+        assert!(request.warnings.is_empty());
+    }
+
+    #[test]
+    fn oversized_thinking_budget_is_clamped() {
+        let request = build_claude_request(&thinking_params(4096, u32::MAX), false).unwrap();
+        assert_eq!(request.body["max_tokens"], MAX_OUTPUT_TOKENS);
+        assert_eq!(request.body["thinking"]["budget_tokens"], MAX_OUTPUT_TOKENS - 4096);
+        assert_eq!(request.warnings.len(), 1);
+    }
+
+    #[test]
+    fn max_tokens_without_room_for_thinking_is_rejected() {
+        assert!(build_claude_request(&thinking_params(MAX_OUTPUT_TOKENS, 2048), false).is_err());
+        assert!(build_claude_request(&thinking_params(i32::MAX, 2048), false).is_err());
+    }
+}
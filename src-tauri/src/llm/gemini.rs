@@ -0,0 +1,391 @@
+use serde::Serialize;
+use serde_json::json;
+
+use super::{
+    ChatAdapter, ChatOutput, ChatRole, DocumentMode, LLMRequestParams, ProviderRequest, ToolCall,
+    ToolChoice,
+};
+use crate::document::is_pdf;
+use crate::error::ApiError;
+use crate::gemini::{Content, FunctionCall, FunctionResponse, GeminiResponse, InlineData, Part};
+use crate::sse::{SseEvent, StreamEvent};
+
+// ==================== Gemini 文本生成结构 ====================
+
+// LLM 专用请求体
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LLMRequest {
+    contents: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generation_config: Option<LLMGenerationConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<GeminiTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_config: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiTool {
+    function_declarations: Vec<GeminiFunctionDeclaration>,
+}
+
+#[derive(Debug, Serialize)]
+struct GeminiFunctionDeclaration {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    parameters: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LLMGenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_schema: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking_config: Option<GeminiThinkingConfig>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiThinkingConfig {
+    // 思考 token 预算，0 表示关闭思考
+    thinking_budget: u32,
+    // 返回思考摘要
+    include_thoughts: bool,
+}
+
+// ==================== Gemini 适配器 ====================
+
+pub struct GeminiAdapter;
+
+impl ChatAdapter for GeminiAdapter {
+    fn name(&self) -> &'static str {
+        "Gemini"
+    }
+
+    fn build_request(&self, params: &LLMRequestParams) -> Result<ProviderRequest, String> {
+        build_gemini_request(params, false)
+    }
+
+    fn parse_response(&self, response_text: &str) -> Result<ChatOutput, ApiError> {
+        let gemini_response: GeminiResponse = serde_json::from_str(response_text)
+            .map_err(|e| ApiError::malformed(format!("解析响应失败: {}", e)))?;
+
+        // 检查 API 错误
+        if let Some(err) = gemini_response.error {
+            return Err(err.to_api_error());
+        }
+
+        let usage = gemini_response.usage_metadata.as_ref().map(|usage| usage.to_usage());
+
+        // 提示词被拦截时没有候选，直接返回拦截原因
+        if let Some(block) = gemini_response.prompt_block() {
+            return Err(block.to_api_error());
+        }
+
+        // 提取文本内容与函数调用
+        let candidate = gemini_response
+            .candidates
+            .and_then(|candidates| candidates.into_iter().next());
+        let block = candidate.as_ref().and_then(|candidate| candidate.block_info());
+        let parts = candidate
+            .and_then(|candidate| candidate.content)
+            .and_then(|content| content.parts)
+            .unwrap_or_default();
+
+        let mut text_parts: Vec<String> = Vec::new();
+        let mut thought_parts: Vec<String> = Vec::new();
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+        for part in parts {
+            if let Some(text) = part.text {
+                if part.thought == Some(true) {
+                    thought_parts.push(text);
+                } else {
+                    text_parts.push(text);
+                }
+            }
+            if let Some(call) = part.function_call {
+                // 旧版接口不返回调用 id，以函数名代替
+                tool_calls.push(ToolCall {
+                    id: call.id.unwrap_or_else(|| call.name.clone()),
+                    name: call.name,
+                    arguments: call.args,
+                });
+            }
+        }
+
+        if text_parts.is_empty() && tool_calls.is_empty() {
+            return Err(block
+                .map(|block| block.to_api_error())
+                .unwrap_or_else(|| ApiError::malformed("API 未返回有效内容")));
+        }
+
+        Ok(ChatOutput {
+            content: text_parts.join(""),
+            thinking: if thought_parts.is_empty() { None } else { Some(thought_parts.join("")) },
+            tool_calls,
+            structured_call: None,
+            usage,
+        })
+    }
+
+    fn build_stream_request(&self, params: &LLMRequestParams) -> Result<ProviderRequest, String> {
+        build_gemini_request(params, true)
+    }
+
+    fn parse_stream_event(&self, event: &SseEvent) -> Result<Vec<StreamEvent>, String> {
+        // 每个事件都是完整的 GenerateContentResponse，候选中只包含本次新增的部分
+        let chunk: GeminiResponse = match serde_json::from_str(&event.data) {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::warn!("Skip malformed Gemini stream chunk: {}", e);
+                return Ok(Vec::new());
+            }
+        };
+
+        if let Some(err) = chunk.error {
+            return Err(err.message);
+        }
+        if let Some(block) = chunk.prompt_block() {
+            return Err(block.describe());
+        }
+
+        let mut events = Vec::new();
+        if let Some(candidate) = chunk.candidates.and_then(|candidates| candidates.into_iter().next()) {
+            let block = candidate.block_info();
+            for part in candidate.content.and_then(|content| content.parts).unwrap_or_default() {
+                if let Some(text) = part.text.filter(|t| !t.is_empty()) {
+                    events.push(if part.thought == Some(true) {
+                        StreamEvent::ReasoningDelta { text }
+                    } else {
+                        StreamEvent::TextDelta { text }
+                    });
+                }
+                // 思考过程中的草稿图不作为生成结果推送
+                if let Some(inline) = part.inline_data.filter(|_| part.thought != Some(true)) {
+                    events.push(StreamEvent::ImageUrl {
+                        url: format!("data:{};base64,{}", inline.mime_type, inline.data),
+                    });
+                }
+            }
+            if let Some(reason) = candidate.finish_reason {
+                // 被拦截或截断时附带说明，已输出的内容仍然保留
+                if let Some(block) = block {
+                    events.push(StreamEvent::Warning { message: block.describe() });
+                }
+                events.push(StreamEvent::Finish { reason: Some(reason) });
+            }
+        }
+
+        // 用量为累计值，通常随最后一个事件返回
+        if let Some(usage) = chunk.usage_metadata {
+            let usage = usage.to_usage();
+            events.push(StreamEvent::Usage {
+                input_tokens: Some(usage.input_tokens),
+                output_tokens: Some(usage.output_tokens),
+                cached_tokens: Some(usage.cached_tokens),
+            });
+        }
+
+        Ok(events)
+    }
+}
+
+// 构建 generateContent 请求，stream 为 true 时改用 streamGenerateContent 接口
+fn build_gemini_request(params: &LLMRequestParams, stream: bool) -> Result<ProviderRequest, String> {
+    let system_prompt = params.system_prompt.as_deref().filter(|p| !p.is_empty());
+
+    let mut warnings: Vec<String> = Vec::new();
+    let document_mode = params.document_mode.unwrap_or_default();
+
+    // 按顺序构建历史对话与本轮用户消息
+    let mut contents: Vec<Content> = Vec::new();
+    for (index, turn) in params.turns().into_iter().enumerate() {
+        // 系统指令合并到第一轮用户消息中
+        let text = match system_prompt {
+            Some(system_prompt) if index == 0 && turn.role == ChatRole::User => {
+                format!("系统指令：{}\n\n用户请求：{}", system_prompt, turn.text)
+            }
+            _ => turn.text.to_string(),
+        };
+
+        // 构建 parts：先添加文本，再添加文件
+        let mut parts: Vec<Part> = Vec::new();
+        if !text.is_empty() {
+            parts.push(Part::Text { text });
+        }
+
+        // 助手消息中的历史函数调用
+        for call in turn.tool_calls {
+            parts.push(Part::FunctionCall {
+                function_call: FunctionCall {
+                    id: None,
+                    name: call.name.clone(),
+                    args: call.arguments.clone(),
+                },
+            });
+        }
+
+        // 工具结果以 functionResponse 回传，response 必须是对象
+        if turn.role == ChatRole::Tool {
+            let response = match serde_json::from_str::<serde_json::Value>(turn.text) {
+                Ok(value @ serde_json::Value::Object(_)) => value,
+                Ok(value) => json!({ "result": value }),
+                Err(_) => json!({ "result": turn.text }),
+            };
+            parts = vec![Part::FunctionResponse {
+                function_response: FunctionResponse {
+                    id: None,
+                    name: turn.tool_name.unwrap_or_default().to_string(),
+                    response,
+                },
+            }];
+        }
+
+        // 添加文件（PDF、图片等），仅用户消息携带附件
+        if turn.role == ChatRole::User {
+            for file in turn.files {
+                tracing::debug!("Adding file: mime_type={}, name={:?}", file.mime_type, file.file_name);
+                // Gemini 原生支持 PDF，仅在指定时改为发送提取出的文本
+                if document_mode == DocumentMode::ExtractText && is_pdf(&file.mime_type) {
+                    match file.document_text() {
+                        Ok(text) => {
+                            parts.push(Part::Text { text });
+                            continue;
+                        }
+                        Err(e) => warnings.push(format!(
+                            "附件 {} 文本提取失败，已按原文件发送：{}",
+                            file.file_name.as_deref().unwrap_or("未命名文件"),
+                            e
+                        )),
+                    }
+                }
+                parts.push(Part::InlineData {
+                    inline_data: InlineData {
+                        mime_type: file.mime_type.clone(),
+                        data: file.data.clone(),
+                    },
+                });
+            }
+        }
+
+        contents.push(Content {
+            role: Some(match turn.role {
+                ChatRole::User | ChatRole::Tool => "user".to_string(),
+                ChatRole::Assistant => "model".to_string(),
+            }),
+            parts,
+        });
+    }
+
+    let wants_json = params.response_json_schema.is_some()
+        || params.output_format.as_deref() == Some("json");
+
+    // 工具定义
+    let tools = params.tools.as_ref().filter(|tools| !tools.is_empty()).map(|tools| {
+        vec![GeminiTool {
+            function_declarations: tools
+                .iter()
+                .map(|tool| GeminiFunctionDeclaration {
+                    name: tool.name.clone(),
+                    description: tool.description.clone(),
+                    parameters: tool.parameters.clone(),
+                })
+                .collect(),
+        }]
+    });
+
+    let tool_config = params.tool_choice.as_ref().map(|choice| {
+        let config = match choice {
+            ToolChoice::Auto => json!({ "mode": "AUTO" }),
+            ToolChoice::None => json!({ "mode": "NONE" }),
+            ToolChoice::Required => json!({ "mode": "ANY" }),
+            ToolChoice::Tool { name } => json!({ "mode": "ANY", "allowedFunctionNames": [name] }),
+        };
+        json!({ "functionCallingConfig": config })
+    });
+
+    let request_body = LLMRequest {
+        contents,
+        tools,
+        tool_config,
+        generation_config: Some(LLMGenerationConfig {
+            response_mime_type: if wants_json {
+                Some("application/json".to_string())
+            } else {
+                None
+            },
+            response_schema: params.response_json_schema.clone(),
+            temperature: params.temperature,
+            max_output_tokens: params.max_tokens,
+            thinking_config: params.reasoning.as_ref().and_then(|r| r.budget_tokens()).map(|budget| {
+                GeminiThinkingConfig {
+                    thinking_budget: budget,
+                    include_thoughts: budget > 0,
+                }
+            }),
+        }),
+    };
+
+    let body = serde_json::to_value(&request_body)
+        .map_err(|e| format!("序列化请求失败: {}", e))?;
+
+    // 流式接口需指定 alt=sse 才会返回标准 SSE
+    let method = if stream { "streamGenerateContent?alt=sse" } else { "generateContent" };
+    Ok(ProviderRequest {
+        url: format!(
+            "{}/models/{}:{}",
+            params.base_url.trim_end_matches('/'),
+            params.model,
+            method
+        ),
+        // Key 放在请求头中，避免出现在 URL 与日志里
+        headers: vec![("x-goog-api-key", params.api_key.clone())],
+        body,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_skips_thought_images() {
+        let data = serde_json::json!({
+            "candidates": [{
+                "content": { "parts": [
+                    { "inlineData": { "mimeType": "image/png", "data": "DRAFT" }, "thought": true },
+                    { "text": "构图中", "thought": true },
+                    { "inlineData": { "mimeType": "image/png", "data": "FINAL" } }
+                ]}
+            }]
+        });
+        let event = SseEvent {
+            event: None,
+            data: data.to_string(),
+        };
+
+        let events = GeminiAdapter.parse_stream_event(&event).unwrap();
+        let urls: Vec<&str> = events
+            .iter()
+            .filter_map(|event| match event {
+                StreamEvent::ImageUrl { url } => Some(url.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(urls, vec!["data:image/png;base64,FINAL"]);
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, StreamEvent::ReasoningDelta { text } if text == "构图中")));
+    }
+}
@@ -0,0 +1,1095 @@
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+use tracing::{debug, info, warn, Instrument};
+
+use crate::cancellation::RequestRegistry;
+use crate::document::{extract_text, is_pdf, is_text};
+use crate::error::{ApiError, ErrorCode};
+use crate::http::{send_with_retry, HttpClientManager, Idempotency, RetryPolicy, TimeoutKey};
+use crate::logging::request_key;
+use crate::media::{prepare_conversation_images, ImageTarget};
+use crate::sse::{extract_markdown_image_urls, SseEvent, SseParser, StreamEvent};
+use crate::structured::{check_structured_output, repair_prompt, StructuredCheck};
+use crate::usage::{record_usage, TokenUsage};
+use crate::vault::resolve_api_key;
+
+mod claude;
+mod gemini;
+mod openai;
+
+// 结构化输出未通过校验时的最大修复轮数
+const MAX_REPAIR_ROUNDS: u32 = 2;
+
+use claude::ClaudeAdapter;
+use gemini::GeminiAdapter;
+use openai::OpenAIAdapter;
+
+// ==================== 通用数据结构 ====================
+
+// 文件数据结构（用于多模态输入）
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileData {
+    pub data: String,      // base64 编码的文件数据
+    pub mime_type: String, // 文件MIME类型
+    pub file_name: Option<String>, // 文件名（可选）
+    #[serde(skip)]
+    pub extracted_text: Option<Result<String, String>>, // 预先提取的文档文本，修复轮次重建请求时复用
+}
+
+impl FileData {
+    // 文档的纯文本：优先使用预先提取的结果
+    pub(crate) fn document_text(&self) -> Result<String, String> {
+        match &self.extracted_text {
+            Some(result) => result.clone(),
+            None => extract_text(&self.data, &self.mime_type, self.file_name.as_deref()),
+        }
+    }
+}
+
+// 文档类附件（PDF 等）的发送方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DocumentMode {
+    // 使用协议原生的文档输入（Claude document、OpenAI file）
+    #[default]
+    Native,
+    // 在本地提取文本后以文字发送（供应商不支持文档输入时使用）
+    ExtractText,
+}
+
+// 对话角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    User,
+    Assistant,
+    Tool, // 工具执行结果
+}
+
+// 历史对话中的一轮消息
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    #[serde(default)]
+    pub content: String,
+    pub files: Option<Vec<FileData>>, // 该轮消息的附件（仅用户消息有效）
+    pub tool_calls: Option<Vec<ToolCall>>, // 助手消息中模型发起的工具调用
+    pub tool_call_id: Option<String>, // 工具结果对应的调用 ID
+    pub tool_name: Option<String>, // 工具结果对应的工具名（Gemini 需要）
+}
+
+// 可供模型调用的工具定义
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: Option<String>,
+    pub parameters: serde_json::Value, // 参数的 JSON Schema
+}
+
+// 工具选择策略
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ToolChoice {
+    Auto,     // 由模型决定
+    None,     // 禁止调用工具
+    Required, // 必须调用任一工具
+    Tool { name: String }, // 必须调用指定工具
+}
+
+// 推理强度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    // 按 token 预算协议换算的默认预算
+    fn budget_tokens(self) -> u32 {
+        match self {
+            ReasoningEffort::Low => 1024,
+            ReasoningEffort::Medium => 8192,
+            ReasoningEffort::High => 24576,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+}
+
+// 推理 / 扩展思考设置：OpenAI 使用 effort，Claude 与 Gemini 使用 token 预算，未指定的一方按另一方换算
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReasoningConfig {
+    pub effort: Option<ReasoningEffort>,
+    pub budget_tokens: Option<u32>, // 思考 token 预算，0 表示关闭思考
+}
+
+impl ReasoningConfig {
+    // 思考 token 预算（预算优先于强度）
+    pub fn budget_tokens(&self) -> Option<u32> {
+        self.budget_tokens.or_else(|| self.effort.map(ReasoningEffort::budget_tokens))
+    }
+
+    // 推理强度（强度优先于预算）；预算为 0 时返回 None
+    pub fn effort(&self) -> Option<ReasoningEffort> {
+        self.effort.or_else(|| match self.budget_tokens? {
+            0 => None,
+            1..=2048 => Some(ReasoningEffort::Low),
+            2049..=8192 => Some(ReasoningEffort::Medium),
+            _ => Some(ReasoningEffort::High),
+        })
+    }
+}
+
+// 模型发起的工具调用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value, // 解析后的调用参数
+}
+
+// LLM 请求参数（前端传入）
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LLMRequestParams {
+    pub base_url: String,
+    #[serde(default)]
+    pub api_key: String, // 未使用凭据库时由前端直接传入
+    pub provider_id: Option<String>, // 凭据库中的供应商 ID，优先于 api_key
+    pub model: String,
+    #[serde(default)]
+    pub prompt: String, // 本轮用户输入，会追加在 messages 之后
+    pub messages: Option<Vec<ChatMessage>>, // 历史对话（按时间顺序）
+    pub system_prompt: Option<String>,
+    pub output_format: Option<String>, // "text" or "json"
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<i32>,
+    pub files: Option<Vec<FileData>>, // 文件数据（PDF、图片等）
+    pub response_json_schema: Option<serde_json::Value>, // 结构化输出的 JSON Schema
+    pub tools: Option<Vec<ToolDefinition>>, // 可供模型调用的工具
+    pub tool_choice: Option<ToolChoice>, // 工具选择策略（默认由模型决定）
+    pub request_id: Option<String>, // 用于 cancel_request 取消请求
+    pub retry: Option<RetryPolicy>, // 重试策略（按供应商配置，默认重试 3 次）
+    pub canvas_id: Option<String>,  // 用量记账归属的画布
+    pub document_mode: Option<DocumentMode>, // PDF 等文档附件的发送方式
+    pub reasoning: Option<ReasoningConfig>, // 推理 / 扩展思考设置（默认沿用模型自身行为）
+}
+
+// LLM 响应结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LLMResult {
+    pub success: bool,
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>, // 模型发起的工具调用
+    #[serde(skip)]
+    pub structured_call: Option<ToolCall>, // 结构化输出工具的调用，修复时按工具调用回放
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parsed: Option<serde_json::Value>, // 结构化输出解析并通过 Schema 校验后的 JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<String>, // 模型的思考过程或推理摘要，与正文分开返回
+    pub error: Option<ApiError>,
+    pub attempts: u32, // 实际发送请求的次数（含重试）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>, // Token 用量
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>, // 被忽略或降级处理的附件等提示
+}
+
+// 展开后的单轮对话，供各协议适配器映射
+pub struct ChatTurn<'a> {
+    pub role: ChatRole,
+    pub text: &'a str,
+    pub files: &'a [FileData],
+    pub tool_calls: &'a [ToolCall],
+    pub tool_call_id: Option<&'a str>,
+    pub tool_name: Option<&'a str>,
+}
+
+impl LLMRequestParams {
+    // 历史消息 + 本轮 prompt（及 files）组成的完整对话
+    pub fn turns(&self) -> Vec<ChatTurn<'_>> {
+        let mut turns: Vec<ChatTurn<'_>> = self
+            .messages
+            .iter()
+            .flatten()
+            .map(|message| ChatTurn {
+                role: message.role,
+                text: &message.content,
+                files: message.files.as_deref().unwrap_or(&[]),
+                tool_calls: message.tool_calls.as_deref().unwrap_or(&[]),
+                tool_call_id: message.tool_call_id.as_deref(),
+                tool_name: message.tool_name.as_deref(),
+            })
+            .collect();
+
+        let files = self.files.as_deref().unwrap_or(&[]);
+        if !self.prompt.is_empty() || !files.is_empty() {
+            turns.push(ChatTurn {
+                role: ChatRole::User,
+                text: &self.prompt,
+                files,
+                tool_calls: &[],
+                tool_call_id: None,
+                tool_name: None,
+            });
+        }
+        turns
+    }
+}
+
+// API 协议类型（与前端 ProviderProtocol 保持一致）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderProtocol {
+    Openai,
+    Google,
+    Claude,
+}
+
+impl ProviderProtocol {
+    // 获取协议对应的适配器
+    pub(crate) fn adapter(self) -> &'static dyn ChatAdapter {
+        match self {
+            ProviderProtocol::Openai => &OpenAIAdapter,
+            ProviderProtocol::Google => &GeminiAdapter,
+            ProviderProtocol::Claude => &ClaudeAdapter,
+        }
+    }
+
+    // 图片附件需满足的供应商限制
+    fn image_target(self) -> ImageTarget {
+        match self {
+            ProviderProtocol::Openai => ImageTarget::OpenAIChat,
+            ProviderProtocol::Google => ImageTarget::Gemini,
+            ProviderProtocol::Claude => ImageTarget::Claude,
+        }
+    }
+}
+
+// 按协议限制缩放、压缩全部图片附件（含历史消息），原地替换数据并返回调整说明
+// 张数上限只针对本轮的图片，历史消息中的图片只计入合计字节预算
+async fn prepare_image_files(params: &mut LLMRequestParams, protocol: ProviderProtocol) -> Result<Vec<String>, ApiError> {
+    let is_image = |file: &&mut FileData| file.mime_type.starts_with("image/");
+    let mut history: Vec<&mut FileData> = params
+        .messages
+        .iter_mut()
+        .flatten()
+        .filter_map(|message| message.files.as_mut())
+        .flatten()
+        .filter(is_image)
+        .collect();
+    let mut current: Vec<&mut FileData> = params.files.iter_mut().flatten().filter(is_image).collect();
+    if history.is_empty() && current.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let history_data = history.iter().map(|file| file.data.clone()).collect();
+    let current_data = current.iter().map(|file| file.data.clone()).collect();
+    let prepared = prepare_conversation_images(history_data, current_data, protocol.image_target()).await?;
+    for (file, image) in history.iter_mut().chain(current.iter_mut()).zip(prepared.images) {
+        file.data = image.to_base64();
+        file.mime_type = image.mime_type().to_string();
+    }
+    Ok(prepared.warnings)
+}
+
+// 在阻塞线程中一次性提取需要以文本发送的文档附件，避免 PDF 解析阻塞异步运行时及修复轮次重复解析
+async fn prepare_document_files(params: &mut LLMRequestParams, protocol: ProviderProtocol) {
+    let mode = params.document_mode.unwrap_or_default();
+    let mut files: Vec<&mut FileData> = params
+        .messages
+        .iter_mut()
+        .flatten()
+        .filter_map(|message| message.files.as_mut())
+        .chain(params.files.as_mut())
+        .flatten()
+        .filter(|file| {
+            // Gemini 直接接收文本文件，其余协议把文本文件展开为文字
+            (is_pdf(&file.mime_type) && mode == DocumentMode::ExtractText)
+                || (is_text(&file.mime_type) && protocol != ProviderProtocol::Google)
+        })
+        .collect();
+    if files.is_empty() {
+        return;
+    }
+
+    let sources: Vec<(String, String, Option<String>)> = files
+        .iter()
+        .map(|file| (file.data.clone(), file.mime_type.clone(), file.file_name.clone()))
+        .collect();
+    let extracted = tokio::task::spawn_blocking(move || {
+        sources
+            .iter()
+            .map(|(data, mime_type, file_name)| extract_text(data, mime_type, file_name.as_deref()))
+            .collect::<Vec<_>>()
+    })
+    .await;
+    match extracted {
+        Ok(extracted) => {
+            for (file, text) in files.iter_mut().zip(extracted) {
+                file.extracted_text = Some(text);
+            }
+        }
+        Err(e) => {
+            warn!("Document extraction task failed: {}", e);
+            for file in files.iter_mut() {
+                file.extracted_text = Some(Err("文档文本提取失败".to_string()));
+            }
+        }
+    }
+}
+
+// ==================== 协议适配器 ====================
+
+// 发往供应商的 HTTP 请求描述
+pub struct ProviderRequest {
+    pub url: String,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: serde_json::Value,
+    pub warnings: Vec<String>, // 构建请求时被忽略或降级的附件
+}
+
+// 附件在协议请求中的表示
+pub(crate) enum Attachment<'a> {
+    Image(&'a FileData),
+    // 原生文档输入（PDF）
+    Document(&'a FileData),
+    // 本地提取出的文本
+    Text(String),
+}
+
+// 按协议能力整理附件：不支持的附件和提取失败的文档记入 warnings
+pub(crate) fn collect_attachments<'a>(
+    files: &'a [FileData],
+    mode: DocumentMode,
+    native_documents: bool,
+    warnings: &mut Vec<String>,
+) -> Vec<Attachment<'a>> {
+    let mut attachments = Vec::new();
+    for file in files {
+        let name = file.file_name.as_deref().unwrap_or("未命名文件");
+        if file.mime_type.starts_with("image/") {
+            attachments.push(Attachment::Image(file));
+        } else if is_pdf(&file.mime_type) && native_documents && mode == DocumentMode::Native {
+            attachments.push(Attachment::Document(file));
+        } else if is_pdf(&file.mime_type) || is_text(&file.mime_type) {
+            match file.document_text() {
+                Ok(text) => attachments.push(Attachment::Text(text)),
+                Err(e) => warnings.push(format!("附件 {} 已忽略：{}", name, e)),
+            }
+        } else {
+            warnings.push(format!("附件 {} 已忽略：该协议不支持 {} 类型", name, file.mime_type));
+        }
+    }
+    attachments
+}
+
+// 适配器解析出的统一结果
+pub struct ChatOutput {
+    pub content: String,
+    pub thinking: Option<String>, // 思考内容（不计入正文）
+    pub tool_calls: Vec<ToolCall>,
+    pub structured_call: Option<ToolCall>, // 强制调用的结构化输出工具（参数已作为正文返回）
+    pub usage: Option<TokenUsage>,
+}
+
+// 协议适配器：新增后端只需实现该 trait
+pub trait ChatAdapter: Send + Sync {
+    // 协议名称（用于日志）
+    fn name(&self) -> &'static str;
+
+    // 根据请求参数构建 HTTP 请求
+    fn build_request(&self, params: &LLMRequestParams) -> Result<ProviderRequest, String>;
+
+    // 解析供应商返回的响应文本
+    fn parse_response(&self, response_text: &str) -> Result<ChatOutput, ApiError>;
+
+    // 构建流式请求（默认不支持）
+    fn build_stream_request(&self, _params: &LLMRequestParams) -> Result<ProviderRequest, String> {
+        Err(format!("{} 协议暂不支持流式输出", self.name()))
+    }
+
+    // 解析单个 SSE 事件，供应商返回的错误事件以 Err 表示
+    fn parse_stream_event(&self, _event: &SseEvent) -> Result<Vec<StreamEvent>, String> {
+        Ok(Vec::new())
+    }
+}
+
+// 解析工具调用参数（部分协议以 JSON 字符串返回），无法解析时保留原文
+fn parse_tool_arguments(raw: &str) -> serde_json::Value {
+    if raw.trim().is_empty() {
+        return serde_json::Value::Object(Default::default());
+    }
+    serde_json::from_str(raw).unwrap_or_else(|_| serde_json::Value::String(raw.to_string()))
+}
+
+// 检查请求中至少包含一轮对话
+fn validate_params(params: &LLMRequestParams) -> Result<(), String> {
+    if params.turns().is_empty() {
+        return Err("请求缺少对话内容".to_string());
+    }
+    Ok(())
+}
+
+// 构建不带查询参数的 URL（避免在日志中输出 key）
+fn url_for_log(url: &str) -> &str {
+    url.split('?').next().unwrap_or(url)
+}
+
+// 可取消的对话请求：登记 request_id 后执行
+#[tracing::instrument(name = "request", skip_all, fields(request_id = %request_key(params.request_id.as_deref())))]
+async fn run_chat(app_handle: AppHandle, protocol: ProviderProtocol, mut params: LLMRequestParams) -> LLMResult {
+    params.api_key = match resolve_api_key(&app_handle, params.provider_id.as_deref(), &params.api_key).await {
+        Ok(api_key) => api_key,
+        Err(e) => return LLMResult::failure(e, 0, Vec::new()),
+    };
+
+    let registry = RequestRegistry::from_app(&app_handle);
+    let request_id = params.request_id.clone();
+
+    let http = HttpClientManager::from_app(&app_handle);
+    let canvas_id = params.canvas_id.clone();
+    let model = params.model.clone();
+
+    match registry.run(request_id.as_deref(), execute_chat(http, protocol, params)).await {
+        Ok(result) => {
+            if let Some(usage) = &result.usage {
+                record_usage(&app_handle, canvas_id.as_deref(), protocol.adapter().name(), &model, usage);
+            }
+            result
+        }
+        Err(_) => {
+            info!("Chat request cancelled");
+            LLMResult::failure(ApiError::cancelled(), 0, Vec::new())
+        }
+    }
+}
+
+// 通用对话请求流程：构建请求 -> 发送 -> 解析
+async fn execute_chat(http: HttpClientManager, protocol: ProviderProtocol, mut params: LLMRequestParams) -> LLMResult {
+    let adapter = protocol.adapter();
+    info!(
+        model = %params.model,
+        files = params.files.as_ref().map(|v| v.len()).unwrap_or(0),
+        messages = params.messages.as_ref().map(|v| v.len()).unwrap_or(0),
+        "{} chat called",
+        adapter.name()
+    );
+
+    let image_warnings = match prepare_image_files(&mut params, protocol).await {
+        Ok(warnings) => warnings,
+        Err(e) => return LLMResult::failure(e, 0, Vec::new()),
+    };
+
+    prepare_document_files(&mut params, protocol).await;
+    let mut result = send_chat(&http, adapter, &params).await;
+    if result.success && result.tool_calls.is_empty() && wants_structured_output(&params) {
+        result = enforce_structured_output(&http, adapter, &mut params, result).await;
+    }
+    result.warnings.splice(0..0, image_warnings);
+    result
+}
+
+impl LLMResult {
+    // 失败结果：保留已发送次数与附件提示
+    pub(crate) fn failure(error: ApiError, attempts: u32, warnings: Vec<String>) -> Self {
+        LLMResult {
+            success: false,
+            content: None,
+            tool_calls: Vec::new(),
+            structured_call: None,
+            parsed: None,
+            thinking: None,
+            error: Some(error),
+            attempts,
+            usage: None,
+            warnings,
+        }
+    }
+}
+
+// 请求了 JSON Schema 或 JSON 格式的输出
+fn wants_structured_output(params: &LLMRequestParams) -> bool {
+    params.response_json_schema.is_some() || params.output_format.as_deref() == Some("json")
+}
+
+// 校验结构化输出：不符合 Schema 时把错误回传给模型修复，最多 MAX_REPAIR_ROUNDS 轮
+async fn enforce_structured_output(
+    http: &HttpClientManager,
+    adapter: &dyn ChatAdapter,
+    params: &mut LLMRequestParams,
+    mut result: LLMResult,
+) -> LLMResult {
+    let schema = params.response_json_schema.clone();
+    let mut repairs = 0;
+    loop {
+        let content = result.content.clone().unwrap_or_default();
+        let errors = match check_structured_output(&content, schema.as_ref()) {
+            StructuredCheck::Valid(value) => {
+                if repairs > 0 {
+                    result.warnings.push(format!("输出未通过 JSON Schema 校验，已自动修复 {} 次", repairs));
+                }
+                result.parsed = Some(value);
+                return result;
+            }
+            StructuredCheck::Invalid(errors) => errors,
+        };
+        warn!(repairs, errors = ?errors, "Structured output failed validation");
+
+        if repairs >= MAX_REPAIR_ROUNDS {
+            result.success = false;
+            result.error = Some(ApiError::malformed(format!(
+                "输出不符合 JSON Schema（已尝试修复 {} 次）：{}",
+                repairs,
+                errors.join("；")
+            )));
+            return result;
+        }
+        repairs += 1;
+
+        // 把本轮输出与校验错误追加到对话中重新请求
+        push_repair_turns(params, content, result.structured_call.take(), &errors);
+
+        let mut next = send_chat(http, adapter, params).await;
+        // 请求次数与用量按所有轮次累计，提示沿用首轮（附件提示在每轮都会重复）
+        next.attempts += result.attempts;
+        next.usage = match (result.usage.take(), next.usage.take()) {
+            (Some(mut total), Some(usage)) => {
+                total += &usage;
+                Some(total)
+            }
+            (total, usage) => total.or(usage),
+        };
+        next.warnings = std::mem::take(&mut result.warnings);
+        if !next.success {
+            return next;
+        }
+        result = next;
+    }
+}
+
+// 把上一轮的输入、输出与校验错误追加到历史对话，本轮没有 prompt 与附件时不追加空的用户消息
+fn push_repair_turns(
+    params: &mut LLMRequestParams,
+    content: String,
+    structured_call: Option<ToolCall>,
+    errors: &[String],
+) {
+    let messages = params.messages.get_or_insert_with(Vec::new);
+    let files = params.files.take().filter(|files| !files.is_empty());
+    if !params.prompt.is_empty() || files.is_some() {
+        messages.push(ChatMessage {
+            role: ChatRole::User,
+            content: std::mem::take(&mut params.prompt),
+            files,
+            tool_calls: None,
+            tool_call_id: None,
+            tool_name: None,
+        });
+    }
+    match structured_call {
+        // 输出来自强制调用的结构化输出工具：按工具调用回放，校验错误作为工具结果回传
+        Some(call) => {
+            let (call_id, call_name) = (call.id.clone(), call.name.clone());
+            messages.push(ChatMessage {
+                role: ChatRole::Assistant,
+                content: String::new(),
+                files: None,
+                tool_calls: Some(vec![call]),
+                tool_call_id: None,
+                tool_name: None,
+            });
+            messages.push(ChatMessage {
+                role: ChatRole::Tool,
+                content: repair_prompt(errors),
+                files: None,
+                tool_calls: None,
+                tool_call_id: Some(call_id),
+                tool_name: Some(call_name),
+            });
+        }
+        None => {
+            messages.push(ChatMessage {
+                role: ChatRole::Assistant,
+                content,
+                files: None,
+                tool_calls: None,
+                tool_call_id: None,
+                tool_name: None,
+            });
+            params.prompt = repair_prompt(errors);
+        }
+    }
+}
+
+// 构建请求 -> 发送 -> 解析
+async fn send_chat(http: &HttpClientManager, adapter: &dyn ChatAdapter, params: &LLMRequestParams) -> LLMResult {
+    let request = match validate_params(params).and_then(|_| adapter.build_request(params)) {
+        Ok(r) => r,
+        Err(e) => return LLMResult::failure(ApiError::invalid_request(e), 0, Vec::new()),
+    };
+    debug!("Request URL: {}", url_for_log(&request.url));
+    for warning in &request.warnings {
+        warn!("Attachment warning: {}", warning);
+    }
+
+    // 使用共享 HTTP 客户端
+    let client = http.client();
+    let timeout = http.timeout(TimeoutKey::Chat);
+
+    // 发送请求
+    let start_time = std::time::Instant::now();
+
+    // 对话生成按次计费，仅在请求确定未被处理时重试
+    let policy = params.retry.clone().unwrap_or_default();
+    let outcome = send_with_retry(&policy, Idempotency::Billed, adapter.name(), || {
+        let mut builder = client
+            .post(&request.url)
+            .timeout(timeout)
+            .header("Content-Type", "application/json");
+        for (name, value) in &request.headers {
+            builder = builder.header(*name, value);
+        }
+        builder.json(&request.body)
+    })
+    .await;
+    let attempts = outcome.attempts;
+
+    let response = match outcome.result {
+        Ok(r) => {
+            info!(status = %r.status(), "Response received in {:?}", start_time.elapsed());
+            r
+        },
+        Err(e) => {
+            warn!("Request failed: {}", e);
+            return LLMResult::failure(ApiError::from_request_error(&e), attempts, request.warnings.clone());
+        }
+    };
+
+    // 检查 HTTP 状态码
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        warn!("Error response: {}", error_text);
+        let error = ApiError::from_response(status, &error_text);
+        return LLMResult::failure(error, attempts, request.warnings.clone());
+    }
+
+    // 解析响应
+    let response_text = match response.text().await {
+        Ok(t) => t,
+        Err(e) => {
+            let error = ApiError::new(ErrorCode::Network, format!("获取响应失败: {}", e));
+            return LLMResult::failure(error, attempts, request.warnings.clone());
+        }
+    };
+
+    match adapter.parse_response(&response_text) {
+        Ok(output) => {
+            info!(
+                content_length = output.content.len(),
+                tool_calls = output.tool_calls.len(),
+                "{} result",
+                adapter.name()
+            );
+            LLMResult {
+                success: true,
+                // 仅返回工具调用时正文为空
+                content: if output.content.is_empty() && !output.tool_calls.is_empty() {
+                    None
+                } else {
+                    Some(output.content)
+                },
+                tool_calls: output.tool_calls,
+                structured_call: output.structured_call,
+                parsed: None,
+                thinking: output.thinking,
+                error: None,
+                attempts,
+                usage: output.usage,
+                warnings: request.warnings.clone(),
+            }
+        }
+        Err(e) => {
+            warn!("{} parse failed: {}", adapter.name(), e);
+            LLMResult::failure(e, attempts, request.warnings.clone())
+        }
+    }
+}
+
+// 通用流式对话流程：请求成功后在后台任务中解析 SSE，并通过 channel_id 推送事件
+#[tracing::instrument(name = "request", skip_all, fields(request_id = %channel_id))]
+async fn run_chat_stream(
+    app_handle: AppHandle,
+    protocol: ProviderProtocol,
+    channel_id: String,
+    mut params: LLMRequestParams,
+) -> Result<(), ApiError> {
+    let adapter = protocol.adapter();
+    info!(model = %params.model, "{} chat stream called", adapter.name());
+    params.api_key = resolve_api_key(&app_handle, params.provider_id.as_deref(), &params.api_key).await?;
+
+    validate_params(&params).map_err(ApiError::invalid_request)?;
+    let image_warnings = prepare_image_files(&mut params, protocol).await?;
+    prepare_document_files(&mut params, protocol).await;
+    let mut request = adapter
+        .build_stream_request(&params)
+        .map_err(ApiError::invalid_request)?;
+    request.warnings.splice(0..0, image_warnings);
+    debug!("Request URL: {}", url_for_log(&request.url));
+
+    // 使用共享 HTTP 客户端（流式生成可能持续较长时间）
+    let http = HttpClientManager::from_app(&app_handle);
+    let client = http.client();
+    let timeout = http.timeout(TimeoutKey::ChatStream);
+
+    // 发送阶段同样可以通过 channel_id 取消；只重试建立连接阶段，开始推送后不再重试
+    let registry = RequestRegistry::from_app(&app_handle);
+    let policy = params.retry.clone().unwrap_or_default();
+    let send = async {
+        let outcome = send_with_retry(&policy, Idempotency::Billed, adapter.name(), || {
+            let mut builder = client
+                .post(&request.url)
+                .timeout(timeout)
+                .header("Content-Type", "application/json");
+            for (name, value) in &request.headers {
+                builder = builder.header(*name, value);
+            }
+            builder.json(&request.body)
+        })
+        .await;
+        debug!("Stream request attempts: {}", outcome.attempts);
+        let response = outcome.result.map_err(|e| ApiError::from_request_error(&e))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let err_text = response.text().await.unwrap_or_default();
+            return Err(ApiError::from_response(status, &err_text));
+        }
+        Ok(response)
+    };
+
+    let response = match registry.run(Some(&channel_id), send).await {
+        Ok(result) => result?,
+        Err(_) => {
+            emit_stream_cancelled(&app_handle, &channel_id);
+            return Err(ApiError::cancelled());
+        }
+    };
+
+    // 附件提示先于正文推送
+    for warning in &request.warnings {
+        let _ = app_handle.emit::<StreamEvent>(
+            &format!("stream://{}", channel_id),
+            StreamEvent::Warning { message: warning.clone() },
+        );
+    }
+
+    // 在后台任务中解析并推送事件，不阻塞当前命令返回
+    let scope = UsageScope {
+        canvas_id: params.canvas_id.clone(),
+        model: params.model.clone(),
+    };
+    spawn_stream_pump(app_handle, adapter, channel_id, scope, false, response);
+
+    Ok(())
+}
+
+// 流式请求的用量记账归属
+pub(crate) struct UsageScope {
+    pub canvas_id: Option<String>,
+    pub model: String,
+}
+
+// 流式过程中累计的正文与用量
+#[derive(Default)]
+struct StreamTally {
+    text: String,
+    usage: TokenUsage,
+}
+
+// 读取 SSE 响应并推送类型化事件：
+// 事件推送到 stream://{channel_id}，结束时推送 stream-done://{channel_id}；
+// 可通过 cancel_request(channel_id) 中止。
+// markdown_images 为 true 时（仅 Lemon 图片生成）把正文中的 Markdown 图片作为生成结果推送
+pub(crate) fn spawn_stream_pump(
+    app_handle: AppHandle,
+    adapter: &'static dyn ChatAdapter,
+    channel_id: String,
+    scope: UsageScope,
+    markdown_images: bool,
+    response: reqwest::Response,
+) {
+    let mut stream = response.bytes_stream();
+    let registry = RequestRegistry::from_app(&app_handle);
+
+    let task = async move {
+        let event_name = format!("stream://{}", channel_id);
+        let mut tally = StreamTally::default();
+
+        let pump = async {
+            let mut parser = SseParser::new();
+
+            while let Some(chunk_result) = stream.next().await {
+                let chunk = match chunk_result {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        warn!("Stream error: {}", e);
+                        let _ = app_handle.emit::<StreamEvent>(&event_name, StreamEvent::Error { message: e.to_string() });
+                        return;
+                    }
+                };
+
+                for event in parser.feed(&chunk) {
+                    if !emit_sse_event(&app_handle, &event_name, adapter, &event, &mut tally) {
+                        return;
+                    }
+                }
+            }
+
+            // 处理未以空行结尾的最后一个事件
+            if let Some(event) = parser.finish() {
+                emit_sse_event(&app_handle, &event_name, adapter, &event, &mut tally);
+            }
+
+            // 正文中以 Markdown 形式返回的图片；普通对话回复里的图片链接不是生成结果
+            if !markdown_images {
+                return;
+            }
+            for url in extract_markdown_image_urls(&tally.text) {
+                tally.usage.images += 1;
+                let _ = app_handle.emit::<StreamEvent>(&event_name, StreamEvent::ImageUrl { url });
+            }
+        };
+
+        let cancelled = registry.run(Some(&channel_id), pump).await.is_err();
+
+        // 取消或出错前已产生的用量同样记账
+        record_usage(&app_handle, scope.canvas_id.as_deref(), adapter.name(), &scope.model, &tally.usage);
+
+        if cancelled {
+            info!("Stream cancelled");
+            emit_stream_cancelled(&app_handle, &channel_id);
+            return;
+        }
+
+        // 发送完成信号
+        let _ = app_handle.emit::<()>(&format!("stream-done://{}", channel_id), ());
+    };
+    // 后台任务沿用当前请求的日志 span
+    tauri::async_runtime::spawn(task.instrument(tracing::Span::current()));
+}
+
+// 推送取消事件，并发送完成信号让前端释放监听
+pub(crate) fn emit_stream_cancelled(app_handle: &AppHandle, channel_id: &str) {
+    let _ = app_handle.emit::<StreamEvent>(&format!("stream://{}", channel_id), StreamEvent::Cancelled);
+    let _ = app_handle.emit::<()>(&format!("stream-done://{}", channel_id), ());
+}
+
+// 解析单个 SSE 事件并推送给前端，遇到供应商错误时返回 false
+fn emit_sse_event(
+    app_handle: &AppHandle,
+    event_name: &str,
+    adapter: &dyn ChatAdapter,
+    event: &SseEvent,
+    tally: &mut StreamTally,
+) -> bool {
+    match adapter.parse_stream_event(event) {
+        Ok(events) => {
+            for stream_event in events {
+                match &stream_event {
+                    StreamEvent::TextDelta { text } => tally.text.push_str(text),
+                    StreamEvent::ImageUrl { .. } => tally.usage.images += 1,
+                    // 用量为累计值，后到的覆盖先到的
+                    StreamEvent::Usage { input_tokens, output_tokens, cached_tokens } => {
+                        if let Some(input) = input_tokens {
+                            tally.usage.input_tokens = *input;
+                        }
+                        if let Some(output) = output_tokens {
+                            tally.usage.output_tokens = *output;
+                        }
+                        if let Some(cached) = cached_tokens {
+                            tally.usage.cached_tokens = *cached;
+                        }
+                    }
+                    _ => {}
+                }
+                let _ = app_handle.emit::<StreamEvent>(event_name, stream_event);
+            }
+            true
+        }
+        Err(e) => {
+            warn!("{} stream error event: {}", adapter.name(), e);
+            let _ = app_handle.emit::<StreamEvent>(event_name, StreamEvent::Error { message: e });
+            false
+        }
+    }
+}
+
+// ==================== Tauri 命令 ====================
+
+// 统一的对话命令：按协议分发到对应适配器
+#[tauri::command]
+pub async fn chat_completion(
+    app_handle: AppHandle,
+    protocol: ProviderProtocol,
+    params: LLMRequestParams,
+) -> LLMResult {
+    run_chat(app_handle, protocol, params).await
+}
+
+// 统一的流式对话命令：类型化事件推送到 stream://{channel_id}
+#[tauri::command]
+pub async fn chat_completion_stream(
+    app_handle: AppHandle,
+    protocol: ProviderProtocol,
+    channel_id: String,
+    params: LLMRequestParams,
+) -> Result<(), ApiError> {
+    run_chat_stream(app_handle, protocol, channel_id, params).await
+}
+
+// OpenAI 兼容协议（保留旧命令，供前端兼容调用）
+#[tauri::command]
+pub async fn openai_chat_completion(app_handle: AppHandle, params: LLMRequestParams) -> LLMResult {
+    run_chat(app_handle, ProviderProtocol::Openai, params).await
+}
+
+// Claude Messages 协议
+#[tauri::command]
+pub async fn claude_chat_completion(app_handle: AppHandle, params: LLMRequestParams) -> LLMResult {
+    run_chat(app_handle, ProviderProtocol::Claude, params).await
+}
+
+// Claude Messages 流式协议
+#[tauri::command]
+pub async fn claude_chat_completion_stream(
+    app_handle: AppHandle,
+    channel_id: String,
+    params: LLMRequestParams,
+) -> Result<(), ApiError> {
+    run_chat_stream(app_handle, ProviderProtocol::Claude, channel_id, params).await
+}
+
+// Gemini generateContent 协议
+#[tauri::command]
+pub async fn gemini_generate_text(app_handle: AppHandle, params: LLMRequestParams) -> LLMResult {
+    run_chat(app_handle, ProviderProtocol::Google, params).await
+}
+
+// Gemini streamGenerateContent 流式协议
+#[tauri::command]
+pub async fn gemini_generate_text_stream(
+    app_handle: AppHandle,
+    channel_id: String,
+    params: LLMRequestParams,
+) -> Result<(), ApiError> {
+    run_chat_stream(app_handle, ProviderProtocol::Google, channel_id, params).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reasoning(effort: Option<ReasoningEffort>, budget_tokens: Option<u32>) -> ReasoningConfig {
+        ReasoningConfig { effort, budget_tokens }
+    }
+
+    #[test]
+    fn effort_maps_to_default_budget() {
+        assert_eq!(reasoning(Some(ReasoningEffort::Low), None).budget_tokens(), Some(1024));
+        assert_eq!(reasoning(Some(ReasoningEffort::Medium), None).budget_tokens(), Some(8192));
+        assert_eq!(reasoning(Some(ReasoningEffort::High), None).budget_tokens(), Some(24576));
+        assert_eq!(reasoning(None, None).budget_tokens(), None);
+    }
+
+    #[test]
+    fn budget_maps_to_effort() {
+        assert_eq!(reasoning(None, Some(0)).effort(), None);
+        assert_eq!(reasoning(None, Some(1)).effort(), Some(ReasoningEffort::Low));
+        assert_eq!(reasoning(None, Some(2048)).effort(), Some(ReasoningEffort::Low));
+        assert_eq!(reasoning(None, Some(2049)).effort(), Some(ReasoningEffort::Medium));
+        assert_eq!(reasoning(None, Some(8192)).effort(), Some(ReasoningEffort::Medium));
+        assert_eq!(reasoning(None, Some(8193)).effort(), Some(ReasoningEffort::High));
+        assert_eq!(reasoning(None, None).effort(), None);
+    }
+
+    #[test]
+    fn explicit_values_take_precedence() {
+        let config = reasoning(Some(ReasoningEffort::High), Some(512));
+        assert_eq!(config.budget_tokens(), Some(512));
+        assert_eq!(config.effort(), Some(ReasoningEffort::High));
+        // 预算为 0 表示关闭思考
+        assert_eq!(reasoning(Some(ReasoningEffort::Low), Some(0)).budget_tokens(), Some(0));
+    }
+
+    #[test]
+    fn reasoning_config_deserializes_from_camel_case() {
+        let config: ReasoningConfig = serde_json::from_str(r#"{"effort": "medium", "budgetTokens": 4096}"#).unwrap();
+        assert_eq!(config.effort, Some(ReasoningEffort::Medium));
+        assert_eq!(config.budget_tokens, Some(4096));
+        assert_eq!(ReasoningEffort::Medium.as_str(), "medium");
+    }
+
+    fn structured_params(prompt: &str) -> LLMRequestParams {
+        serde_json::from_value(serde_json::json!({
+            "baseUrl": "https://api.example.com",
+            "model": "test-model",
+            "prompt": prompt,
+            "responseJsonSchema": { "type": "object", "properties": { "age": { "type": "integer" } } },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn repair_replays_text_output_as_assistant_turn() {
+        let mut params = structured_params("生成一个人物");
+        push_repair_turns(&mut params, "{\"age\": \"x\"}".to_string(), None, &["/age: 类型错误".to_string()]);
+        let roles: Vec<ChatRole> = params.turns().iter().map(|turn| turn.role).collect();
+        assert_eq!(roles, [ChatRole::User, ChatRole::Assistant, ChatRole::User]);
+        assert!(params.prompt.contains("/age: 类型错误"));
+
+        // 修复轮没有新的输入，不再追加空的用户消息
+        push_repair_turns(&mut params, "{}".to_string(), None, &["/age: 缺失".to_string()]);
+        let roles: Vec<ChatRole> = params.turns().iter().map(|turn| turn.role).collect();
+        assert_eq!(roles, [ChatRole::User, ChatRole::Assistant, ChatRole::User, ChatRole::Assistant, ChatRole::User]);
+        assert!(params.turns().iter().all(|turn| !turn.text.is_empty()));
+    }
+
+    #[test]
+    fn repair_replays_structured_tool_call() {
+        let mut params = structured_params("生成一个人物");
+        let call = ToolCall {
+            id: "toolu_1".to_string(),
+            name: "structured_output".to_string(),
+            arguments: serde_json::json!({ "age": "x" }),
+        };
+        push_repair_turns(&mut params, "{\"age\":\"x\"}".to_string(), Some(call), &["/age: 类型错误".to_string()]);
+        assert!(params.prompt.is_empty());
+
+        let request = ClaudeAdapter.build_request(&params).unwrap();
+        let messages = request.body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"][0]["type"], "tool_use");
+        assert_eq!(messages[1]["content"][0]["id"], "toolu_1");
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][0]["type"], "tool_result");
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "toolu_1");
+    }
+}
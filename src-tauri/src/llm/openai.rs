@@ -0,0 +1,486 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{
+    collect_attachments, parse_tool_arguments, Attachment, ChatAdapter, ChatOutput, ChatRole, LLMRequestParams, ProviderRequest,
+    ToolCall, ToolChoice,
+};
+use crate::usage::TokenUsage;
+use crate::error::{ApiError, ErrorCode};
+use crate::sse::{SseEvent, StreamEvent};
+
+// ==================== OpenAI 协议结构 ====================
+
+#[derive(Debug, Serialize)]
+struct OpenAIRequest {
+    model: String,
+    messages: Vec<OpenAIMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<i32>,
+    // 推理模型不接受 max_tokens，输出上限（含推理 token）改用该字段
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<OpenAIResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<OpenAITool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    // 流式输出时要求在最后一个分块返回用量，否则无法记账
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<serde_json::Value>,
+    // 推理模型的推理强度（low / medium / high）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAIMessage {
+    role: String,
+    // 仅包含工具调用的助手消息可以没有正文
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<OpenAIContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<OpenAIToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAITool {
+    #[serde(rename = "type")]
+    tool_type: String,
+    function: OpenAIFunction,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAIFunction {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    parameters: serde_json::Value,
+}
+
+// 工具调用（请求中的历史调用与响应共用）
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAIToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    call_type: Option<String>,
+    function: OpenAIFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAIFunctionCall {
+    name: String,
+    // JSON 字符串形式的参数
+    #[serde(default)]
+    arguments: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum OpenAIContent {
+    Text(String),
+    Parts(Vec<OpenAIContentPart>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+enum OpenAIContentPart {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(rename = "image_url")]
+    ImageUrl { image_url: OpenAIImageUrl },
+    #[serde(rename = "file")]
+    File { file: OpenAIFile },
+}
+
+// 文件输入（PDF），file_data 为 data URL
+#[derive(Debug, Serialize)]
+struct OpenAIFile {
+    filename: String,
+    file_data: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAIImageUrl {
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAIResponseFormat {
+    #[serde(rename = "type")]
+    format_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_schema: Option<OpenAIJsonSchema>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAIJsonSchema {
+    name: String,
+    schema: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIResponse {
+    choices: Option<Vec<OpenAIChoice>>,
+    usage: Option<OpenAIUsage>,
+    error: Option<OpenAIError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIChoice {
+    message: Option<OpenAIMessageResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIMessageResponse {
+    content: Option<String>,
+    // 部分兼容网关（如 DeepSeek）返回的推理内容
+    reasoning_content: Option<String>,
+    tool_calls: Option<Vec<OpenAIToolCall>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIError {
+    message: String,
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+}
+
+impl OpenAIError {
+    fn to_api_error(&self) -> ApiError {
+        ApiError::new(ErrorCode::ProviderError, self.message.clone()).with_provider_type(self.error_type.clone())
+    }
+}
+
+// ==================== OpenAI 流式结构 ====================
+
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamChunk {
+    choices: Option<Vec<OpenAIStreamChoice>>,
+    usage: Option<OpenAIUsage>,
+    error: Option<OpenAIError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamChoice {
+    delta: Option<OpenAIStreamDelta>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamDelta {
+    content: Option<String>,
+    // 部分兼容网关（如 DeepSeek、Lemon）返回的推理内容
+    reasoning_content: Option<String>,
+    // 部分兼容网关以独立字段返回生成的图片
+    images: Option<Vec<OpenAIStreamImage>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamImage {
+    image_url: Option<OpenAIImageUrl>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIUsage {
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
+    prompt_tokens_details: Option<OpenAIPromptTokensDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIPromptTokensDetails {
+    cached_tokens: Option<u64>,
+}
+
+impl OpenAIUsage {
+    fn cached_tokens(&self) -> Option<u64> {
+        self.prompt_tokens_details.as_ref().and_then(|details| details.cached_tokens)
+    }
+
+    fn to_usage(&self) -> TokenUsage {
+        TokenUsage {
+            input_tokens: self.prompt_tokens.unwrap_or(0),
+            output_tokens: self.completion_tokens.unwrap_or(0),
+            cached_tokens: self.cached_tokens().unwrap_or(0),
+            ..Default::default()
+        }
+    }
+}
+
+// ==================== OpenAI 适配器 ====================
+
+pub struct OpenAIAdapter;
+
+impl ChatAdapter for OpenAIAdapter {
+    fn name(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    fn build_request(&self, params: &LLMRequestParams) -> Result<ProviderRequest, String> {
+        build_openai_request(params, false)
+    }
+
+    fn parse_response(&self, response_text: &str) -> Result<ChatOutput, ApiError> {
+        let openai_response: OpenAIResponse = serde_json::from_str(response_text)
+            .map_err(|e| ApiError::malformed(format!("解析响应失败: {}", e)))?;
+
+        // 检查 API 错误
+        if let Some(err) = openai_response.error {
+            return Err(err.to_api_error());
+        }
+
+        let usage = openai_response.usage.as_ref().map(OpenAIUsage::to_usage);
+
+        // 提取内容与工具调用
+        let message = openai_response
+            .choices
+            .and_then(|choices| choices.into_iter().next())
+            .and_then(|choice| choice.message)
+            .ok_or_else(|| ApiError::malformed("API 未返回有效内容"))?;
+
+        let tool_calls: Vec<ToolCall> = message
+            .tool_calls
+            .unwrap_or_default()
+            .into_iter()
+            .map(|call| ToolCall {
+                id: call.id,
+                arguments: parse_tool_arguments(&call.function.arguments),
+                name: call.function.name,
+            })
+            .collect();
+
+        let thinking = message.reasoning_content.filter(|t| !t.is_empty());
+        match message.content {
+            Some(content) => Ok(ChatOutput {
+                content,
+                thinking,
+                tool_calls,
+                structured_call: None,
+                usage,
+            }),
+            None if !tool_calls.is_empty() => Ok(ChatOutput {
+                content: String::new(),
+                thinking,
+                tool_calls,
+                structured_call: None,
+                usage,
+            }),
+            None => Err(ApiError::malformed("API 未返回有效内容")),
+        }
+    }
+
+    fn build_stream_request(&self, params: &LLMRequestParams) -> Result<ProviderRequest, String> {
+        build_openai_request(params, true)
+    }
+
+    fn parse_stream_event(&self, event: &SseEvent) -> Result<Vec<StreamEvent>, String> {
+        // 流结束标记
+        if event.data.trim() == "[DONE]" {
+            return Ok(Vec::new());
+        }
+
+        let chunk: OpenAIStreamChunk = match serde_json::from_str(&event.data) {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::warn!("Skip malformed OpenAI stream chunk: {}", e);
+                return Ok(Vec::new());
+            }
+        };
+
+        if let Some(err) = chunk.error {
+            return Err(err.message);
+        }
+
+        let mut events = Vec::new();
+        let choice = chunk.choices.and_then(|choices| choices.into_iter().next());
+        if let Some(choice) = choice {
+            if let Some(delta) = choice.delta {
+                if let Some(reasoning) = delta.reasoning_content.filter(|t| !t.is_empty()) {
+                    events.push(StreamEvent::ReasoningDelta { text: reasoning });
+                }
+                if let Some(content) = delta.content.filter(|t| !t.is_empty()) {
+                    events.push(StreamEvent::TextDelta { text: content });
+                }
+                for image in delta.images.unwrap_or_default() {
+                    if let Some(image_url) = image.image_url {
+                        events.push(StreamEvent::ImageUrl { url: image_url.url });
+                    }
+                }
+            }
+            if let Some(reason) = choice.finish_reason {
+                events.push(StreamEvent::Finish { reason: Some(reason) });
+            }
+        }
+
+        if let Some(usage) = chunk.usage {
+            events.push(StreamEvent::Usage {
+                input_tokens: usage.prompt_tokens,
+                output_tokens: usage.completion_tokens,
+                cached_tokens: usage.cached_tokens(),
+            });
+        }
+
+        Ok(events)
+    }
+}
+
+// OpenAI 消息角色名
+fn role_name(role: ChatRole) -> &'static str {
+    match role {
+        ChatRole::User => "user",
+        ChatRole::Assistant => "assistant",
+        ChatRole::Tool => "tool",
+    }
+}
+
+// 构建 Chat Completions 请求，stream 为 true 时开启 SSE 流式输出
+fn build_openai_request(params: &LLMRequestParams, stream: bool) -> Result<ProviderRequest, String> {
+    // 构建消息数组
+    let mut messages: Vec<OpenAIMessage> = Vec::new();
+    let mut warnings: Vec<String> = Vec::new();
+    let document_mode = params.document_mode.unwrap_or_default();
+
+    // 添加系统消息
+    if let Some(system_prompt) = &params.system_prompt {
+        if !system_prompt.is_empty() {
+            messages.push(OpenAIMessage {
+                role: "system".to_string(),
+                content: Some(OpenAIContent::Text(system_prompt.clone())),
+                tool_calls: None,
+                tool_call_id: None,
+            });
+        }
+    }
+
+    // 按顺序添加历史对话与本轮用户消息
+    for turn in params.turns() {
+        let content = match turn.role {
+            // 助手消息与工具结果只支持纯文本
+            ChatRole::Assistant if turn.text.is_empty() && !turn.tool_calls.is_empty() => None,
+            ChatRole::Assistant | ChatRole::Tool => Some(OpenAIContent::Text(turn.text.to_string())),
+            ChatRole::User if turn.files.is_empty() => Some(OpenAIContent::Text(turn.text.to_string())),
+            ChatRole::User => {
+                // 多模态消息
+                let mut parts: Vec<OpenAIContentPart> = Vec::new();
+                if !turn.text.is_empty() {
+                    parts.push(OpenAIContentPart::Text { text: turn.text.to_string() });
+                }
+                for attachment in collect_attachments(turn.files, document_mode, true, &mut warnings) {
+                    parts.push(match attachment {
+                        Attachment::Image(file) => OpenAIContentPart::ImageUrl {
+                            image_url: OpenAIImageUrl {
+                                url: format!("data:{};base64,{}", file.mime_type, file.data),
+                            },
+                        },
+                        Attachment::Document(file) => OpenAIContentPart::File {
+                            file: OpenAIFile {
+                                filename: file.file_name.clone().unwrap_or_else(|| "document.pdf".to_string()),
+                                file_data: format!("data:{};base64,{}", file.mime_type, file.data),
+                            },
+                        },
+                        Attachment::Text(text) => OpenAIContentPart::Text { text },
+                    });
+                }
+                Some(OpenAIContent::Parts(parts))
+            }
+        };
+
+        // 助手消息中的历史工具调用
+        let tool_calls = if turn.tool_calls.is_empty() {
+            None
+        } else {
+            Some(
+                turn.tool_calls
+                    .iter()
+                    .map(|call| OpenAIToolCall {
+                        id: call.id.clone(),
+                        call_type: Some("function".to_string()),
+                        function: OpenAIFunctionCall {
+                            name: call.name.clone(),
+                            arguments: call.arguments.to_string(),
+                        },
+                    })
+                    .collect(),
+            )
+        };
+
+        messages.push(OpenAIMessage {
+            role: role_name(turn.role).to_string(),
+            content,
+            tool_calls,
+            tool_call_id: turn.tool_call_id.map(|id| id.to_string()),
+        });
+    }
+
+    // 工具定义
+    let tools = params.tools.as_ref().filter(|tools| !tools.is_empty()).map(|tools| {
+        tools
+            .iter()
+            .map(|tool| OpenAITool {
+                tool_type: "function".to_string(),
+                function: OpenAIFunction {
+                    name: tool.name.clone(),
+                    description: tool.description.clone(),
+                    parameters: tool.parameters.clone(),
+                },
+            })
+            .collect()
+    });
+
+    let tool_choice = params.tool_choice.as_ref().map(|choice| match choice {
+        ToolChoice::Auto => json!("auto"),
+        ToolChoice::None => json!("none"),
+        ToolChoice::Required => json!("required"),
+        ToolChoice::Tool { name } => json!({ "type": "function", "function": { "name": name } }),
+    });
+
+    // 构建响应格式
+    let response_format = params.response_json_schema.as_ref().map(|schema| {
+        OpenAIResponseFormat {
+            format_type: "json_schema".to_string(),
+            json_schema: Some(OpenAIJsonSchema {
+                name: "response".to_string(),
+                schema: schema.clone(),
+            }),
+        }
+    });
+
+    // 推理模型只支持默认 temperature，且输出上限使用 max_completion_tokens
+    let reasoning_effort = params.reasoning.as_ref().and_then(|r| r.effort());
+    if reasoning_effort.is_some() && params.temperature.is_some() {
+        warnings.push("启用推理时不支持 temperature，已忽略".to_string());
+    }
+
+    // 构建请求体
+    let request_body = OpenAIRequest {
+        model: params.model.clone(),
+        messages,
+        temperature: params.temperature.filter(|_| reasoning_effort.is_none()),
+        max_tokens: params.max_tokens.filter(|_| reasoning_effort.is_none()),
+        max_completion_tokens: params.max_tokens.filter(|_| reasoning_effort.is_some()),
+        response_format,
+        tools,
+        tool_choice,
+        stream: if stream { Some(true) } else { None },
+        stream_options: if stream { Some(json!({ "include_usage": true })) } else { None },
+        reasoning_effort: reasoning_effort.map(|effort| effort.as_str()),
+    };
+
+    let body = serde_json::to_value(&request_body)
+        .map_err(|e| format!("序列化请求失败: {}", e))?;
+
+    Ok(ProviderRequest {
+        url: format!("{}/v1/chat/completions", params.base_url.trim_end_matches('/')),
+        headers: vec![("Authorization", format!("Bearer {}", params.api_key))],
+        body,
+        warnings,
+    })
+}
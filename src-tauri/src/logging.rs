@@ -0,0 +1,403 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{LazyLock, OnceLock};
+use tauri::{AppHandle, Manager};
+use tracing::Level;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::{self, MakeWriter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::error::ApiError;
+
+// 日志文件按天滚动：logs/nextlemon.2026-01-01.log，保留最近 7 个
+const LOG_FILE_PREFIX: &str = "nextlemon";
+const LOG_FILE_SUFFIX: &str = "log";
+const MAX_LOG_FILES: usize = 7;
+
+// 各模块的日志级别，可通过环境变量覆盖（语法同 RUST_LOG），
+// 如 NEXTLEMON_LOG=info,nextcreator_lib::http=debug
+const LOG_FILTER_ENV: &str = "NEXTLEMON_LOG";
+const DEFAULT_LOG_FILTER: &str = "warn,nextcreator_lib=info";
+
+// 响应体等长文本在日志中最多保留的字符数
+const LOG_PREVIEW_CHARS: usize = 1000;
+
+// get_logs 默认返回的条数
+const DEFAULT_LOG_LIMIT: usize = 500;
+
+// 后台写入线程的守卫，需保持存活才能在退出前刷新缓冲
+static LOG_GUARD: OnceLock<WorkerGuard> = OnceLock::new();
+
+// ==================== 初始化 ====================
+
+// 日志目录：app_data_dir/logs
+fn get_log_dir(app: &AppHandle) -> Result<PathBuf, ApiError> {
+    let log_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| ApiError::storage(format!("无法获取应用数据目录: {}", e)))?
+        .join("logs");
+    if !log_dir.exists() {
+        fs::create_dir_all(&log_dir).map_err(|e| ApiError::storage(format!("创建日志目录失败: {}", e)))?;
+    }
+    Ok(log_dir)
+}
+
+fn current_filter() -> String {
+    std::env::var(LOG_FILTER_ENV).unwrap_or_else(|_| DEFAULT_LOG_FILTER.to_string())
+}
+
+/// 初始化日志：控制台输出可读文本，日志文件按行写入 JSON，两者都经过脱敏
+///
+/// 在 setup 中调用一次；日志文件不可用时只输出到控制台。
+pub fn init_logging(app: &AppHandle) {
+    let filter = EnvFilter::try_new(current_filter()).unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+
+    // 控制台不使用颜色，避免转义序列打断脱敏匹配
+    let console_layer = fmt::layer()
+        .with_ansi(false)
+        .with_writer(Redacting(io::stdout));
+
+    let file_layer = match open_log_appender(app) {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let _ = LOG_GUARD.set(guard);
+            Some(
+                fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(false)
+                    .with_writer(Redacting(writer)),
+            )
+        }
+        Err(e) => {
+            eprintln!("[Rust] Failed to open log file: {}", e);
+            None
+        }
+    };
+
+    let _ = tracing_subscriber::registry()
+        .with(filter)
+        .with(console_layer)
+        .with(file_layer)
+        .try_init();
+}
+
+fn open_log_appender(app: &AppHandle) -> Result<RollingFileAppender, ApiError> {
+    RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(get_log_dir(app)?)
+        .map_err(|e| ApiError::storage(format!("创建日志文件失败: {}", e)))
+}
+
+/// 日志中的请求 ID：优先使用前端传入的 request_id / channel_id，没有时生成短 ID
+///
+/// 配合 `#[tracing::instrument(name = "request", fields(request_id = ...))]` 使用，
+/// 请求期间的每行日志都会带上该 ID。
+pub fn request_key(request_id: Option<&str>) -> String {
+    match request_id.filter(|id| !id.is_empty()) {
+        Some(id) => id.to_string(),
+        None => uuid::Uuid::new_v4().simple().to_string()[..8].to_string(),
+    }
+}
+
+// ==================== 脱敏 ====================
+
+// 大段 base64 数据（图片、视频、PDF 等）
+static BASE64_REDACTIONS: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    compile_redactions(&[
+        (r"data:([\w.+-]+/[\w.+-]+);base64,[A-Za-z0-9+/=]+", "data:$1;base64,<omitted>"),
+        (r"[A-Za-z0-9+/]{256,}={0,2}", "<base64 omitted>"),
+    ])
+});
+
+// 各种形式的 Key
+static SECRET_REDACTIONS: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    compile_redactions(&[
+        // Authorization: Bearer xxx
+        (r"(?i)(bearer\s+)[A-Za-z0-9._~+/=-]+", "${1}***"),
+        // api_key=xxx、"x-api-key": "xxx"（含 JSON 转义的引号）
+        (
+            r#"(?i)((?:api[_-]?key|x-goog-api-key|access[_-]?token|secret)(?:\\?["'])?\s*[:=]\s*(?:\\?["'])?)[^"'\s,&}\\]+"#,
+            "${1}***",
+        ),
+        // URL 查询参数 ?key=xxx
+        (r"([?&]key=)[^&\s\\]+", "${1}***"),
+        // 出现在其他位置的常见 Key 格式
+        (r"\bsk-[A-Za-z0-9_-]{8,}", "sk-***"),
+        (r"\bAIza[0-9A-Za-z_-]{20,}", "AIza***"),
+    ])
+});
+
+fn compile_redactions(rules: &[(&str, &'static str)]) -> Vec<(Regex, &'static str)> {
+    rules
+        .iter()
+        .map(|(pattern, replacement)| (Regex::new(pattern).expect("invalid redaction pattern"), *replacement))
+        .collect()
+}
+
+fn apply_redactions<'a>(text: &'a str, rules: &[(Regex, &'static str)]) -> Cow<'a, str> {
+    let mut result = Cow::Borrowed(text);
+    for (pattern, replacement) in rules {
+        let replaced = pattern.replace_all(&result, *replacement).into_owned();
+        if replaced != result {
+            result = Cow::Owned(replaced);
+        }
+    }
+    result
+}
+
+/// 隐藏文本中的 API Key 与 base64 数据
+pub fn redact(text: &str) -> Cow<'_, str> {
+    // 先去掉大段 base64，再隐藏 Key
+    match apply_redactions(text, &BASE64_REDACTIONS) {
+        Cow::Borrowed(text) => apply_redactions(text, &SECRET_REDACTIONS),
+        Cow::Owned(text) => Cow::Owned(apply_redactions(&text, &SECRET_REDACTIONS).into_owned()),
+    }
+}
+
+/// 只隐藏 API Key，保留 base64 数据（用于需要原样回放的内容）
+pub fn redact_secrets(text: &str) -> Cow<'_, str> {
+    apply_redactions(text, &SECRET_REDACTIONS)
+}
+
+/// 日志用的长文本预览：截取开头一段并脱敏，超长时注明原始长度
+///
+/// 先截取再脱敏，避免对整个响应体（可能含数 MB 的 base64 图片）做正则替换。
+pub fn log_preview(text: &str) -> Cow<'_, str> {
+    match text.char_indices().nth(LOG_PREVIEW_CHARS) {
+        Some((end, _)) => Cow::Owned(format!("{}…（共 {} 字节）", redact(&text[..end]), text.len())),
+        None => redact(text),
+    }
+}
+
+// 对每条日志脱敏后再写入
+struct Redacting<M>(M);
+
+struct RedactingWriter<W>(W);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter(self.0.make_writer())
+    }
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    // fmt 层每条日志格式化完成后一次性写入，因此可以按整条处理
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// ==================== 日志读取 ====================
+
+/// get_logs 查询条件
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogQuery {
+    pub limit: Option<usize>,       // 最多返回条数，默认 500
+    pub level: Option<String>,      // 最低级别：error / warn / info / debug / trace
+    pub request_id: Option<String>, // 只返回该请求的日志
+}
+
+/// 单条日志
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogEntry {
+    pub timestamp: String,
+    pub level: String,
+    pub target: String, // 模块路径，如 nextcreator_lib::gemini
+    pub message: String,
+    pub request_id: Option<String>,
+    pub fields: serde_json::Map<String, Value>, // 其他结构化字段
+}
+
+// 解析一行 JSON 日志：
+// {"timestamp", "level", "fields": {"message", ...}, "target", "span": {"request_id", ...}}
+fn parse_log_line(line: &str) -> Option<LogEntry> {
+    let mut value: Value = serde_json::from_str(line).ok()?;
+    let text = |value: &Value, key: &str| value.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+
+    let mut fields = match value.get_mut("fields").map(Value::take) {
+        Some(Value::Object(fields)) => fields,
+        _ => serde_json::Map::new(),
+    };
+    let message = match fields.remove("message") {
+        Some(Value::String(message)) => message,
+        Some(other) => other.to_string(),
+        None => String::new(),
+    };
+    let request_id = value
+        .get("span")
+        .and_then(|span| span.get("request_id"))
+        .and_then(|id| id.as_str())
+        .map(|id| id.to_string());
+
+    Some(LogEntry {
+        timestamp: text(&value, "timestamp"),
+        level: text(&value, "level"),
+        target: text(&value, "target"),
+        message,
+        request_id,
+        fields,
+    })
+}
+
+// 日志文件按文件名（含日期）从新到旧排列
+fn list_log_files(dir: &Path) -> Result<Vec<PathBuf>, ApiError> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| ApiError::storage(format!("读取日志目录失败: {}", e)))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(LOG_FILE_PREFIX) && name.ends_with(LOG_FILE_SUFFIX))
+        })
+        .collect();
+    files.sort();
+    files.reverse();
+    Ok(files)
+}
+
+/// 读取最近的日志（按时间正序返回）
+#[tauri::command]
+pub fn get_logs(app: AppHandle, query: Option<LogQuery>) -> Result<Vec<LogEntry>, ApiError> {
+    let query = query.unwrap_or_default();
+    let limit = query.limit.unwrap_or(DEFAULT_LOG_LIMIT);
+    let min_level = match query.level.as_deref() {
+        Some(level) => Some(Level::from_str(level).map_err(|_| ApiError::invalid_request(format!("无效的日志级别: {}", level)))?),
+        None => None,
+    };
+
+    let mut entries = Vec::new();
+    'files: for path in list_log_files(&get_log_dir(&app)?)? {
+        let content = fs::read_to_string(&path).map_err(|e| ApiError::storage(format!("读取日志失败: {}", e)))?;
+        for line in content.lines().rev() {
+            let Some(entry) = parse_log_line(line) else {
+                continue;
+            };
+            // tracing 中越详细的级别越「大」
+            if min_level.is_some_and(|min| Level::from_str(&entry.level).is_ok_and(|level| level > min)) {
+                continue;
+            }
+            if query.request_id.as_ref().is_some_and(|id| entry.request_id.as_ref() != Some(id)) {
+                continue;
+            }
+            entries.push(entry);
+            if entries.len() >= limit {
+                break 'files;
+            }
+        }
+    }
+
+    entries.reverse();
+    Ok(entries)
+}
+
+/// 导出诊断信息（应用版本、系统信息与全部日志）到指定文件，供用户附在问题反馈中
+#[tauri::command]
+pub fn export_diagnostics(app: AppHandle, output_path: String) -> Result<String, ApiError> {
+    tracing::info!(path = %output_path, "export_diagnostics called");
+
+    let mut report = String::new();
+    let _ = writeln!(report, "NextLemon diagnostics");
+    let _ = writeln!(report, "generated_at: {}", chrono::Local::now().to_rfc3339());
+    let _ = writeln!(report, "app_version: {}", app.package_info().version);
+    let _ = writeln!(report, "os: {} ({})", std::env::consts::OS, std::env::consts::ARCH);
+    let _ = writeln!(report, "log_filter: {}", current_filter());
+
+    // 旧日志在前，便于按时间阅读
+    let mut files = list_log_files(&get_log_dir(&app)?)?;
+    files.reverse();
+    for path in files {
+        let content = fs::read_to_string(&path).map_err(|e| ApiError::storage(format!("读取日志失败: {}", e)))?;
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        let _ = writeln!(report, "\n==================== {} ====================", name);
+        // 写入时已脱敏，这里再处理一次以防规则更新前写入的旧日志
+        report.push_str(&redact(&content));
+    }
+
+    fs::write(&output_path, report).map_err(|e| ApiError::storage(format!("写入诊断文件失败: {}", e)))?;
+    Ok(output_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_preview_truncates_and_redacts() {
+        assert_eq!(log_preview(r#"{"api_key": "k-123"}"#), r#"{"api_key": "***"}"#);
+
+        let body = format!("Bearer abc {}", "中".repeat(LOG_PREVIEW_CHARS));
+        let preview = log_preview(&body);
+        assert!(preview.starts_with("Bearer ***"));
+        assert!(preview.ends_with(&format!("…（共 {} 字节）", body.len())));
+        assert!(preview.chars().count() < LOG_PREVIEW_CHARS + 20);
+    }
+
+    #[test]
+    fn redacts_bearer_token() {
+        assert_eq!(redact("Authorization: Bearer abc.DEF-123"), "Authorization: Bearer ***");
+    }
+
+    #[test]
+    fn redacts_key_fields() {
+        assert_eq!(redact(r#"{"api_key": "k-123", "model": "x"}"#), r#"{"api_key": "***", "model": "x"}"#);
+        assert_eq!(redact("x-goog-api-key=abc&alt=sse"), "x-goog-api-key=***&alt=sse");
+        // 日志中 JSON 再次转义后的引号
+        assert_eq!(redact(r#"{\"apiKey\":\"k-123\"}"#), r#"{\"apiKey\":\"***\"}"#);
+    }
+
+    #[test]
+    fn redacts_url_query_key() {
+        assert_eq!(
+            redact("https://host/v1beta/models?key=AIzaXYZ&alt=sse"),
+            "https://host/v1beta/models?key=***&alt=sse"
+        );
+    }
+
+    #[test]
+    fn redacts_bare_key_formats() {
+        assert_eq!(redact("using sk-proj_ABCDEFGH123 now"), "using sk-*** now");
+        assert_eq!(redact("token AIzaSyA1234567890abcdefghij"), "token AIza***");
+        // 过短的片段不是 Key
+        assert_eq!(redact("task-sk-1"), "task-sk-1");
+    }
+
+    #[test]
+    fn omits_base64_payloads() {
+        let data_url = format!("data:image/png;base64,{}", "A".repeat(64));
+        assert_eq!(redact(&data_url), "data:image/png;base64,<omitted>");
+        let blob = "Qk0".repeat(100);
+        assert_eq!(redact(&format!("x {} y", blob)), "x <base64 omitted> y");
+        // 只隐藏 Key 时保留 base64
+        assert_eq!(redact_secrets(&data_url), data_url);
+    }
+
+    #[test]
+    fn leaves_plain_text_borrowed() {
+        assert!(matches!(redact("nothing to hide"), Cow::Borrowed(_)));
+    }
+}
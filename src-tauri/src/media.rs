@@ -0,0 +1,442 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType as PngFilter, PngEncoder};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader};
+use std::collections::VecDeque;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Cursor;
+use std::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::error::ApiError;
+
+// 各供应商普遍接受的图片格式，其他格式（GIF、BMP、TIFF 等）上传前统一转为 PNG
+const ACCEPTED_FORMATS: [ImageFormat; 3] = [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::WebP];
+
+// 重新编码为 JPEG 时的初始质量与下限，低于下限后改为继续缩小尺寸
+const JPEG_QUALITY_START: u8 = 90;
+const JPEG_QUALITY_MIN: u8 = 60;
+// 为满足字节预算逐步缩小时，短边不低于该值
+const MIN_SHORT_EDGE: u32 = 256;
+// 压缩结果缓存的总字节上限
+const FITTED_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+// ==================== 供应商限制 ====================
+
+/// 图片的目标接口
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageTarget {
+    Gemini,       // Gemini generateContent（图片生成与对话）
+    OpenAIChat,   // OpenAI 兼容的 Chat Completions（含 Lemon 流式生图）
+    Claude,       // Claude Messages
+    OpenAIImages, // OpenAI Images API 编辑接口
+    Video,        // 视频任务的参考图片
+}
+
+// 单张图片与单次请求的限制
+#[derive(Debug, Clone, Copy)]
+struct ImageLimits {
+    max_pixels: u64,  // 宽 × 高上限，超出时等比缩小（供应商自身也会缩放，多传的像素只浪费带宽）
+    max_bytes: usize,       // 单张图片编码后的字节预算
+    max_total_bytes: usize, // 单次请求所有图片合计的字节预算（base64 之前）
+    max_images: usize,      // 本轮输入图片的张数上限（对话历史中的图片不计入）
+    resizable: bool, // 视频参考图需与输出尺寸一致，只能重新压缩不能缩放
+}
+
+impl ImageTarget {
+    fn limits(self) -> ImageLimits {
+        match self {
+            // 内联数据整体上限 20 MB（按 base64 膨胀 4/3 并预留提示词空间，原始数据合计不超过 14 MB）；
+            // gemini-3-pro-image 最多 14 张参考图
+            ImageTarget::Gemini => ImageLimits {
+                max_pixels: 3072 * 3072,
+                max_bytes: 4 * 1024 * 1024,
+                max_total_bytes: 14 * 1024 * 1024,
+                max_images: 14,
+                resizable: true,
+            },
+            // 服务端会缩放到 2048×2048 以内；单张上限 20 MB
+            ImageTarget::OpenAIChat => ImageLimits {
+                max_pixels: 2048 * 2048,
+                max_bytes: 5 * 1024 * 1024,
+                max_total_bytes: usize::MAX,
+                max_images: 20,
+                resizable: true,
+            },
+            // 超过约 1.15 百万像素会被服务端缩小；单张上限 5 MB（base64 之前）；请求体整体上限 32 MB
+            ImageTarget::Claude => ImageLimits {
+                max_pixels: 1_150_000,
+                max_bytes: 5 * 1024 * 1024 - 1024,
+                max_total_bytes: 22 * 1024 * 1024,
+                max_images: 100,
+                resizable: true,
+            },
+            // gpt-image 系列最多 16 张输入图片，单张上限 50 MB
+            ImageTarget::OpenAIImages => ImageLimits {
+                max_pixels: 2048 * 2048,
+                max_bytes: 20 * 1024 * 1024,
+                max_total_bytes: usize::MAX,
+                max_images: 16,
+                resizable: true,
+            },
+            ImageTarget::Video => ImageLimits {
+                max_pixels: u64::MAX,
+                max_bytes: 10 * 1024 * 1024,
+                max_total_bytes: 10 * 1024 * 1024,
+                max_images: 1,
+                resizable: false,
+            },
+        }
+    }
+}
+
+// ==================== 输入图片 ====================
+
+/// 解码并按实际内容识别格式后的输入图片
+#[derive(Clone)]
+pub struct InputImage {
+    pub bytes: Vec<u8>,
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl InputImage {
+    /// 解析 base64 或 data URL，按文件内容识别格式，必要时转换为可接受的格式
+    ///
+    /// data URL 中声明的类型不可信，只使用数据部分；无法识别或已损坏的数据直接报错。
+    pub fn decode(data: &str, label: &str) -> Result<Self, ApiError> {
+        let payload = match data.strip_prefix("data:") {
+            Some(rest) => rest
+                .split_once(";base64,")
+                .map(|(_, payload)| payload)
+                .ok_or_else(|| ApiError::invalid_request(format!("{}不是 base64 编码的 data URL", label)))?,
+            None => data,
+        };
+        let bytes = BASE64
+            .decode(payload.trim())
+            .map_err(|e| ApiError::invalid_request(format!("{}不是有效的 base64 数据: {}", label, e)))?;
+
+        let format = image::guess_format(&bytes)
+            .map_err(|_| ApiError::invalid_request(format!("{}无法识别的图片格式", label)))?;
+
+        if ACCEPTED_FORMATS.contains(&format) {
+            // 只读取文件头校验尺寸，避免把损坏的数据原样上传
+            let (width, height) = ImageReader::with_format(Cursor::new(&bytes), format)
+                .into_dimensions()
+                .map_err(|e| ApiError::invalid_request(format!("{}已损坏，无法解析: {}", label, e)))?;
+            Ok(Self {
+                bytes,
+                format,
+                width,
+                height,
+            })
+        } else {
+            let decoded = load(&bytes, format, label)?;
+            debug!("Converted {} from {:?} to PNG", label, format);
+            Self::encode(&decoded, ImageFormat::Png, label)
+        }
+    }
+
+    /// 转为 PNG（编辑蒙版等只接受 PNG 的场景）
+    pub fn into_png(self, label: &str) -> Result<Self, ApiError> {
+        if self.format == ImageFormat::Png {
+            return Ok(self);
+        }
+        Self::encode(&load(&self.bytes, self.format, label)?, ImageFormat::Png, label)
+    }
+
+    /// 缩放到指定尺寸（如蒙版需与缩小后的原图一致），输出 PNG
+    pub fn resize_exact(self, width: u32, height: u32, label: &str) -> Result<Self, ApiError> {
+        if self.width == width && self.height == height {
+            return self.into_png(label);
+        }
+        let resized = load(&self.bytes, self.format, label)?.resize_exact(width, height, FilterType::Lanczos3);
+        Self::encode(&resized, ImageFormat::Png, label)
+    }
+
+    // 编码为 PNG（最高压缩率）或 JPEG（初始质量）
+    fn encode(image: &DynamicImage, format: ImageFormat, label: &str) -> Result<Self, ApiError> {
+        let bytes = match format {
+            ImageFormat::Jpeg => encode_jpeg(image, JPEG_QUALITY_START, label)?,
+            _ => encode_png(image, label)?,
+        };
+        Ok(Self {
+            bytes,
+            format: if format == ImageFormat::Jpeg { format } else { ImageFormat::Png },
+            width: image.width(),
+            height: image.height(),
+        })
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        self.format.to_mime_type()
+    }
+
+    // 上传时使用的文件扩展名
+    pub fn extension(&self) -> &'static str {
+        self.format.extensions_str().first().copied().unwrap_or("png")
+    }
+
+    pub fn to_base64(&self) -> String {
+        BASE64.encode(&self.bytes)
+    }
+
+    pub fn to_data_url(&self) -> String {
+        format!("data:{};base64,{}", self.mime_type(), self.to_base64())
+    }
+}
+
+fn load(bytes: &[u8], format: ImageFormat, label: &str) -> Result<DynamicImage, ApiError> {
+    // 动图只保留第一帧
+    image::load_from_memory_with_format(bytes, format)
+        .map_err(|e| ApiError::invalid_request(format!("{}已损坏，无法解析: {}", label, e)))
+}
+
+fn encode_png(image: &DynamicImage, label: &str) -> Result<Vec<u8>, ApiError> {
+    let mut bytes = Vec::new();
+    let encoder = PngEncoder::new_with_quality(&mut bytes, CompressionType::Best, PngFilter::Adaptive);
+    image
+        .write_with_encoder(encoder)
+        .map_err(|e| ApiError::invalid_request(format!("{}编码为 PNG 失败: {}", label, e)))?;
+    Ok(bytes)
+}
+
+fn encode_jpeg(image: &DynamicImage, quality: u8, label: &str) -> Result<Vec<u8>, ApiError> {
+    let mut bytes = Vec::new();
+    // JPEG 不支持透明通道
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))
+        .map_err(|e| ApiError::invalid_request(format!("{}编码为 JPEG 失败: {}", label, e)))?;
+    Ok(bytes)
+}
+
+// 是否存在真正透明的像素（很多 PNG 带 alpha 通道但完全不透明）
+fn has_transparency(image: &DynamicImage) -> bool {
+    image.color().has_alpha() && image.to_rgba8().pixels().any(|pixel| pixel.0[3] < 255)
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{} KB", bytes.div_ceil(1024))
+    }
+}
+
+// ==================== 预处理 ====================
+
+/// 预处理后的输入图片，warnings 描述了被缩放或重新压缩的图片
+pub struct PreparedImages {
+    pub images: Vec<InputImage>,
+    pub warnings: Vec<String>,
+}
+
+/// 解码前端传入的全部输入图片，并按目标接口的限制缩放、重新压缩
+///
+/// 解码与编码在阻塞线程池中执行，不占用异步运行时。
+pub async fn prepare_input_images(images: Vec<String>, target: ImageTarget) -> Result<PreparedImages, ApiError> {
+    prepare_conversation_images(Vec::new(), images, target).await
+}
+
+/// 同 prepare_input_images，但对话历史中的图片不计入张数上限，只参与合计字节预算
+///
+/// 返回结果按 history 在前、current 在后的顺序排列。
+pub async fn prepare_conversation_images(
+    history: Vec<String>,
+    current: Vec<String>,
+    target: ImageTarget,
+) -> Result<PreparedImages, ApiError> {
+    if history.is_empty() && current.is_empty() {
+        return Ok(PreparedImages {
+            images: Vec::new(),
+            warnings: Vec::new(),
+        });
+    }
+    let counted = current.len();
+    let mut images = history;
+    images.extend(current);
+    tauri::async_runtime::spawn_blocking(move || prepare_blocking(&images, counted, target))
+        .await
+        .map_err(|e| ApiError::invalid_request(format!("图片预处理失败: {}", e)))?
+}
+
+/// 解码编辑蒙版并缩放到输入图片的尺寸，输出 PNG
+pub async fn prepare_mask(mask: String, width: u32, height: u32) -> Result<InputImage, ApiError> {
+    tauri::async_runtime::spawn_blocking(move || InputImage::decode(&mask, "蒙版")?.resize_exact(width, height, "蒙版"))
+        .await
+        .map_err(|e| ApiError::invalid_request(format!("蒙版预处理失败: {}", e)))?
+}
+
+// counted：末尾属于本轮输入、计入张数上限的图片数
+fn prepare_blocking(images: &[String], counted: usize, target: ImageTarget) -> Result<PreparedImages, ApiError> {
+    let limits = target.limits();
+    if counted > limits.max_images {
+        return Err(ApiError::invalid_request(format!(
+            "输入图片过多：最多支持 {} 张，当前 {} 张",
+            limits.max_images, counted
+        )));
+    }
+
+    // 合计预算按从小到大的顺序分配：每张最多分到剩余预算的平均值，小图用不完的份额留给后面的大图
+    // （base64 长度与解码后的字节数成正比，排序时不必先解码）
+    let mut order: Vec<usize> = (0..images.len()).collect();
+    order.sort_by_key(|&index| images[index].len());
+    let mut fitted: Vec<Option<(InputImage, Option<String>)>> = (0..images.len()).map(|_| None).collect();
+    let mut remaining = limits.max_total_bytes;
+    for (position, &index) in order.iter().enumerate() {
+        let label = format!("第 {} 张输入图片", index + 1);
+        let budget = limits.max_bytes.min(remaining / (order.len() - position));
+        let key = cache_key(&images[index], target);
+        let (image, detail) = match cached_fit(key, budget) {
+            Some(hit) => hit,
+            None => {
+                let image = InputImage::decode(&images[index], &label)?;
+                let (image, detail) = fit_to_limits(image, &limits, budget, &label)?;
+                if let Some(detail) = &detail {
+                    cache_fit(key, &image, detail);
+                }
+                (image, detail)
+            }
+        };
+        remaining = remaining.saturating_sub(image.bytes.len());
+        fitted[index] = Some((image, detail.map(|detail| format!("{}{}", label, detail))));
+    }
+
+    let mut prepared = PreparedImages {
+        images: Vec::with_capacity(images.len()),
+        warnings: Vec::new(),
+    };
+    for (image, warning) in fitted.into_iter().flatten() {
+        if let Some(warning) = warning {
+            info!("{}", warning);
+            prepared.warnings.push(warning);
+        }
+        prepared.images.push(image);
+    }
+    Ok(prepared)
+}
+
+// ==================== 压缩结果缓存 ====================
+//
+// 对话历史中的图片每次请求都会原样重新传入，缓存最近的压缩结果，避免重复解码与压缩。
+// 只缓存实际被调整过的图片；未调整的图片只需读取文件头，开销很小。
+
+struct FittedEntry {
+    key: u64,
+    image: InputImage,
+    detail: String, // 调整说明（不含图片序号）
+}
+
+static FITTED_CACHE: Mutex<VecDeque<FittedEntry>> = Mutex::new(VecDeque::new());
+
+fn cache_key(data: &str, target: ImageTarget) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    target.hash(&mut hasher);
+    hasher.finish()
+}
+
+// 缓存的结果不超过本次预算时直接复用
+fn cached_fit(key: u64, budget: usize) -> Option<(InputImage, Option<String>)> {
+    let cache = FITTED_CACHE.lock().unwrap();
+    let entry = cache
+        .iter()
+        .find(|entry| entry.key == key && entry.image.bytes.len() <= budget)?;
+    debug!(bytes = entry.image.bytes.len(), "Input image served from cache");
+    Some((entry.image.clone(), Some(entry.detail.clone())))
+}
+
+fn cache_fit(key: u64, image: &InputImage, detail: &str) {
+    if image.bytes.len() > FITTED_CACHE_BYTES {
+        return;
+    }
+    let mut cache = FITTED_CACHE.lock().unwrap();
+    cache.retain(|entry| entry.key != key);
+    cache.push_back(FittedEntry {
+        key,
+        image: image.clone(),
+        detail: detail.to_string(),
+    });
+    // 超出总量时淘汰最早的结果
+    let mut total: usize = cache.iter().map(|entry| entry.image.bytes.len()).sum();
+    while total > FITTED_CACHE_BYTES {
+        let Some(evicted) = cache.pop_front() else { break };
+        total -= evicted.image.bytes.len();
+    }
+}
+
+// 超出像素上限时等比缩小；超出字节预算 max_bytes 时依次尝试：无损 PNG -> JPEG（逐步降低质量）-> 继续缩小
+// 返回的调整说明不含 label，由调用方拼接
+fn fit_to_limits(
+    image: InputImage,
+    limits: &ImageLimits,
+    max_bytes: usize,
+    label: &str,
+) -> Result<(InputImage, Option<String>), ApiError> {
+    let pixels = image.width as u64 * image.height as u64;
+    if pixels <= limits.max_pixels && image.bytes.len() <= max_bytes {
+        return Ok((image, None));
+    }
+
+    let (original_width, original_height, original_bytes) = (image.width, image.height, image.bytes.len());
+    let mut decoded = load(&image.bytes, image.format, label)?;
+    if pixels > limits.max_pixels && limits.resizable {
+        let scale = (limits.max_pixels as f64 / pixels as f64).sqrt();
+        let width = ((image.width as f64 * scale).floor() as u32).max(1);
+        let height = ((image.height as f64 * scale).floor() as u32).max(1);
+        decoded = decoded.resize(width, height, FilterType::Lanczos3);
+    }
+
+    let transparent = has_transparency(&decoded);
+    // JPEG 原图保持 JPEG；其他格式先尝试无损 PNG
+    let mut use_jpeg = image.format == ImageFormat::Jpeg && !transparent;
+    let mut quality = JPEG_QUALITY_START;
+    let (bytes, format) = loop {
+        let (bytes, format) = if use_jpeg {
+            (encode_jpeg(&decoded, quality, label)?, ImageFormat::Jpeg)
+        } else {
+            (encode_png(&decoded, label)?, ImageFormat::Png)
+        };
+        if bytes.len() <= max_bytes {
+            break (bytes, format);
+        }
+
+        if !use_jpeg && !transparent {
+            use_jpeg = true;
+        } else if use_jpeg && quality > JPEG_QUALITY_MIN {
+            quality -= 10;
+        } else if limits.resizable && decoded.width().min(decoded.height()) > MIN_SHORT_EDGE {
+            decoded = decoded.resize(decoded.width() * 3 / 4, decoded.height() * 3 / 4, FilterType::Lanczos3);
+        } else {
+            // 预算小于单张上限时说明是所有图片合计超限
+            let scope = if max_bytes < limits.max_bytes { "（按所有输入图片合计的限制分配）" } else { "" };
+            return Err(ApiError::invalid_request(format!(
+                "{}过大（{}），压缩后仍超过 {}{} 的限制",
+                label,
+                format_bytes(original_bytes),
+                format_bytes(max_bytes),
+                scope
+            )));
+        }
+    };
+
+    let detail = format!(
+        "已从 {}×{}（{}）调整为 {}×{} {}（{}）",
+        original_width,
+        original_height,
+        format_bytes(original_bytes),
+        decoded.width(),
+        decoded.height(),
+        if format == ImageFormat::Jpeg { "JPEG" } else { "PNG" },
+        format_bytes(bytes.len())
+    );
+    let image = InputImage {
+        bytes,
+        format,
+        width: decoded.width(),
+        height: decoded.height(),
+    };
+    Ok((image, Some(detail)))
+}
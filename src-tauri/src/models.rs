@@ -0,0 +1,620 @@
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use tracing::{debug, info, warn};
+
+use crate::cassette::send_with_cassette;
+use crate::error::{ApiError, ErrorCode};
+use crate::http::{send_with_retry, HttpClientManager, Idempotency, RetryPolicy, TimeoutKey};
+use crate::llm::ProviderProtocol;
+use crate::logging::redact;
+use crate::vault::resolve_api_key;
+
+// 模型列表缓存有效期
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+// 分页拉取的最大页数，防止网关返回错误的分页标记导致死循环
+const MAX_PAGES: usize = 20;
+
+// ==================== 数据结构 ====================
+
+// 前端调用的参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListModelsParams {
+    pub protocol: ProviderProtocol,
+    pub base_url: String, // 与对话命令一致：Gemini 包含版本路径（如 /v1beta），其他协议不含 /v1
+    #[serde(default)]
+    pub api_key: String, // 未使用凭据库时由前端直接传入
+    pub provider_id: Option<String>, // 凭据库中的供应商 ID，优先于 api_key
+    #[serde(default)]
+    pub refresh: bool, // 忽略缓存重新拉取
+    pub retry: Option<RetryPolicy>,
+}
+
+/// 模型能力
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ModelCapability {
+    Text,        // 文本对话
+    ImageOutput, // 生成图片
+    Vision,      // 理解图片输入
+    Video,       // 生成视频
+}
+
+/// 归一化后的模型信息
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u64>, // 输入 token 上限（供应商未返回时为空）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u64>,
+    pub capabilities: Vec<ModelCapability>,
+}
+
+// ==================== 模型列表缓存 ====================
+
+// 拉取时间与模型列表
+type CacheEntry = (Instant, Vec<ModelInfo>);
+
+/// 按供应商缓存的模型列表（Tauri 托管状态）
+#[derive(Clone, Default)]
+pub struct ModelCache {
+    entries: Arc<Mutex<HashMap<String, CacheEntry>>>,
+}
+
+impl ModelCache {
+    /// 从 AppHandle 中取出缓存
+    pub fn from_app(app_handle: &AppHandle) -> Self {
+        app_handle.state::<ModelCache>().inner().clone()
+    }
+
+    fn get(&self, key: &str) -> Option<Vec<ModelInfo>> {
+        let entries = self.entries.lock().unwrap();
+        let (fetched_at, models) = entries.get(key)?;
+        (fetched_at.elapsed() < CACHE_TTL).then(|| models.clone())
+    }
+
+    fn insert(&self, key: String, models: Vec<ModelInfo>) {
+        self.entries.lock().unwrap().insert(key, (Instant::now(), models));
+    }
+}
+
+// 缓存只在进程内使用，DefaultHasher 即可
+fn key_fingerprint(api_key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    api_key.hash(&mut hasher);
+    hasher.finish()
+}
+
+// ==================== 各协议的响应结构 ====================
+
+// OpenAI 兼容：GET /v1/models
+#[derive(Debug, Deserialize)]
+struct OpenAIModelList {
+    #[serde(default)]
+    data: Vec<OpenAIModel>,
+}
+
+// 部分网关（如 OpenRouter）会额外返回名称、上下文长度与模态
+#[derive(Debug, Deserialize)]
+struct OpenAIModel {
+    id: String,
+    name: Option<String>,
+    context_length: Option<u64>,
+    architecture: Option<OpenAIModelArchitecture>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIModelArchitecture {
+    #[serde(default)]
+    input_modalities: Vec<String>,
+    #[serde(default)]
+    output_modalities: Vec<String>,
+}
+
+// Claude：GET /v1/models（按 after_id 分页）
+#[derive(Debug, Deserialize)]
+struct ClaudeModelList {
+    #[serde(default)]
+    data: Vec<ClaudeModel>,
+    #[serde(default)]
+    has_more: bool,
+    last_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeModel {
+    id: String,
+    display_name: Option<String>,
+}
+
+// Gemini：GET /models（按 pageToken 分页）
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModelList {
+    #[serde(default)]
+    models: Vec<GeminiModel>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModel {
+    name: String, // models/gemini-2.5-flash
+    display_name: Option<String>,
+    input_token_limit: Option<u64>,
+    output_token_limit: Option<u64>,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+// ==================== 能力推断 ====================
+
+// 根据模型 ID 推断能力（OpenAI 兼容接口不返回能力信息）
+fn infer_capabilities(id: &str) -> Vec<ModelCapability> {
+    let id = id.to_ascii_lowercase();
+    let has = |patterns: &[&str]| patterns.iter().any(|pattern| id.contains(pattern));
+
+    // 嵌入、语音、审核等非生成模型
+    if has(&["embed", "whisper", "tts", "transcribe", "moderation", "audio", "realtime", "rerank"]) {
+        return Vec::new();
+    }
+    if has(&["sora", "veo", "video", "kling", "seedance"]) {
+        return vec![ModelCapability::Video];
+    }
+    if has(&["dall-e", "gpt-image", "imagen", "flux", "seedream", "midjourney", "stable-diffusion"]) {
+        return vec![ModelCapability::ImageOutput];
+    }
+
+    let mut capabilities = vec![ModelCapability::Text];
+    if has(&[
+        "gpt-4o", "gpt-4.1", "gpt-4-turbo", "gpt-5", "o1", "o3", "o4", "claude", "gemini", "vision", "-vl", "qwen-vl",
+        "pixtral", "llava",
+    ]) {
+        capabilities.push(ModelCapability::Vision);
+    }
+    // Gemini 图片模型（如 gemini-2.5-flash-image）同时支持文本与图片输出
+    if has(&["-image", "image-preview"]) {
+        capabilities.push(ModelCapability::ImageOutput);
+    }
+    capabilities
+}
+
+// 使用网关返回的模态信息，缺失时按 ID 推断
+fn openai_capabilities(model: &OpenAIModel) -> Vec<ModelCapability> {
+    let Some(architecture) = &model.architecture else {
+        return infer_capabilities(&model.id);
+    };
+    let mut capabilities = Vec::new();
+    let outputs = &architecture.output_modalities;
+    if outputs.is_empty() || outputs.iter().any(|m| m == "text") {
+        capabilities.push(ModelCapability::Text);
+    }
+    if architecture.input_modalities.iter().any(|m| m == "image") {
+        capabilities.push(ModelCapability::Vision);
+    }
+    if outputs.iter().any(|m| m == "image") {
+        capabilities.push(ModelCapability::ImageOutput);
+    }
+    if outputs.iter().any(|m| m == "video") {
+        capabilities.push(ModelCapability::Video);
+    }
+    capabilities
+}
+
+// ==================== 拉取与归一化 ====================
+
+// 按协议添加鉴权头
+fn authorize(builder: RequestBuilder, protocol: ProviderProtocol, api_key: &str) -> RequestBuilder {
+    match protocol {
+        ProviderProtocol::Openai => builder.header("Authorization", format!("Bearer {}", api_key)),
+        ProviderProtocol::Claude => builder
+            .header("x-api-key", api_key)
+            .header("anthropic-version", "2023-06-01"),
+        ProviderProtocol::Google => builder.header("x-goog-api-key", api_key),
+    }
+}
+
+// 模型列表接口地址（base_url 约定与对话命令一致）
+fn models_url(protocol: ProviderProtocol, base_url: &str) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    match protocol {
+        ProviderProtocol::Google => format!("{}/models", base_url),
+        ProviderProtocol::Openai | ProviderProtocol::Claude => format!("{}/v1/models", base_url),
+    }
+}
+
+struct ModelFetcher<'a> {
+    http: &'a HttpClientManager,
+    params: &'a ListModelsParams,
+}
+
+impl ModelFetcher<'_> {
+    // 发送 GET 请求并解析 JSON
+    async fn get_json<T: for<'de> Deserialize<'de>>(&self, url: &str, query: &[(&str, String)]) -> Result<T, ApiError> {
+        let client = self.http.client();
+        let timeout = self.http.timeout(TimeoutKey::ModelList);
+        let api_key = &self.params.api_key;
+        let policy = self.params.retry.clone().unwrap_or_default();
+
+        let outcome = send_with_retry(&policy, Idempotency::Idempotent, "List models", || {
+            authorize(client.get(url).timeout(timeout).query(query), self.params.protocol, api_key)
+        })
+        .await;
+        let response = outcome.result.map_err(|e| ApiError::from_request_error(&e))?;
+
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(|e| ApiError::new(ErrorCode::Network, format!("获取响应失败: {}", e)))?;
+        if !status.is_success() {
+            warn!("Error response: {}", text);
+            return Err(ApiError::from_response(status, &text));
+        }
+        serde_json::from_str(&text).map_err(|e| ApiError::malformed(format!("解析模型列表失败: {}", e)))
+    }
+
+    async fn fetch(&self) -> Result<Vec<ModelInfo>, ApiError> {
+        let url = models_url(self.params.protocol, &self.params.base_url);
+        match self.params.protocol {
+            ProviderProtocol::Openai => self.fetch_openai(&url).await,
+            ProviderProtocol::Claude => self.fetch_claude(&url).await,
+            ProviderProtocol::Google => self.fetch_gemini(&url).await,
+        }
+    }
+
+    async fn fetch_openai(&self, url: &str) -> Result<Vec<ModelInfo>, ApiError> {
+        let list: OpenAIModelList = self.get_json(url, &[]).await?;
+        Ok(list
+            .data
+            .into_iter()
+            .map(|model| ModelInfo {
+                capabilities: openai_capabilities(&model),
+                display_name: model.name.clone().unwrap_or_else(|| model.id.clone()),
+                context_window: model.context_length,
+                max_output_tokens: None,
+                id: model.id,
+            })
+            .collect())
+    }
+
+    async fn fetch_claude(&self, url: &str) -> Result<Vec<ModelInfo>, ApiError> {
+        let mut models = Vec::new();
+        let mut after_id: Option<String> = None;
+        for _ in 0..MAX_PAGES {
+            let mut query = vec![("limit", "1000".to_string())];
+            if let Some(after_id) = after_id.take() {
+                query.push(("after_id", after_id));
+            }
+            let page: ClaudeModelList = self.get_json(url, &query).await?;
+            models.extend(page.data.into_iter().map(|model| ModelInfo {
+                display_name: model.display_name.unwrap_or_else(|| model.id.clone()),
+                context_window: None,
+                max_output_tokens: None,
+                // 当前所有 Claude 模型都支持图片输入
+                capabilities: vec![ModelCapability::Text, ModelCapability::Vision],
+                id: model.id,
+            }));
+            match page.last_id {
+                Some(last_id) if page.has_more => after_id = Some(last_id),
+                _ => break,
+            }
+        }
+        Ok(models)
+    }
+
+    async fn fetch_gemini(&self, url: &str) -> Result<Vec<ModelInfo>, ApiError> {
+        let mut models = Vec::new();
+        let mut page_token: Option<String> = None;
+        for _ in 0..MAX_PAGES {
+            let mut query = vec![("pageSize", "1000".to_string())];
+            if let Some(page_token) = page_token.take() {
+                query.push(("pageToken", page_token));
+            }
+            let page: GeminiModelList = self.get_json(url, &query).await?;
+            for model in page.models {
+                let id = model.name.strip_prefix("models/").unwrap_or(&model.name).to_string();
+                let methods = &model.supported_generation_methods;
+                // generateContent 为对话/生图模型，predictLongRunning 为 Veo 视频模型，predict 为 Imagen
+                let capabilities = if methods.iter().any(|m| m == "generateContent") {
+                    infer_capabilities(&id)
+                } else if methods.iter().any(|m| m == "predictLongRunning") {
+                    vec![ModelCapability::Video]
+                } else if methods.iter().any(|m| m == "predict") && id.contains("imagen") {
+                    vec![ModelCapability::ImageOutput]
+                } else {
+                    Vec::new()
+                };
+                models.push(ModelInfo {
+                    display_name: model.display_name.unwrap_or_else(|| id.clone()),
+                    context_window: model.input_token_limit,
+                    max_output_tokens: model.output_token_limit,
+                    capabilities,
+                    id,
+                });
+            }
+            match page.next_page_token.filter(|token| !token.is_empty()) {
+                Some(token) => page_token = Some(token),
+                None => break,
+            }
+        }
+        Ok(models)
+    }
+}
+
+// ==================== Tauri 命令 ====================
+
+/// 查询供应商可用的模型列表，结果按供应商与 API Key 缓存一小时
+///
+/// 嵌入、语音等无法在本应用中使用的模型会被过滤掉。
+#[tauri::command]
+pub async fn list_models(app_handle: AppHandle, mut params: ListModelsParams) -> Result<Vec<ModelInfo>, ApiError> {
+    params.api_key = resolve_api_key(&app_handle, params.provider_id.as_deref(), &params.api_key).await?;
+    // 不同 Key 可访问的模型不同，缓存键包含 Key 的指纹（不保存明文）
+    let cache_key = format!(
+        "{:?}|{}|{}|{:016x}",
+        params.protocol,
+        params.base_url.trim_end_matches('/'),
+        params.provider_id.as_deref().unwrap_or_default(),
+        key_fingerprint(&params.api_key)
+    );
+    let cache = ModelCache::from_app(&app_handle);
+    if !params.refresh {
+        if let Some(models) = cache.get(&cache_key) {
+            debug!(models = models.len(), "Model list served from cache");
+            return Ok(models);
+        }
+    }
+
+    let http = HttpClientManager::from_app(&app_handle);
+    let fetcher = ModelFetcher { http: &http, params: &params };
+
+    let mut models: Vec<ModelInfo> = fetcher
+        .fetch()
+        .await?
+        .into_iter()
+        .filter(|model| !model.capabilities.is_empty())
+        .collect();
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    info!(protocol = ?params.protocol, models = models.len(), "Model list fetched");
+
+    cache.insert(cache_key, models.clone());
+    Ok(models)
+}
+
+// ==================== 供应商连接测试 ====================
+
+/// 连接测试的目标服务
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionTarget {
+    Openai,
+    Claude,
+    Google,
+    Video, // OpenAI 兼容的视频网关（/v1/videos）
+}
+
+impl ConnectionTarget {
+    // 鉴权方式与模型接口所对应的协议
+    fn protocol(self) -> ProviderProtocol {
+        match self {
+            ConnectionTarget::Openai | ConnectionTarget::Video => ProviderProtocol::Openai,
+            ConnectionTarget::Claude => ProviderProtocol::Claude,
+            ConnectionTarget::Google => ProviderProtocol::Google,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestProviderParams {
+    pub target: ConnectionTarget,
+    pub base_url: String, // 与对应服务命令的约定一致
+    #[serde(default)]
+    pub api_key: String, // 未使用凭据库时由前端直接传入
+    pub provider_id: Option<String>, // 凭据库中的供应商 ID，优先于 api_key
+    pub model: Option<String>, // 需要确认是否存在的模型
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderTestResult {
+    pub success: bool,   // 可访问、鉴权通过，且指定的模型存在
+    pub reachable: bool, // 收到了 HTTP 响应
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authenticated: Option<bool>, // 无法判断时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_found: Option<bool>, // 未指定模型或无法判断时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_body: Option<String>, // 网关返回的错误内容（已脱敏、截断）
+}
+
+impl ProviderTestResult {
+    fn failed(error: ApiError) -> Self {
+        Self {
+            success: false,
+            reachable: false,
+            authenticated: None,
+            model_found: None,
+            latency_ms: None,
+            status: error.status,
+            message: error.message.clone(),
+            error: Some(error),
+            response_body: None,
+        }
+    }
+}
+
+// 错误响应体最多保留的字符数
+const MAX_ERROR_BODY_CHARS: usize = 2000;
+
+fn error_body(body: &str) -> Option<String> {
+    let body = body.trim();
+    (!body.is_empty()).then(|| redact(body).chars().take(MAX_ERROR_BODY_CHARS).collect())
+}
+
+// 从任意协议的模型列表响应中取出模型 ID
+fn model_ids(body: &str) -> Vec<String> {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(body) else {
+        return Vec::new();
+    };
+    let ids = value["data"].as_array().map(|data| (data, "id"));
+    let names = value["models"].as_array().map(|models| (models, "name"));
+    ids.or(names)
+        .map(|(items, field)| {
+            items
+                .iter()
+                .filter_map(|item| item[field].as_str())
+                .map(|id| id.strip_prefix("models/").unwrap_or(id).to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// 查询模型详情确认模型是否存在，无法确认时返回 None
+//
+// 很多网关没有模型详情接口，404 只有在响应体是供应商的"模型不存在"错误时才算未找到。
+async fn lookup_model(
+    client: &reqwest::Client,
+    protocol: ProviderProtocol,
+    list_url: &str,
+    model: &str,
+    api_key: &str,
+    timeout: Duration,
+) -> Option<bool> {
+    // 模型名作为单个路径段编码（可能包含 / 等字符）
+    let mut model_url = reqwest::Url::parse(list_url).ok()?;
+    model_url.path_segments_mut().ok()?.push(model);
+
+    let response = match send_with_cassette(authorize(client.get(model_url).timeout(timeout), protocol, api_key)).await {
+        Ok(response) => response,
+        Err(e) => {
+            // 详情请求失败（超时等）不能说明模型不存在
+            warn!("Model lookup failed: {}", e);
+            return None;
+        }
+    };
+    let status = response.status();
+    if status.is_success() {
+        return Some(true);
+    }
+    let body = response.text().await.unwrap_or_default();
+    if status.as_u16() == 404 && is_model_not_found(&body) {
+        return Some(false);
+    }
+    debug!(status = %status, "Model lookup inconclusive");
+    None
+}
+
+// 各协议的"模型不存在"错误：OpenAI 的 model_not_found、Claude 的 not_found_error、Gemini 的 NOT_FOUND
+fn is_model_not_found(body: &str) -> bool {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(body) else {
+        return false;
+    };
+    let error = &value["error"];
+    error["code"] == "model_not_found" || error["type"] == "not_found_error" || error["status"] == "NOT_FOUND"
+}
+
+/// 测试供应商的连通性与凭据
+///
+/// 只调用免费的模型列表/模型详情接口，不会产生费用。
+#[tauri::command]
+pub async fn test_provider_connection(app_handle: AppHandle, mut params: TestProviderParams) -> ProviderTestResult {
+    info!(target = ?params.target, base_url = %params.base_url, "Testing provider connection");
+    params.api_key = match resolve_api_key(&app_handle, params.provider_id.as_deref(), &params.api_key).await {
+        Ok(api_key) => api_key,
+        Err(e) => return ProviderTestResult::failed(e),
+    };
+
+    let http = HttpClientManager::from_app(&app_handle);
+    let client = http.client();
+    let timeout = http.timeout(TimeoutKey::HealthCheck);
+    let protocol = params.target.protocol();
+    let list_url = models_url(protocol, &params.base_url);
+
+    // 连接测试不重试，如实反映首次请求的结果
+    let start_time = Instant::now();
+    let response = match send_with_cassette(authorize(client.get(&list_url).timeout(timeout), protocol, &params.api_key)).await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Connection test failed: {}", e);
+            return ProviderTestResult::failed(ApiError::from_request_error(&e));
+        }
+    };
+    let latency_ms = start_time.elapsed().as_millis() as u64;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    debug!(status = %status, latency_ms, "Connection test response");
+
+    let mut result = ProviderTestResult {
+        success: false,
+        reachable: true,
+        authenticated: None,
+        model_found: None,
+        latency_ms: Some(latency_ms),
+        status: Some(status.as_u16()),
+        message: String::new(),
+        error: None,
+        response_body: None,
+    };
+
+    if !status.is_success() {
+        let error = ApiError::from_response(status, &body);
+        result.message = match status.as_u16() {
+            401 | 403 => {
+                result.authenticated = Some(false);
+                format!("API Key 无效或无权访问：{}", error.message)
+            }
+            404 => "服务可访问，但未找到模型列表接口，请检查 Base URL".to_string(),
+            _ => format!("服务返回错误：{}", error.message),
+        };
+        result.error = Some(error);
+        result.response_body = error_body(&body);
+        return result;
+    }
+    result.authenticated = Some(true);
+
+    let Some(model) = params.model.as_deref().map(str::trim).filter(|model| !model.is_empty()) else {
+        result.success = true;
+        result.message = format!("连接成功（{} ms）", latency_ms);
+        return result;
+    };
+
+    // 先在已获取的列表中查找，找不到时（列表可能分页或不完整）再查询模型详情
+    let found = if model_ids(&body).iter().any(|id| id == model) {
+        true
+    } else {
+        match lookup_model(&client, protocol, &list_url, model, &params.api_key, timeout).await {
+            Some(found) => found,
+            None => {
+                result.success = true;
+                result.message = format!("连接成功（{} ms），但无法确认模型 {} 是否存在", latency_ms, model);
+                return result;
+            }
+        }
+    };
+
+    result.model_found = Some(found);
+    result.success = found;
+    result.message = if found {
+        format!("连接成功（{} ms），模型 {} 可用", latency_ms, model)
+    } else {
+        format!("连接成功（{} ms），但未找到模型 {}，请检查模型名称", latency_ms, model)
+    };
+    result
+}
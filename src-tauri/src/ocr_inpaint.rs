@@ -4,6 +4,14 @@ use serde::{Deserialize, Serialize};
 use std::io::Cursor;
 use std::time::Duration;
 
+use tauri::AppHandle;
+use tracing::{debug, info};
+
+use crate::cassette::send_with_cassette;
+use crate::error::{ApiError, ErrorCode};
+use crate::http::{send_with_retry, HttpClientManager, Idempotency, RetryPolicy, TimeoutKey};
+use crate::logging::request_key;
+
 // ==================== 数据结构 ====================
 
 /// 处理 PPT 页面的请求参数
@@ -19,6 +27,8 @@ pub struct ProcessPageParams {
     /// 蒙版扩展边距（像素）
     #[serde(default = "default_mask_padding")]
     pub mask_padding: u32,
+    /// 重试策略（OCR 与背景修复请求分别按此重试）
+    pub retry: Option<RetryPolicy>,
 }
 
 fn default_mask_padding() -> u32 {
@@ -47,7 +57,9 @@ pub struct ProcessPageResult {
     /// 检测到的文本框列表
     pub text_boxes: Vec<TextBoxData>,
     /// 错误信息
-    pub error: Option<String>,
+    pub error: Option<ApiError>,
+    /// OCR 与背景修复请求的总尝试次数（含重试）
+    pub attempts: u32,
 }
 
 // ==================== OCR 服务相关结构 ====================
@@ -111,30 +123,28 @@ pub struct TestConnectionResult {
 
 /// 处理单个 PPT 页面：OCR 识别 + 背景修复
 #[tauri::command]
-pub async fn process_ppt_page(params: ProcessPageParams) -> ProcessPageResult {
-    println!("[Rust] process_ppt_page called");
-    println!("[Rust] OCR API: {}", params.ocr_api_url);
-    println!("[Rust] Inpaint API: {}", params.inpaint_api_url);
-
-    // 创建 HTTP 客户端
-    let client = match Client::builder()
-        .timeout(Duration::from_secs(300)) // 5 分钟超时
-        .build()
-    {
-        Ok(c) => c,
-        Err(e) => {
-            return ProcessPageResult {
-                success: false,
-                background_image: None,
-                text_boxes: vec![],
-                error: Some(format!("创建 HTTP 客户端失败: {}", e)),
-            }
-        }
+#[tracing::instrument(name = "request", skip_all, fields(request_id = %request_key(None)))]
+pub async fn process_ppt_page(app_handle: AppHandle, params: ProcessPageParams) -> ProcessPageResult {
+    info!("process_ppt_page called");
+    debug!(ocr_api = %params.ocr_api_url, inpaint_api = %params.inpaint_api_url, "Service endpoints");
+
+    // 使用共享 HTTP 客户端（默认 5 分钟超时）
+    let http = HttpClientManager::from_app(&app_handle);
+    let mut service = ServiceClient {
+        client: http.client(),
+        timeout: http.timeout(TimeoutKey::Ocr),
+        policy: params.retry.clone().unwrap_or_default(),
+        attempts: 0,
     };
 
     // 1. 调用 OCR 服务
-    println!("[Rust] Step 1: Calling OCR service...");
-    let ocr_result = match call_ocr_service(&client, &params.ocr_api_url, &params.image_data).await
+    debug!("Step 1: Calling OCR service...");
+    let ocr_result = match call_ocr_service(
+        &mut service,
+        &params.ocr_api_url,
+        &params.image_data,
+    )
+    .await
     {
         Ok(r) => r,
         Err(e) => {
@@ -142,15 +152,13 @@ pub async fn process_ppt_page(params: ProcessPageParams) -> ProcessPageResult {
                 success: false,
                 background_image: None,
                 text_boxes: vec![],
-                error: Some(format!("OCR 服务调用失败: {}", e)),
+                error: Some(e.context("OCR 服务调用失败")),
+                attempts: service.attempts,
             }
         }
     };
 
-    println!(
-        "[Rust] OCR detected {} text regions",
-        ocr_result.text_boxes.len()
-    );
+    info!("OCR detected {} text regions", ocr_result.text_boxes.len());
 
     // 如果没有检测到文字，直接返回原图
     if ocr_result.text_boxes.is_empty() {
@@ -159,18 +167,17 @@ pub async fn process_ppt_page(params: ProcessPageParams) -> ProcessPageResult {
             background_image: Some(params.image_data),
             text_boxes: vec![],
             error: None,
+            attempts: service.attempts,
         };
     }
 
     // 2. 创建蒙版并调用 Inpaint 服务
-    println!("[Rust] Step 2: Creating mask and calling inpaint service...");
+    debug!("Step 2: Creating mask and calling inpaint service...");
     let background_image = match call_inpaint_service(
-        &client,
+        &mut service,
         &params.inpaint_api_url,
         &params.image_data,
-        &ocr_result.text_boxes,
-        ocr_result.image_width,
-        ocr_result.image_height,
+        &ocr_result,
         params.mask_padding,
     )
     .await
@@ -181,43 +188,36 @@ pub async fn process_ppt_page(params: ProcessPageParams) -> ProcessPageResult {
                 success: false,
                 background_image: None,
                 text_boxes: ocr_result.text_boxes,
-                error: Some(format!("背景修复失败: {}", e)),
+                error: Some(e.context("背景修复失败")),
+                attempts: service.attempts,
             }
         }
     };
 
-    println!("[Rust] process_ppt_page completed successfully");
+    info!("process_ppt_page completed successfully");
 
     ProcessPageResult {
         success: true,
         background_image: Some(background_image),
         text_boxes: ocr_result.text_boxes,
         error: None,
+        attempts: service.attempts,
     }
 }
 
 /// 测试 OCR 服务连接
 #[tauri::command]
-pub async fn test_ocr_connection(params: TestConnectionParams) -> TestConnectionResult {
-    println!("[Rust] Testing OCR connection: {}", params.url);
+pub async fn test_ocr_connection(app_handle: AppHandle, params: TestConnectionParams) -> TestConnectionResult {
+    info!(url = %params.url, "Testing OCR connection");
 
-    let client = match Client::builder()
-        .timeout(Duration::from_secs(10))
-        .build()
-    {
-        Ok(c) => c,
-        Err(e) => {
-            return TestConnectionResult {
-                success: false,
-                message: format!("创建客户端失败: {}", e),
-            }
-        }
-    };
+    let http = HttpClientManager::from_app(&app_handle);
+    let client = http.client();
+    let timeout = http.timeout(TimeoutKey::HealthCheck);
 
     // 尝试访问 OCR 服务健康检查端点
     let health_url = format!("{}/", params.url.trim_end_matches('/'));
 
-    match client.get(&health_url).send().await {
+    match send_with_cassette(client.get(&health_url).timeout(timeout)).await {
         Ok(resp) => {
             if resp.status().is_success() || resp.status().as_u16() == 405 {
                 // 405 表示端点存在但方法不对，服务可用
@@ -255,26 +255,17 @@ pub async fn test_ocr_connection(params: TestConnectionParams) -> TestConnection
 
 /// 测试 IOPaint 服务连接
 #[tauri::command]
-pub async fn test_inpaint_connection(params: TestConnectionParams) -> TestConnectionResult {
-    println!("[Rust] Testing IOPaint connection: {}", params.url);
+pub async fn test_inpaint_connection(app_handle: AppHandle, params: TestConnectionParams) -> TestConnectionResult {
+    info!(url = %params.url, "Testing IOPaint connection");
 
-    let client = match Client::builder()
-        .timeout(Duration::from_secs(10))
-        .build()
-    {
-        Ok(c) => c,
-        Err(e) => {
-            return TestConnectionResult {
-                success: false,
-                message: format!("创建客户端失败: {}", e),
-            }
-        }
-    };
+    let http = HttpClientManager::from_app(&app_handle);
+    let client = http.client();
+    let timeout = http.timeout(TimeoutKey::HealthCheck);
 
     // IOPaint 健康检查
     let health_url = format!("{}/", params.url.trim_end_matches('/'));
 
-    match client.get(&health_url).send().await {
+    match send_with_cassette(client.get(&health_url).timeout(timeout)).await {
         Ok(resp) => {
             if resp.status().is_success()
                 || resp.status().as_u16() == 404
@@ -315,6 +306,15 @@ pub async fn test_inpaint_connection(params: TestConnectionParams) -> TestConnec
 
 // ==================== 内部函数 ====================
 
+/// 发送 OCR / 背景修复请求所需的客户端与重试设置
+struct ServiceClient {
+    client: Client,
+    timeout: Duration,
+    policy: RetryPolicy,
+    /// 累计尝试次数（含重试）
+    attempts: u32,
+}
+
 /// OCR 服务调用结果
 struct OcrServiceResult {
     text_boxes: Vec<TextBoxData>,
@@ -324,23 +324,21 @@ struct OcrServiceResult {
 
 /// 调用 PaddleOCR 服务
 async fn call_ocr_service(
-    client: &Client,
+    service: &mut ServiceClient,
     api_url: &str,
     image_data: &str,
-) -> Result<OcrServiceResult, String> {
+) -> Result<OcrServiceResult, ApiError> {
     // 解码图片获取尺寸
     let image_bytes = STANDARD
         .decode(image_data)
-        .map_err(|e| format!("Base64 解码失败: {}", e))?;
+        .map_err(|e| ApiError::invalid_request(format!("Base64 解码失败: {}", e)))?;
 
-    let img = image::load_from_memory(&image_bytes).map_err(|e| format!("图片解析失败: {}", e))?;
+    let img = image::load_from_memory(&image_bytes)
+        .map_err(|e| ApiError::invalid_request(format!("图片解析失败: {}", e)))?;
 
     let image_width = img.width();
     let image_height = img.height();
-    println!(
-        "[Rust] Image size: {}x{}",
-        image_width, image_height
-    );
+    debug!("Image size: {}x{}", image_width, image_height);
 
     // 构建 OCR 请求
     let ocr_url = format!("{}/predict/ocr", api_url.trim_end_matches('/'));
@@ -348,42 +346,50 @@ async fn call_ocr_service(
         images: vec![image_data.to_string()],
     };
 
-    println!("[Rust] Sending OCR request to: {}", ocr_url);
+    debug!("Sending OCR request to: {}", ocr_url);
 
-    let response = client
-        .post(&ocr_url)
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await
-        .map_err(|e| {
-            if e.is_connect() {
-                "无法连接到 OCR 服务，请检查服务是否启动".to_string()
-            } else if e.is_timeout() {
-                "OCR 请求超时".to_string()
-            } else {
-                format!("OCR 请求失败: {}", e)
-            }
-        })?;
+    let outcome = send_with_retry(&service.policy, Idempotency::Idempotent, "OCR", || {
+        service
+            .client
+            .post(&ocr_url)
+            .timeout(service.timeout)
+            .header("Content-Type", "application/json")
+            .json(&request_body)
+    })
+    .await;
+    service.attempts += outcome.attempts;
+
+    let response = outcome.result.map_err(|e| {
+        let message = if e.is_connect() {
+            "无法连接到 OCR 服务，请检查服务是否启动".to_string()
+        } else if e.is_timeout() {
+            "OCR 请求超时".to_string()
+        } else {
+            format!("OCR 请求失败: {}", e)
+        };
+        ApiError::from_request_error(&e).with_message(message)
+    })?;
 
     if !response.status().is_success() {
         let status = response.status();
         let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("OCR 服务返回错误 ({}): {}", status, error_text));
+        return Err(ApiError::from_response(status, &error_text)
+            .with_message(format!("OCR 服务返回错误 ({}): {}", status, error_text)));
     }
 
     let ocr_response: OcrResponse = response
         .json()
         .await
-        .map_err(|e| format!("解析 OCR 响应失败: {}", e))?;
+        .map_err(|e| ApiError::malformed(format!("解析 OCR 响应失败: {}", e)))?;
 
     // 检查服务状态
     if let Some(status) = &ocr_response.status {
         if status != "000" && status.to_lowercase() != "success" {
-            return Err(format!(
-                "OCR 服务错误: {}",
-                ocr_response.msg.unwrap_or_default()
-            ));
+            return Err(ApiError::new(
+                ErrorCode::ProviderError,
+                format!("OCR 服务错误: {}", ocr_response.msg.unwrap_or_default()),
+            )
+            .with_provider_type(Some(status.clone())));
         }
     }
 
@@ -456,16 +462,19 @@ async fn call_ocr_service(
 
 /// 调用 IOPaint 服务进行背景修复
 async fn call_inpaint_service(
-    client: &Client,
+    service: &mut ServiceClient,
     api_url: &str,
     image_data: &str,
-    text_boxes: &[TextBoxData],
-    image_width: u32,
-    image_height: u32,
+    ocr_result: &OcrServiceResult,
     mask_padding: u32,
-) -> Result<String, String> {
+) -> Result<String, ApiError> {
     // 创建蒙版图片
-    let mask_base64 = create_mask_image(text_boxes, image_width, image_height, mask_padding)?;
+    let mask_base64 = create_mask_image(
+        &ocr_result.text_boxes,
+        ocr_result.image_width,
+        ocr_result.image_height,
+        mask_padding,
+    )?;
 
     // 构建 IOPaint 请求
     let inpaint_url = format!("{}/api/v1/inpaint", api_url.trim_end_matches('/'));
@@ -476,39 +485,45 @@ async fn call_inpaint_service(
         hd_strategy: "Original".to_string(),
     };
 
-    println!("[Rust] Sending inpaint request to: {}", inpaint_url);
+    debug!("Sending inpaint request to: {}", inpaint_url);
 
-    let response = client
-        .post(&inpaint_url)
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await
-        .map_err(|e| {
-            if e.is_connect() {
-                "无法连接到 IOPaint 服务，请检查服务是否启动".to_string()
-            } else if e.is_timeout() {
-                "背景修复请求超时（可能需要更长时间）".to_string()
-            } else {
-                format!("背景修复请求失败: {}", e)
-            }
-        })?;
+    let outcome = send_with_retry(&service.policy, Idempotency::Idempotent, "Inpaint", || {
+        service
+            .client
+            .post(&inpaint_url)
+            .timeout(service.timeout)
+            .header("Content-Type", "application/json")
+            .json(&request_body)
+    })
+    .await;
+    service.attempts += outcome.attempts;
+
+    let response = outcome.result.map_err(|e| {
+        let message = if e.is_connect() {
+            "无法连接到 IOPaint 服务，请检查服务是否启动".to_string()
+        } else if e.is_timeout() {
+            "背景修复请求超时（可能需要更长时间）".to_string()
+        } else {
+            format!("背景修复请求失败: {}", e)
+        };
+        ApiError::from_request_error(&e).with_message(message)
+    })?;
 
     if !response.status().is_success() {
         let status = response.status();
         let error_text = response.text().await.unwrap_or_default();
-        return Err(format!(
+        return Err(ApiError::from_response(status, &error_text).with_message(format!(
             "IOPaint 服务返回错误 ({}): {}",
             status,
             &error_text[..error_text.len().min(200)]
-        ));
+        )));
     }
 
     // IOPaint 直接返回图片二进制数据
     let image_bytes = response
         .bytes()
         .await
-        .map_err(|e| format!("获取修复图片失败: {}", e))?;
+        .map_err(|e| ApiError::new(ErrorCode::Network, format!("获取修复图片失败: {}", e)))?;
 
     // 转换为 base64
     let result_base64 = STANDARD.encode(&image_bytes);
@@ -522,7 +537,7 @@ fn create_mask_image(
     width: u32,
     height: u32,
     padding: u32,
-) -> Result<String, String> {
+) -> Result<String, ApiError> {
     use image::{ImageBuffer, Luma};
 
     // 创建全黑图片（黑色 = 保留区域）
@@ -545,7 +560,7 @@ fn create_mask_image(
     // 转换为 PNG 并编码为 base64
     let mut buffer = Cursor::new(Vec::new());
     mask.write_to(&mut buffer, image::ImageFormat::Png)
-        .map_err(|e| format!("创建蒙版图片失败: {}", e))?;
+        .map_err(|e| ApiError::invalid_request(format!("创建蒙版图片失败: {}", e)))?;
 
     let mask_base64 = STANDARD.encode(buffer.into_inner());
 
@@ -0,0 +1,349 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::AppHandle;
+use tracing::{debug, info, warn};
+
+use crate::cancellation::RequestRegistry;
+use crate::cassette::send_with_cassette;
+use crate::error::{ApiError, ErrorCode};
+use crate::gemini::{CandidateResult, GeminiResult, GeneratedImage};
+use crate::http::{send_with_retry, HttpClientManager, Idempotency, RetryPolicy, TimeoutKey};
+use crate::logging::request_key;
+use crate::media::{prepare_input_images, prepare_mask, ImageTarget, InputImage};
+use crate::usage::{record_usage, TokenUsage};
+use crate::vault::resolve_api_key;
+
+// ==================== OpenAI Images API 数据结构 ====================
+
+// 前端调用的参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenAIImageParams {
+    pub base_url: String,
+    #[serde(default)]
+    pub api_key: String, // 未使用凭据库时由前端直接传入
+    pub provider_id: Option<String>, // 凭据库中的供应商 ID，优先于 api_key
+    pub model: String,
+    pub prompt: String,
+    pub input_images: Option<Vec<String>>, // base64 或 data URL；非空时调用 /v1/images/edits
+    pub mask: Option<String>,              // 编辑蒙版（PNG，透明区域为待修改部分）
+    pub n: Option<u32>,                    // 生成张数
+    pub size: Option<String>,              // 如 1024x1024、1536x1024、auto
+    pub quality: Option<String>,           // low / medium / high / auto（dall-e-3 为 standard / hd）
+    pub background: Option<String>,        // transparent / opaque / auto
+    pub output_format: Option<String>,     // png / jpeg / webp（gpt-image 系列）
+    pub response_format: Option<String>,   // b64_json / url（dall-e 系列）
+    pub request_id: Option<String>,        // 用于 cancel_request 取消请求
+    pub retry: Option<RetryPolicy>,        // 重试策略（按供应商配置，默认重试 3 次）
+    pub canvas_id: Option<String>,         // 用量记账归属的画布
+}
+
+// /v1/images/generations 请求体
+#[derive(Debug, Serialize)]
+struct ImageGenerationRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    background: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_format: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageResponse {
+    #[serde(default)]
+    data: Vec<ImageData>,
+    usage: Option<ImageUsage>,
+}
+
+// 每张图片返回 b64_json 或 url 之一
+#[derive(Debug, Deserialize)]
+struct ImageData {
+    b64_json: Option<String>,
+    url: Option<String>,
+    revised_prompt: Option<String>, // dall-e-3 改写后的提示词
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    input_tokens_details: Option<ImageInputTokensDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageInputTokensDetails {
+    #[serde(default)]
+    cached_tokens: u64,
+}
+
+// 构建 multipart 中的图片字段
+fn image_part(image: &InputImage, file_name: &str) -> reqwest::multipart::Part {
+    reqwest::multipart::Part::bytes(image.bytes.clone())
+        .file_name(format!("{}.{}", file_name, image.extension()))
+        .mime_str(image.mime_type())
+        .unwrap_or_else(|_| reqwest::multipart::Part::bytes(image.bytes.clone()))
+}
+
+// ==================== Tauri 命令 ====================
+
+/// 通过 OpenAI Images API 生成或编辑图片，返回与 gemini_generate_content 相同结构的结果
+#[tauri::command]
+#[tracing::instrument(name = "request", skip_all, fields(request_id = %request_key(params.request_id.as_deref())))]
+pub async fn openai_image_generate(app_handle: AppHandle, mut params: OpenAIImageParams) -> GeminiResult {
+    params.api_key = match resolve_api_key(&app_handle, params.provider_id.as_deref(), &params.api_key).await {
+        Ok(api_key) => api_key,
+        Err(e) => return GeminiResult::failure(e, 0),
+    };
+
+    let request_id = params.request_id.clone();
+    let canvas_id = params.canvas_id.clone();
+    let model = params.model.clone();
+
+    let result = RequestRegistry::from_app(&app_handle)
+        .run(request_id.as_deref(), generate_images(HttpClientManager::from_app(&app_handle), params))
+        .await
+        .unwrap_or_else(|_| {
+            info!("openai_image_generate cancelled");
+            GeminiResult::failure(ApiError::cancelled(), 0)
+        });
+
+    if let Some(usage) = &result.usage {
+        record_usage(&app_handle, canvas_id.as_deref(), "OpenAI Images", &model, usage);
+    }
+    result
+}
+
+async fn generate_images(http: HttpClientManager, mut params: OpenAIImageParams) -> GeminiResult {
+    let input_images = params.input_images.take().unwrap_or_default();
+    info!(
+        model = %params.model,
+        input_images = input_images.len(),
+        has_mask = params.mask.is_some(),
+        "openai_image_generate called"
+    );
+
+    // 解码并按限制缩放输入图片（每次重试都要重新构建 multipart form）
+    let prepared = match prepare_input_images(input_images, ImageTarget::OpenAIImages).await {
+        Ok(prepared) => prepared,
+        Err(e) => return GeminiResult::failure(e, 0),
+    };
+    let mut result = request_images(http, params, prepared.images).await;
+    result.warnings = prepared.warnings;
+    result
+}
+
+async fn request_images(http: HttpClientManager, mut params: OpenAIImageParams, images: Vec<InputImage>) -> GeminiResult {
+    // 蒙版必须为 PNG，且与（缩放后的）第一张输入图片尺寸一致
+    let mask = match (params.mask.take(), images.first()) {
+        (Some(mask), Some(image)) => match prepare_mask(mask, image.width, image.height).await {
+            Ok(mask) => Some(mask),
+            Err(e) => return GeminiResult::failure(e, 0),
+        },
+        (Some(_), None) => return GeminiResult::failure(ApiError::invalid_request("使用蒙版时需要提供输入图片"), 0),
+        (None, _) => None,
+    };
+
+    let is_edit = !images.is_empty();
+    let base_url = params.base_url.trim_end_matches('/');
+    let endpoint = if is_edit { "edits" } else { "generations" };
+    let url = format!("{}/v1/images/{}", base_url, endpoint);
+    debug!("Request URL: {}", url);
+
+    let client = http.client();
+    let timeout = http.timeout(TimeoutKey::OpenAIImage);
+
+    let generation_body = ImageGenerationRequest {
+        model: &params.model,
+        prompt: &params.prompt,
+        n: params.n,
+        size: params.size.as_deref(),
+        quality: params.quality.as_deref(),
+        background: params.background.as_deref(),
+        output_format: params.output_format.as_deref(),
+        response_format: params.response_format.as_deref(),
+    };
+
+    let build_edit_form = || {
+        let mut form = reqwest::multipart::Form::new()
+            .text("model", params.model.clone())
+            .text("prompt", params.prompt.clone());
+        // 多图输入使用 image[]，单图保持 image 以兼容 dall-e-2
+        let image_field = if images.len() > 1 { "image[]" } else { "image" };
+        for (index, image) in images.iter().enumerate() {
+            form = form.part(image_field, image_part(image, &format!("image_{}", index + 1)));
+        }
+        if let Some(mask) = &mask {
+            form = form.part("mask", image_part(mask, "mask"));
+        }
+        let options = [
+            ("n", params.n.map(|n| n.to_string())),
+            ("size", params.size.clone()),
+            ("quality", params.quality.clone()),
+            ("background", params.background.clone()),
+            ("output_format", params.output_format.clone()),
+            ("response_format", params.response_format.clone()),
+        ];
+        for (name, value) in options {
+            if let Some(value) = value {
+                form = form.text(name, value);
+            }
+        }
+        form
+    };
+
+    let start_time = std::time::Instant::now();
+    let policy = params.retry.clone().unwrap_or_default();
+    let outcome = send_with_retry(&policy, Idempotency::Billed, "OpenAI Images", || {
+        let builder = client
+            .post(&url)
+            .timeout(timeout)
+            .header("Authorization", format!("Bearer {}", params.api_key));
+        if is_edit {
+            builder.multipart(build_edit_form())
+        } else {
+            builder.json(&generation_body)
+        }
+    })
+    .await;
+    let attempts = outcome.attempts;
+
+    let response = match outcome.result {
+        Ok(r) => {
+            info!(status = %r.status(), "Response received in {:?}", start_time.elapsed());
+            r
+        }
+        Err(e) => {
+            warn!("Request failed: {}", e);
+            return GeminiResult::failure(ApiError::from_request_error(&e), attempts);
+        }
+    };
+
+    let status = response.status();
+    let response_text = match response.text().await {
+        Ok(t) => t,
+        Err(e) => {
+            return GeminiResult::failure(
+                ApiError::new(ErrorCode::Network, format!("获取响应失败: {}", e)),
+                attempts,
+            )
+        }
+    };
+    if !status.is_success() {
+        warn!("Error response: {}", response_text);
+        return GeminiResult::failure(ApiError::from_response(status, &response_text), attempts);
+    }
+
+    let image_response: ImageResponse = match serde_json::from_str(&response_text) {
+        Ok(r) => r,
+        Err(e) => {
+            warn!("Failed to parse JSON: {}", e);
+            return GeminiResult::failure(ApiError::malformed(format!("解析响应失败: {}", e)), attempts);
+        }
+    };
+
+    // b64_json 的格式由 output_format 决定，默认 PNG
+    let default_mime = format!("image/{}", params.output_format.as_deref().unwrap_or("png"));
+    let mut candidates: Vec<CandidateResult> = Vec::new();
+    for item in image_response.data {
+        let image = match (item.b64_json, item.url) {
+            (Some(data), _) => Some(GeneratedImage {
+                data,
+                mime_type: default_mime.clone(),
+            }),
+            (None, Some(url)) => download_image(&client, &url, timeout).await,
+            (None, None) => None,
+        };
+        candidates.push(CandidateResult {
+            images: image.into_iter().collect(),
+            text: item.revised_prompt,
+            thinking: None,
+            finish_reason: None,
+            block: None,
+        });
+    }
+
+    let images: Vec<GeneratedImage> = candidates
+        .iter()
+        .flat_map(|candidate| candidate.images.iter().cloned())
+        .collect();
+    info!(candidates = candidates.len(), images = images.len(), "OpenAI Images result");
+
+    let mut usage = image_response
+        .usage
+        .map(|usage| TokenUsage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cached_tokens: usage.input_tokens_details.map(|details| details.cached_tokens).unwrap_or(0),
+            ..Default::default()
+        })
+        .unwrap_or_default();
+    usage.images = images.len() as u64;
+    let usage = if usage.is_empty() { None } else { Some(usage) };
+
+    if images.is_empty() {
+        return GeminiResult {
+            usage,
+            candidates,
+            ..GeminiResult::failure(ApiError::malformed("API 未返回有效图片"), attempts)
+        };
+    }
+
+    GeminiResult {
+        success: true,
+        image_data: images.first().map(|image| image.data.clone()),
+        text: candidates.iter().find_map(|candidate| candidate.text.clone()),
+        thinking: None,
+        images,
+        candidates,
+        error: None,
+        block: None,
+        attempts,
+        usage,
+        warnings: Vec::new(),
+    }
+}
+
+// 下载以 url 形式返回的图片并转为 base64（链接通常只在短时间内有效）
+async fn download_image(client: &Client, url: &str, timeout: Duration) -> Option<GeneratedImage> {
+    let response = match send_with_cassette(client.get(url).timeout(timeout)).await {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            warn!("Failed to download image: HTTP {}", response.status());
+            return None;
+        }
+        Err(e) => {
+            warn!("Failed to download image: {}", e);
+            return None;
+        }
+    };
+    let mime_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| value.starts_with("image/"))
+        .unwrap_or("image/png")
+        .to_string();
+    match response.bytes().await {
+        Ok(bytes) => Some(GeneratedImage {
+            data: BASE64.encode(&bytes),
+            mime_type,
+        }),
+        Err(e) => {
+            warn!("Failed to download image: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use serde::Serialize;
+
+// ==================== SSE 解析 ====================
+//
+// 按字节缓冲网络分片，只在遇到完整行时才解码 UTF-8，
+// 避免多字节字符（如中文）被拆分到两个分片时出现乱码。
+
+/// 一个完整的 SSE 事件
+#[derive(Debug, Clone)]
+pub struct SseEvent {
+    /// `event:` 字段（未指定时为 None）
+    pub event: Option<String>,
+    /// 所有 `data:` 行以换行拼接后的内容
+    pub data: String,
+}
+
+/// SSE 增量解析器
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    scanned: usize, // buffer 中已确认不含换行符的前缀长度，新分片只需从这里开始查找
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 追加一个网络分片，返回其中已完整的事件
+    ///
+    /// 只在新到达的字节中查找换行符，已处理的行在最后一次性移出缓冲区，
+    /// 单个很长的事件分成大量分片到达时也不会反复扫描。
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        let mut start = 0;
+        while let Some(offset) = self.buffer[self.scanned..].iter().position(|b| *b == b'\n') {
+            let end = self.scanned + offset;
+            let line = String::from_utf8_lossy(&self.buffer[start..end]);
+            let line = line.strip_suffix('\r').unwrap_or(&line).to_string();
+            start = end + 1;
+            self.scanned = start;
+            if let Some(event) = self.process_line(&line) {
+                events.push(event);
+            }
+        }
+        self.buffer.drain(..start);
+        self.scanned = self.buffer.len();
+        events
+    }
+
+    /// 流结束时调用，取出缓冲区中最后一个未以空行结尾的事件
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let rest = std::mem::take(&mut self.buffer);
+            self.scanned = 0;
+            let line = String::from_utf8_lossy(&rest);
+            let line = line.trim_end_matches(['\r', '\n']).to_string();
+            if let Some(event) = self.process_line(&line) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        // 空行表示一个事件结束
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        // 冒号开头为注释（常用于心跳）
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        let data = std::mem::take(&mut self.data).join("\n");
+        Some(SseEvent { event, data })
+    }
+}
+
+// ==================== 推送给前端的流式事件 ====================
+
+/// 通过 `stream://{channel_id}` 推送给前端的类型化事件，
+/// 前端不再需要自行解析 SSE
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum StreamEvent {
+    /// 正文增量
+    TextDelta { text: String },
+    /// 推理/思考过程增量
+    ReasoningDelta { text: String },
+    /// 生成的图片地址（URL 或 data URL）
+    ImageUrl { url: String },
+    /// 生成结束原因
+    Finish { reason: Option<String> },
+    /// Token 用量
+    Usage {
+        input_tokens: Option<u64>,
+        output_tokens: Option<u64>,
+        cached_tokens: Option<u64>,
+    },
+    /// 非致命提示（如被忽略的附件）
+    Warning { message: String },
+    /// 流式过程中的错误
+    Error { message: String },
+    /// 请求被 cancel_request 取消
+    Cancelled,
+}
+
+/// 从 Markdown 文本中提取所有图片地址（`![alt](url)`）
+pub fn extract_markdown_image_urls(text: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("![") {
+        rest = &rest[start + 2..];
+        let Some(alt_end) = rest.find("](") else { break };
+        let after = &rest[alt_end + 2..];
+        let Some(url_end) = after.find(')') else { break };
+        let url = after[..url_end].trim();
+        if !url.is_empty() {
+            urls.push(url.to_string());
+        }
+        rest = &after[url_end + 1..];
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multibyte_character_split_across_chunks() {
+        let mut parser = SseParser::new();
+        let bytes = "data: 你好\n\n".as_bytes();
+        // “你”占 3 个字节，在第 2 个字节处切开
+        let split = "data: ".len() + 2;
+        assert!(parser.feed(&bytes[..split]).is_empty());
+        let events = parser.feed(&bytes[split..]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "你好");
+    }
+
+    #[test]
+    fn line_split_across_chunks() {
+        let mut parser = SseParser::new();
+        assert!(parser.feed(b"event: mess").is_empty());
+        assert!(parser.feed(b"age\r\ndata: {\"a\"").is_empty());
+        let events = parser.feed(b":1}\r\n\r\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event.as_deref(), Some("message"));
+        assert_eq!(events[0].data, "{\"a\":1}");
+    }
+
+    #[test]
+    fn multiple_data_lines_and_comments() {
+        let mut parser = SseParser::new();
+        let events = parser.feed(b": ping\ndata: a\ndata: b\n\ndata: c\n\n");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, None);
+        assert_eq!(events[0].data, "a\nb");
+        assert_eq!(events[1].data, "c");
+    }
+
+    #[test]
+    fn finish_flushes_unterminated_event() {
+        let mut parser = SseParser::new();
+        assert!(parser.feed(b"data: [DONE]").is_empty());
+        let event = parser.finish().expect("pending event");
+        assert_eq!(event.data, "[DONE]");
+        assert!(parser.finish().is_none());
+    }
+
+    #[test]
+    fn long_event_fed_byte_by_byte() {
+        let mut parser = SseParser::new();
+        let payload = "x".repeat(64 * 1024);
+        let bytes = format!("data: {}\n\ndata: end\n\n", payload).into_bytes();
+        let mut events = Vec::new();
+        for byte in &bytes {
+            events.extend(parser.feed(std::slice::from_ref(byte)));
+        }
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].data, payload);
+        assert_eq!(events[1].data, "end");
+        assert!(parser.finish().is_none());
+    }
+
+    #[test]
+    fn markdown_image_urls() {
+        let text = "前言 ![a](https://x/1.png) 中间 ![](  data:image/png;base64,AAA ) ![broken](";
+        assert_eq!(
+            extract_markdown_image_urls(text),
+            vec!["https://x/1.png".to_string(), "data:image/png;base64,AAA".to_string()]
+        );
+    }
+}
@@ -5,6 +5,8 @@ use std::path::PathBuf;
 use tauri::Manager;
 use uuid::Uuid;
 
+use crate::error::ApiError;
+
 // 图片类型枚举
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "lowercase")]
@@ -74,28 +76,28 @@ pub struct CanvasImageStats {
 }
 
 // 获取应用数据目录
-fn get_app_data_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+fn get_app_data_dir(app: &tauri::AppHandle) -> Result<PathBuf, ApiError> {
     app.path()
         .app_data_dir()
-        .map_err(|e| format!("无法获取应用数据目录: {}", e))
+        .map_err(|e| ApiError::storage(format!("无法获取应用数据目录: {}", e)))
 }
 
 // 获取图片存储目录
-fn get_images_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+fn get_images_dir(app: &tauri::AppHandle) -> Result<PathBuf, ApiError> {
     let app_data = get_app_data_dir(app)?;
     let images_dir = app_data.join("images");
     if !images_dir.exists() {
-        fs::create_dir_all(&images_dir).map_err(|e| format!("创建图片目录失败: {}", e))?;
+        fs::create_dir_all(&images_dir).map_err(|e| ApiError::storage(format!("创建图片目录失败: {}", e)))?;
     }
     Ok(images_dir)
 }
 
 // 获取缓存目录
-fn get_cache_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+fn get_cache_dir(app: &tauri::AppHandle) -> Result<PathBuf, ApiError> {
     let app_data = get_app_data_dir(app)?;
     let cache_dir = app_data.join("cache");
     if !cache_dir.exists() {
-        fs::create_dir_all(&cache_dir).map_err(|e| format!("创建缓存目录失败: {}", e))?;
+        fs::create_dir_all(&cache_dir).map_err(|e| ApiError::storage(format!("创建缓存目录失败: {}", e)))?;
     }
     Ok(cache_dir)
 }
@@ -110,14 +112,14 @@ pub fn save_image(
     prompt: Option<String>,
     input_images: Option<Vec<InputImageInfo>>,
     image_type: Option<ImageType>,  // 新增：图片类型
-) -> Result<ImageInfo, String> {
+) -> Result<ImageInfo, ApiError> {
     let images_dir = get_images_dir(&app)?;
 
     // 根据 canvas_id 创建子目录
     let target_dir = if let Some(ref cid) = canvas_id {
         let canvas_dir = images_dir.join(cid);
         if !canvas_dir.exists() {
-            fs::create_dir_all(&canvas_dir).map_err(|e| format!("创建画布目录失败: {}", e))?;
+            fs::create_dir_all(&canvas_dir).map_err(|e| ApiError::storage(format!("创建画布目录失败: {}", e)))?;
         }
         canvas_dir
     } else {
@@ -127,7 +129,7 @@ pub fn save_image(
     // 解码 base64
     let image_data = general_purpose::STANDARD
         .decode(&base64_data)
-        .map_err(|e| format!("Base64 解码失败: {}", e))?;
+        .map_err(|e| ApiError::invalid_request(format!("Base64 解码失败: {}", e)))?;
 
     // 生成唯一文件名
     let id = Uuid::new_v4().to_string();
@@ -136,7 +138,7 @@ pub fn save_image(
     let file_path = target_dir.join(&filename);
 
     // 写入图片文件
-    fs::write(&file_path, &image_data).map_err(|e| format!("写入文件失败: {}", e))?;
+    fs::write(&file_path, &image_data).map_err(|e| ApiError::storage(format!("写入文件失败: {}", e)))?;
 
     // 保存元数据文件（如果有提示词或输入图片）
     if prompt.is_some() || input_images.is_some() {
@@ -152,14 +154,14 @@ pub fn save_image(
         let meta_path = target_dir.join(&meta_filename);
 
         let meta_json = serde_json::to_string_pretty(&metadata)
-            .map_err(|e| format!("序列化元数据失败: {}", e))?;
+            .map_err(|e| ApiError::storage(format!("序列化元数据失败: {}", e)))?;
 
-        fs::write(&meta_path, meta_json).map_err(|e| format!("写入元数据失败: {}", e))?;
+        fs::write(&meta_path, meta_json).map_err(|e| ApiError::storage(format!("写入元数据失败: {}", e)))?;
     }
 
     let path_str = file_path
         .to_str()
-        .ok_or("路径转换失败")?
+        .ok_or_else(|| ApiError::storage("路径转换失败"))?
         .to_string();
 
     Ok(ImageInfo {
@@ -176,20 +178,20 @@ pub fn save_image(
 
 // 读取图片（返回 base64）
 #[tauri::command]
-pub fn read_image(path: String) -> Result<String, String> {
-    let data = fs::read(&path).map_err(|e| format!("读取文件失败: {}", e))?;
+pub fn read_image(path: String) -> Result<String, ApiError> {
+    let data = fs::read(&path).map_err(|e| ApiError::storage(format!("读取文件失败: {}", e)))?;
     Ok(general_purpose::STANDARD.encode(&data))
 }
 
 // 删除图片
 #[tauri::command]
-pub fn delete_image(path: String) -> Result<(), String> {
-    fs::remove_file(&path).map_err(|e| format!("删除文件失败: {}", e))
+pub fn delete_image(path: String) -> Result<(), ApiError> {
+    fs::remove_file(&path).map_err(|e| ApiError::storage(format!("删除文件失败: {}", e)))
 }
 
 // 删除画布的所有图片
 #[tauri::command]
-pub fn delete_canvas_images(app: tauri::AppHandle, canvas_id: String) -> Result<u64, String> {
+pub fn delete_canvas_images(app: tauri::AppHandle, canvas_id: String) -> Result<u64, ApiError> {
     let images_dir = get_images_dir(&app)?;
     let canvas_dir = images_dir.join(&canvas_id);
 
@@ -219,7 +221,7 @@ pub fn delete_canvas_images(app: tauri::AppHandle, canvas_id: String) -> Result<
 
 // 获取存储统计信息
 #[tauri::command]
-pub fn get_storage_stats(app: tauri::AppHandle) -> Result<StorageStats, String> {
+pub fn get_storage_stats(app: tauri::AppHandle) -> Result<StorageStats, ApiError> {
     let images_dir = get_images_dir(&app)?;
     let cache_dir = get_cache_dir(&app)?;
 
@@ -297,13 +299,13 @@ pub fn get_storage_stats(app: tauri::AppHandle) -> Result<StorageStats, String>
 
 // 清理缓存
 #[tauri::command]
-pub fn clear_cache(app: tauri::AppHandle) -> Result<u64, String> {
+pub fn clear_cache(app: tauri::AppHandle) -> Result<u64, ApiError> {
     let cache_dir = get_cache_dir(&app)?;
     let cleared_size = calculate_dir_size(&cache_dir);
 
     if cache_dir.exists() {
-        fs::remove_dir_all(&cache_dir).map_err(|e| format!("清理缓存失败: {}", e))?;
-        fs::create_dir_all(&cache_dir).map_err(|e| format!("重建缓存目录失败: {}", e))?;
+        fs::remove_dir_all(&cache_dir).map_err(|e| ApiError::storage(format!("清理缓存失败: {}", e)))?;
+        fs::create_dir_all(&cache_dir).map_err(|e| ApiError::storage(format!("重建缓存目录失败: {}", e)))?;
     }
 
     Ok(cleared_size)
@@ -311,13 +313,13 @@ pub fn clear_cache(app: tauri::AppHandle) -> Result<u64, String> {
 
 // 清理所有图片
 #[tauri::command]
-pub fn clear_all_images(app: tauri::AppHandle) -> Result<u64, String> {
+pub fn clear_all_images(app: tauri::AppHandle) -> Result<u64, ApiError> {
     let images_dir = get_images_dir(&app)?;
     let cleared_size = calculate_dir_size(&images_dir);
 
     if images_dir.exists() {
-        fs::remove_dir_all(&images_dir).map_err(|e| format!("清理图片失败: {}", e))?;
-        fs::create_dir_all(&images_dir).map_err(|e| format!("重建图片目录失败: {}", e))?;
+        fs::remove_dir_all(&images_dir).map_err(|e| ApiError::storage(format!("清理图片失败: {}", e)))?;
+        fs::create_dir_all(&images_dir).map_err(|e| ApiError::storage(format!("重建图片目录失败: {}", e)))?;
     }
 
     Ok(cleared_size)
@@ -325,12 +327,12 @@ pub fn clear_all_images(app: tauri::AppHandle) -> Result<u64, String> {
 
 // 获取应用数据目录路径（供前端显示）
 #[tauri::command]
-pub fn get_storage_path(app: tauri::AppHandle) -> Result<String, String> {
+pub fn get_storage_path(app: tauri::AppHandle) -> Result<String, ApiError> {
     let app_data = get_app_data_dir(&app)?;
     app_data
         .to_str()
         .map(|s| s.to_string())
-        .ok_or("路径转换失败".to_string())
+        .ok_or_else(|| ApiError::storage("路径转换失败"))
 }
 
 // 列出画布的所有图片（带元数据）
@@ -338,7 +340,7 @@ pub fn get_storage_path(app: tauri::AppHandle) -> Result<String, String> {
 pub fn list_canvas_images(
     app: tauri::AppHandle,
     canvas_id: String,
-) -> Result<Vec<ImageInfoWithMetadata>, String> {
+) -> Result<Vec<ImageInfoWithMetadata>, ApiError> {
     let images_dir = get_images_dir(&app)?;
     let canvas_dir = images_dir.join(&canvas_id);
 
@@ -442,7 +444,7 @@ pub fn list_canvas_images(
 
 // 读取单个图片的元数据
 #[tauri::command]
-pub fn read_image_metadata(image_path: String) -> Result<Option<ImageMetadata>, String> {
+pub fn read_image_metadata(image_path: String) -> Result<Option<ImageMetadata>, ApiError> {
     // 从图片路径构造元数据文件路径
     let meta_path = image_path.replace(".png", ".meta.json");
 
@@ -451,10 +453,10 @@ pub fn read_image_metadata(image_path: String) -> Result<Option<ImageMetadata>,
     }
 
     let content = fs::read_to_string(&meta_path)
-        .map_err(|e| format!("读取元数据失败: {}", e))?;
+        .map_err(|e| ApiError::storage(format!("读取元数据失败: {}", e)))?;
 
     let metadata: ImageMetadata = serde_json::from_str(&content)
-        .map_err(|e| format!("解析元数据失败: {}", e))?;
+        .map_err(|e| ApiError::storage(format!("解析元数据失败: {}", e)))?;
 
     Ok(Some(metadata))
 }
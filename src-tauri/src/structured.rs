@@ -0,0 +1,169 @@
+use serde_json::Value;
+use tracing::warn;
+
+// 单次校验最多报告的错误条数（回传给模型修复时避免提示过长）
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// 结构化输出的校验结果
+pub enum StructuredCheck {
+    Valid(Value),
+    // 无法提取 JSON，或 JSON 不符合 Schema
+    Invalid(Vec<String>),
+}
+
+/// 从模型输出中提取 JSON，并按 Schema 校验（schema 为空时只检查能否解析）
+///
+/// 兼容 Markdown 代码块包裹、前后附带说明文字等常见情况。
+pub fn check_structured_output(content: &str, schema: Option<&Value>) -> StructuredCheck {
+    let Some(value) = extract_json(content) else {
+        return StructuredCheck::Invalid(vec!["输出不是有效的 JSON".to_string()]);
+    };
+    let Some(schema) = schema else {
+        return StructuredCheck::Valid(value);
+    };
+
+    // 供应商能接受的 Schema 可能超出校验器支持的范围，此时只检查能否解析
+    let validator = match jsonschema::validator_for(schema) {
+        Ok(validator) => validator,
+        Err(e) => {
+            warn!("Skip schema validation, invalid schema: {}", e);
+            return StructuredCheck::Valid(value);
+        }
+    };
+    let errors: Vec<String> = validator
+        .iter_errors(&value)
+        .take(MAX_REPORTED_ERRORS)
+        .map(|error| {
+            let path = error.instance_path.to_string();
+            format!("{}: {}", if path.is_empty() { "/" } else { &path }, error)
+        })
+        .collect();
+    if errors.is_empty() {
+        StructuredCheck::Valid(value)
+    } else {
+        StructuredCheck::Invalid(errors)
+    }
+}
+
+/// 提取文本中的 JSON：依次尝试整段解析、代码块内容、首个 `{`/`[` 到末尾对应括号之间的内容
+pub fn extract_json(content: &str) -> Option<Value> {
+    let content = content.trim();
+    if let Ok(value) = serde_json::from_str(content) {
+        return Some(value);
+    }
+
+    if let Some(inner) = fenced_block(content) {
+        if let Ok(value) = serde_json::from_str(inner) {
+            return Some(value);
+        }
+    }
+
+    let start = content.find(['{', '['])?;
+    let close = if content[start..].starts_with('{') { '}' } else { ']' };
+    let end = content.rfind(close)?;
+    if end <= start {
+        return None;
+    }
+    serde_json::from_str(&content[start..=end]).ok()
+}
+
+// ```json ... ``` 代码块中的内容
+fn fenced_block(content: &str) -> Option<&str> {
+    let start = content.find("```")?;
+    let after = &content[start + 3..];
+    // 跳过语言标记（如 json）所在的行
+    let body_start = after.find('\n')? + 1;
+    let body = &after[body_start..];
+    let end = body.find("```")?;
+    Some(body[..end].trim())
+}
+
+/// 回传给模型的修复提示
+pub fn repair_prompt(errors: &[String]) -> String {
+    format!(
+        "你上一次的输出不符合要求的 JSON Schema，问题如下：\n{}\n\n请修正以上问题，只输出符合 Schema 的 JSON，不要包含 Markdown 代码块或任何说明文字。",
+        errors.iter().map(|error| format!("- {}", error)).collect::<Vec<_>>().join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn person_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "age": { "type": "integer", "minimum": 0 }
+            },
+            "required": ["name", "age"]
+        })
+    }
+
+    #[test]
+    fn extracts_plain_json() {
+        assert_eq!(extract_json("  {\"a\": 1}\n"), Some(json!({ "a": 1 })));
+        assert_eq!(extract_json("[1, 2]"), Some(json!([1, 2])));
+    }
+
+    #[test]
+    fn extracts_fenced_json() {
+        let content = "结果如下：\n```json\n{\"a\": [1, 2]}\n```\n以上。";
+        assert_eq!(extract_json(content), Some(json!({ "a": [1, 2] })));
+    }
+
+    #[test]
+    fn extracts_json_surrounded_by_text() {
+        let content = "Here you go: {\"a\": {\"b\": true}} — done";
+        assert_eq!(extract_json(content), Some(json!({ "a": { "b": true } })));
+    }
+
+    #[test]
+    fn rejects_non_json() {
+        assert_eq!(extract_json("no json here"), None);
+        assert_eq!(extract_json("} backwards {"), None);
+        assert_eq!(extract_json("{\"a\": 1"), None);
+    }
+
+    #[test]
+    fn valid_output_passes_schema() {
+        let schema = person_schema();
+        match check_structured_output("```json\n{\"name\": \"Ann\", \"age\": 3}\n```", Some(&schema)) {
+            StructuredCheck::Valid(value) => assert_eq!(value, json!({ "name": "Ann", "age": 3 })),
+            StructuredCheck::Invalid(errors) => panic!("unexpected errors: {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn schema_violations_are_reported_with_paths() {
+        let schema = person_schema();
+        match check_structured_output("{\"name\": \"Ann\", \"age\": -1}", Some(&schema)) {
+            StructuredCheck::Valid(value) => panic!("unexpected valid output: {}", value),
+            StructuredCheck::Invalid(errors) => {
+                assert_eq!(errors.len(), 1);
+                assert!(errors[0].starts_with("/age: "), "{}", errors[0]);
+            }
+        }
+        match check_structured_output("{}", Some(&schema)) {
+            StructuredCheck::Valid(value) => panic!("unexpected valid output: {}", value),
+            StructuredCheck::Invalid(errors) => {
+                assert!(!errors.is_empty());
+                assert!(errors.iter().all(|error| error.starts_with("/: ")), "{:?}", errors);
+            }
+        }
+    }
+
+    #[test]
+    fn unparsable_output_is_invalid_without_schema() {
+        assert!(matches!(check_structured_output("oops", None), StructuredCheck::Invalid(_)));
+        assert!(matches!(check_structured_output("{\"x\": 1}", None), StructuredCheck::Valid(_)));
+    }
+
+    #[test]
+    fn repair_prompt_lists_errors() {
+        let prompt = repair_prompt(&["/age: -1 is less than the minimum of 0".to_string()]);
+        assert!(prompt.contains("- /age: -1 is less than the minimum of 0"));
+    }
+}
@@ -0,0 +1,407 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::ops::AddAssign;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+use tracing::warn;
+
+use crate::error::ApiError;
+
+// 追加账本时串行写入，避免并发请求的记录交错
+static LEDGER_LOCK: Mutex<()> = Mutex::new(());
+
+// 生效的价格表（内置价格 + prices.json 中的用户覆盖），首次使用时加载，保存价格表时更新
+static PRICES: Mutex<Option<PriceTable>> = Mutex::new(None);
+
+// ==================== 用量数据结构 ====================
+
+/// 单次调用的用量
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cached_tokens: u64, // 命中缓存的输入 token（已包含在 input_tokens 中）
+    pub images: u64,        // 生成的图片张数
+    pub video_seconds: u64, // 生成的视频时长（秒）
+}
+
+impl TokenUsage {
+    pub fn is_empty(&self) -> bool {
+        self.input_tokens == 0
+            && self.output_tokens == 0
+            && self.images == 0
+            && self.video_seconds == 0
+    }
+}
+
+// 累加多次请求（如结构化输出的修复轮次、用量汇总）的全部用量
+impl AddAssign<&TokenUsage> for TokenUsage {
+    fn add_assign(&mut self, other: &TokenUsage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cached_tokens += other.cached_tokens;
+        self.images += other.images;
+        self.video_seconds += other.video_seconds;
+    }
+}
+
+/// 模型单价（美元），token 按每百万计价
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ModelPrice {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+    pub cached_input_per_million: Option<f64>, // 未设置时按普通输入计价
+    pub per_image: f64,
+    pub per_video_second: f64,
+}
+
+impl ModelPrice {
+    fn cost(&self, usage: &TokenUsage) -> f64 {
+        let cached = usage.cached_tokens.min(usage.input_tokens);
+        let uncached = usage.input_tokens - cached;
+        let cached_price = self.cached_input_per_million.unwrap_or(self.input_per_million);
+
+        (uncached as f64 * self.input_per_million
+            + cached as f64 * cached_price
+            + usage.output_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+            + usage.images as f64 * self.per_image
+            + usage.video_seconds as f64 * self.per_video_second
+    }
+}
+
+// 价格表：键为模型名或模型名前缀（如 "gpt-4o"、"gemini-2.5-"）
+type PriceTable = HashMap<String, ModelPrice>;
+
+// 内置价格：参考各官方公开的标准价格，网关或折扣价不同时可在 prices.json 中按同名键覆盖
+fn default_prices() -> PriceTable {
+    let tokens = |input: f64, output: f64, cached: f64| ModelPrice {
+        input_per_million: input,
+        output_per_million: output,
+        cached_input_per_million: Some(cached),
+        ..Default::default()
+    };
+    let image = |per_image: f64| ModelPrice {
+        per_image,
+        ..Default::default()
+    };
+
+    [
+        ("gpt-4o", tokens(2.5, 10.0, 1.25)),
+        ("gpt-4o-mini", tokens(0.15, 0.6, 0.075)),
+        ("gpt-4.1", tokens(2.0, 8.0, 0.5)),
+        ("gpt-4.1-mini", tokens(0.4, 1.6, 0.1)),
+        ("gpt-4.1-nano", tokens(0.1, 0.4, 0.025)),
+        ("o3", tokens(2.0, 8.0, 0.5)),
+        ("o4-mini", tokens(1.1, 4.4, 0.275)),
+        ("claude-opus-4", tokens(15.0, 75.0, 1.5)),
+        ("claude-sonnet-4", tokens(3.0, 15.0, 0.3)),
+        ("claude-3-5-haiku", tokens(0.8, 4.0, 0.08)),
+        ("claude-haiku-4-5", tokens(1.0, 5.0, 0.1)),
+        ("gemini-2.5-pro", tokens(1.25, 10.0, 0.31)),
+        ("gemini-2.5-flash", tokens(0.3, 2.5, 0.075)),
+        ("gemini-2.5-flash-lite", tokens(0.1, 0.4, 0.025)),
+        // 图片模型按张计价（输出 token 已折算在单张价格中）
+        (
+            "gemini-2.5-flash-image",
+            ModelPrice {
+                input_per_million: 0.3,
+                ..image(0.039)
+            },
+        ),
+        ("dall-e-3", image(0.04)),
+        ("gpt-image-1", image(0.042)),
+    ]
+    .into_iter()
+    .map(|(model, price)| (model.to_string(), price))
+    .collect()
+}
+
+// 查找模型价格：优先精确匹配，其次最长前缀匹配
+fn find_price<'a>(prices: &'a PriceTable, model: &str) -> Option<&'a ModelPrice> {
+    prices.get(model).or_else(|| {
+        prices
+            .iter()
+            .filter(|(key, _)| model.starts_with(key.as_str()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, price)| price)
+    })
+}
+
+/// 账本中的一条记录（JSON Lines）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRecord {
+    pub created_at: i64, // 毫秒时间戳
+    pub day: String,     // 本地日期 YYYY-MM-DD，便于按天汇总
+    pub canvas_id: Option<String>,
+    pub provider: String,
+    pub model: String,
+    #[serde(flatten)]
+    pub usage: TokenUsage,
+    pub cost: Option<f64>, // 记录时按价格表计算，未配置价格时为空
+}
+
+// ==================== 账本文件 ====================
+
+// 获取用量目录
+fn get_usage_dir(app: &AppHandle) -> Result<PathBuf, ApiError> {
+    let usage_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| ApiError::storage(format!("无法获取应用数据目录: {}", e)))?
+        .join("usage");
+    if !usage_dir.exists() {
+        fs::create_dir_all(&usage_dir).map_err(|e| ApiError::storage(format!("创建用量目录失败: {}", e)))?;
+    }
+    Ok(usage_dir)
+}
+
+// 读取 prices.json 中的用户覆盖，与内置价格合并（内置价格更新后，未覆盖的模型自动使用新价格）
+fn load_prices(app: &AppHandle) -> PriceTable {
+    let overrides: PriceTable = get_usage_dir(app)
+        .ok()
+        .and_then(|dir| fs::read_to_string(dir.join("prices.json")).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    merge_prices(overrides)
+}
+
+fn merge_prices(overrides: PriceTable) -> PriceTable {
+    let mut prices = default_prices();
+    prices.extend(overrides);
+    prices
+}
+
+// 只保留与内置价格不同的条目（与内置价格相同或未提供的模型恢复为内置价格）
+fn price_overrides(prices: PriceTable) -> PriceTable {
+    let defaults = default_prices();
+    prices
+        .into_iter()
+        .filter(|(model, price)| defaults.get(model) != Some(price))
+        .collect()
+}
+
+// 生效的价格表（只在首次使用时读取文件）
+fn current_prices(app: &AppHandle) -> PriceTable {
+    PRICES.lock().unwrap().get_or_insert_with(|| load_prices(app)).clone()
+}
+
+/// 记录一次调用的用量（写入失败只打印日志，不影响调用结果）
+///
+/// 文件读写在阻塞线程池中进行，不占用异步运行时。
+pub fn record_usage(app: &AppHandle, canvas_id: Option<&str>, provider: &str, model: &str, usage: &TokenUsage) {
+    if usage.is_empty() {
+        return;
+    }
+
+    let now = chrono::Local::now();
+    let mut record = UsageRecord {
+        created_at: now.timestamp_millis(),
+        day: now.format("%Y-%m-%d").to_string(),
+        canvas_id: canvas_id.map(|id| id.to_string()),
+        provider: provider.to_string(),
+        model: model.to_string(),
+        usage: usage.clone(),
+        cost: None,
+    };
+
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        record.cost = find_price(&current_prices(&app), &record.model).map(|price| price.cost(&record.usage));
+        if let Err(e) = append_record(&app, &record) {
+            warn!("Failed to record usage: {}", e);
+        }
+    });
+}
+
+fn append_record(app: &AppHandle, record: &UsageRecord) -> Result<(), ApiError> {
+    let path = get_usage_dir(app)?.join("ledger.jsonl");
+    let line = serde_json::to_string(record).map_err(|e| ApiError::storage(format!("序列化用量失败: {}", e)))?;
+
+    let _guard = LEDGER_LOCK.lock().unwrap();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| ApiError::storage(format!("打开用量账本失败: {}", e)))?;
+    writeln!(file, "{}", line).map_err(|e| ApiError::storage(format!("写入用量账本失败: {}", e)))
+}
+
+fn read_records(app: &AppHandle) -> Result<Vec<UsageRecord>, ApiError> {
+    let path = get_usage_dir(app)?.join("ledger.jsonl");
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let _guard = LEDGER_LOCK.lock().unwrap();
+    let file = fs::File::open(&path).map_err(|e| ApiError::storage(format!("打开用量账本失败: {}", e)))?;
+    // 跳过损坏的行（如写入中途断电）
+    Ok(BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect())
+}
+
+// ==================== 查询 ====================
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageGroupBy {
+    #[default]
+    Day,
+    Canvas,
+    Model,
+}
+
+// 用量查询参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageQueryParams {
+    pub from: Option<String>, // 起始日期 YYYY-MM-DD（含）
+    pub to: Option<String>,   // 结束日期 YYYY-MM-DD（含）
+    pub canvas_id: Option<String>,
+    pub model: Option<String>,
+    #[serde(default)]
+    pub group_by: UsageGroupBy,
+}
+
+// 单个分组的汇总
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageSummary {
+    pub key: String,
+    pub requests: u64,
+    #[serde(flatten)]
+    pub usage: TokenUsage,
+    pub cost: f64,
+    pub unpriced_requests: u64, // 未配置价格、未计入 cost 的调用次数
+}
+
+impl UsageSummary {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.usage += &record.usage;
+        match record.cost {
+            Some(cost) => self.cost += cost,
+            None => self.unpriced_requests += 1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageQueryResult {
+    pub success: bool,
+    pub groups: Vec<UsageSummary>,
+    pub total: UsageSummary,
+    pub error: Option<ApiError>,
+}
+
+impl UsageQueryResult {
+    fn failed(error: ApiError) -> Self {
+        UsageQueryResult {
+            success: false,
+            groups: Vec::new(),
+            total: UsageSummary::default(),
+            error: Some(error),
+        }
+    }
+}
+
+// 按日期、画布或模型汇总用量（读取账本在阻塞线程池中进行）
+#[tauri::command]
+pub async fn query_usage(app: AppHandle, params: UsageQueryParams) -> UsageQueryResult {
+    tauri::async_runtime::spawn_blocking(move || summarize_usage(&app, &params))
+        .await
+        .unwrap_or_else(|e| UsageQueryResult::failed(ApiError::storage(format!("查询用量失败: {}", e))))
+}
+
+fn summarize_usage(app: &AppHandle, params: &UsageQueryParams) -> UsageQueryResult {
+    let records = match read_records(app) {
+        Ok(records) => records,
+        Err(e) => return UsageQueryResult::failed(e),
+    };
+
+    let mut groups: BTreeMap<String, UsageSummary> = BTreeMap::new();
+    let mut total = UsageSummary {
+        key: "total".to_string(),
+        ..Default::default()
+    };
+
+    for record in records.iter().filter(|record| {
+        // 日期格式固定，可直接按字符串比较
+        params.from.as_deref().is_none_or(|from| record.day.as_str() >= from)
+            && params.to.as_deref().is_none_or(|to| record.day.as_str() <= to)
+            && params.canvas_id.as_ref().is_none_or(|id| record.canvas_id.as_ref() == Some(id))
+            && params.model.as_ref().is_none_or(|model| &record.model == model)
+    }) {
+        let key = match params.group_by {
+            UsageGroupBy::Day => record.day.clone(),
+            UsageGroupBy::Canvas => record.canvas_id.clone().unwrap_or_default(),
+            UsageGroupBy::Model => record.model.clone(),
+        };
+        groups
+            .entry(key.clone())
+            .or_insert_with(|| UsageSummary { key, ..Default::default() })
+            .add(record);
+        total.add(record);
+    }
+
+    UsageQueryResult {
+        success: true,
+        groups: groups.into_values().collect(),
+        total,
+        error: None,
+    }
+}
+
+// 获取生效的价格表（内置价格 + 用户覆盖）
+#[tauri::command]
+pub fn get_usage_prices(app: AppHandle) -> HashMap<String, ModelPrice> {
+    current_prices(&app)
+}
+
+// 保存价格表（只影响之后记录的调用）
+//
+// prices 为完整的期望价格表，prices.json 只保存与内置价格不同的条目；
+// 省略或改回内置价格的模型恢复跟随内置价格，传空表即全部重置。
+#[tauri::command]
+pub fn set_usage_prices(app: AppHandle, prices: HashMap<String, ModelPrice>) -> Result<(), ApiError> {
+    let path = get_usage_dir(&app)?.join("prices.json");
+    let overrides = price_overrides(prices);
+    let content =
+        serde_json::to_string_pretty(&overrides).map_err(|e| ApiError::storage(format!("序列化价格表失败: {}", e)))?;
+    fs::write(&path, content).map_err(|e| ApiError::storage(format!("保存价格表失败: {}", e)))?;
+    *PRICES.lock().unwrap() = Some(merge_prices(overrides));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_changed_prices_are_stored_as_overrides() {
+        let mut prices = default_prices();
+        prices.get_mut("gpt-4o").unwrap().input_per_million = 2.0;
+        prices.insert("my-model".to_string(), ModelPrice::default());
+        prices.remove("o3");
+
+        let overrides = price_overrides(prices);
+        let mut keys: Vec<&str> = overrides.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, ["gpt-4o", "my-model"]);
+
+        // 未覆盖（含被省略）的模型仍使用内置价格
+        let merged = merge_prices(overrides);
+        assert_eq!(merged["gpt-4o"].input_per_million, 2.0);
+        assert_eq!(merged["o3"], default_prices()["o3"]);
+    }
+}
@@ -0,0 +1,342 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once, RwLock};
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+
+use crate::error::{ApiError, ErrorCode};
+
+// 读写凭据文件时串行化，避免并发修改互相覆盖
+static VAULT_LOCK: Mutex<()> = Mutex::new(());
+
+// 已加载的主密钥，进程内只访问一次钥匙串
+static CIPHER: RwLock<Option<ChaCha20Poly1305>> = RwLock::new(None);
+
+// 主密钥在系统钥匙串中的服务名与账户名
+const KEYCHAIN_SERVICE: &str = "com.sy.nextlemon";
+const KEYCHAIN_ACCOUNT: &str = "credential-vault-key";
+
+// ==================== 凭据库数据结构 ====================
+
+// 单个供应商的加密凭据（密文以供应商 ID 作为附加数据，不能挪用到其他供应商）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SealedSecret {
+    nonce: String,      // base64
+    ciphertext: String, // base64
+    hint: String,       // 明文末尾几位，供界面展示
+    updated_at: i64,    // 毫秒时间戳
+}
+
+// 供应商 ID -> 加密凭据
+type VaultEntries = BTreeMap<String, SealedSecret>;
+
+/// 已保存凭据的摘要（不含明文）
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredKeyInfo {
+    pub provider_id: String,
+    pub hint: String,
+    pub updated_at: i64,
+}
+
+// ==================== 凭据文件 ====================
+
+// 凭据目录：app_data_dir/credentials，包含密文 vault.json（系统钥匙串不可用时还有主密钥 vault.key）
+fn get_vault_dir(app: &AppHandle) -> Result<PathBuf, ApiError> {
+    let vault_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| ApiError::storage(format!("无法获取应用数据目录: {}", e)))?
+        .join("credentials");
+    if !vault_dir.exists() {
+        fs::create_dir_all(&vault_dir).map_err(|e| ApiError::storage(format!("创建凭据目录失败: {}", e)))?;
+    }
+    Ok(vault_dir)
+}
+
+// 写入仅当前用户可读的文件
+fn write_private_file(path: &Path, content: &[u8]) -> Result<(), ApiError> {
+    let mut options = fs::OpenOptions::new();
+    options.create(true).write(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| ApiError::storage(format!("写入凭据文件失败: {}", e)))?;
+    file.write_all(content)
+        .map_err(|e| ApiError::storage(format!("写入凭据文件失败: {}", e)))
+}
+
+/// 读取主密钥，首次使用时随机生成
+///
+/// 主密钥优先保存在系统钥匙串（macOS Keychain、Windows 凭据管理器、Linux Secret Service）中，
+/// 与密文分开存放；已有的 vault.key 会在钥匙串可用时迁移过去并删除。
+/// 系统没有可用的钥匙串时（如没有 Secret Service 的 Linux 桌面）退回到凭据目录下仅当前用户可读的
+/// vault.key 文件，此时主密钥与密文位于同一目录，只能防止密文被单独拷走后解密。
+fn load_cipher(dir: &Path) -> Result<ChaCha20Poly1305, ApiError> {
+    let key_path = dir.join("vault.key");
+    let keychain = keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT)
+        .and_then(|entry| match entry.get_password() {
+            Ok(encoded) => Ok((entry, Some(encoded))),
+            Err(keyring::Error::NoEntry) => Ok((entry, None)),
+            Err(e) => Err(e),
+        });
+
+    match keychain {
+        Ok((_, Some(encoded))) => {
+            let bytes = BASE64
+                .decode(encoded.trim())
+                .ok()
+                .filter(|bytes| bytes.len() == 32)
+                .ok_or_else(|| ApiError::storage("系统钥匙串中的凭据主密钥已损坏"))?;
+            return Ok(ChaCha20Poly1305::new(Key::from_slice(&bytes)));
+        }
+        Ok((entry, None)) => {
+            // 钥匙串中还没有主密钥：迁移旧的密钥文件，或生成新密钥
+            let migrated = read_key_file(&key_path)?;
+            let key = match migrated {
+                Some(key) => key,
+                None => new_key(dir)?,
+            };
+            match entry.set_password(&BASE64.encode(key)) {
+                Ok(()) if migrated.is_some() => {
+                    if let Err(e) = fs::remove_file(&key_path) {
+                        warn!("Failed to remove migrated vault key file: {}", e);
+                    }
+                    info!("Migrated credential vault key to system keychain");
+                    return Ok(ChaCha20Poly1305::new(&key));
+                }
+                Ok(()) => {
+                    info!("Created credential vault key in system keychain");
+                    return Ok(ChaCha20Poly1305::new(&key));
+                }
+                Err(e) => warn_keychain_unavailable(&e),
+            }
+        }
+        Err(e) => warn_keychain_unavailable(&e),
+    }
+
+    // 退回到密钥文件
+    if let Some(key) = read_key_file(&key_path)? {
+        return Ok(ChaCha20Poly1305::new(&key));
+    }
+    let key = new_key(dir)?;
+    write_private_file(&key_path, key.as_slice())?;
+    info!("Created credential vault key file");
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+// 读取缓存的主密钥，首次调用时加载（调用方需持有 VAULT_LOCK）
+fn cached_cipher(dir: &Path) -> Result<ChaCha20Poly1305, ApiError> {
+    if let Some(cipher) = CIPHER.read().unwrap().as_ref() {
+        return Ok(cipher.clone());
+    }
+    let cipher = load_cipher(dir)?;
+    *CIPHER.write().unwrap() = Some(cipher.clone());
+    Ok(cipher)
+}
+
+// 生成新的主密钥；已有密文时说明原主密钥丢失（如钥匙串被清空），不能用新密钥覆盖
+fn new_key(dir: &Path) -> Result<Key, ApiError> {
+    if dir.join("vault.json").exists() {
+        return Err(ApiError::storage("找不到凭据主密钥，请检查系统钥匙串是否可以访问"));
+    }
+    Ok(ChaCha20Poly1305::generate_key(&mut OsRng))
+}
+
+fn read_key_file(key_path: &Path) -> Result<Option<Key>, ApiError> {
+    if !key_path.exists() {
+        return Ok(None);
+    }
+    let bytes = fs::read(key_path).map_err(|e| ApiError::storage(format!("读取凭据主密钥失败: {}", e)))?;
+    if bytes.len() != 32 {
+        return Err(ApiError::storage("凭据主密钥已损坏"));
+    }
+    Ok(Some(*Key::from_slice(&bytes)))
+}
+
+// 每次读取凭据都会访问钥匙串，不可用的提示只记录一次
+fn warn_keychain_unavailable(error: &keyring::Error) {
+    static WARNED: Once = Once::new();
+    WARNED.call_once(|| warn!("System keychain unavailable, falling back to vault key file: {}", error));
+}
+
+fn read_entries(dir: &Path) -> Result<VaultEntries, ApiError> {
+    let path = dir.join("vault.json");
+    if !path.exists() {
+        return Ok(VaultEntries::new());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| ApiError::storage(format!("读取凭据失败: {}", e)))?;
+    serde_json::from_str(&content).map_err(|e| ApiError::storage(format!("解析凭据失败: {}", e)))
+}
+
+// 先写临时文件再替换，避免写入中途崩溃导致凭据全部丢失
+fn write_entries(dir: &Path, entries: &VaultEntries) -> Result<(), ApiError> {
+    let content =
+        serde_json::to_vec_pretty(entries).map_err(|e| ApiError::storage(format!("序列化凭据失败: {}", e)))?;
+    let tmp_path = dir.join("vault.json.tmp");
+    write_private_file(&tmp_path, &content)?;
+    fs::rename(&tmp_path, dir.join("vault.json")).map_err(|e| ApiError::storage(format!("保存凭据失败: {}", e)))
+}
+
+// ==================== 加解密 ====================
+
+fn seal(cipher: &ChaCha20Poly1305, provider_id: &str, api_key: &str) -> Result<SealedSecret, ApiError> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: api_key.as_bytes(),
+                aad: provider_id.as_bytes(),
+            },
+        )
+        .map_err(|_| ApiError::storage("加密凭据失败"))?;
+
+    Ok(SealedSecret {
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+        hint: key_hint(api_key),
+        updated_at: chrono::Utc::now().timestamp_millis(),
+    })
+}
+
+fn open(cipher: &ChaCha20Poly1305, provider_id: &str, sealed: &SealedSecret) -> Result<String, ApiError> {
+    let nonce = BASE64
+        .decode(&sealed.nonce)
+        .ok()
+        .filter(|nonce| nonce.len() == 12)
+        .ok_or_else(|| ApiError::storage("凭据已损坏"))?;
+    let ciphertext = BASE64
+        .decode(&sealed.ciphertext)
+        .map_err(|_| ApiError::storage("凭据已损坏"))?;
+
+    // 主密钥被替换或密文被篡改时解密失败
+    let plaintext = cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: provider_id.as_bytes(),
+            },
+        )
+        .map_err(|_| ApiError::storage("凭据解密失败，请重新设置 API Key"))?;
+    String::from_utf8(plaintext).map_err(|_| ApiError::storage("凭据已损坏"))
+}
+
+// 只保留末尾 4 位，如 "…a1b2"
+fn key_hint(api_key: &str) -> String {
+    let chars: Vec<char> = api_key.chars().collect();
+    let tail: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+    format!("…{}", tail)
+}
+
+// ==================== 对外接口 ====================
+
+/// 读取供应商的 API Key（未保存时返回 None）
+pub fn get_api_key(app: &AppHandle, provider_id: &str) -> Result<Option<String>, ApiError> {
+    let dir = get_vault_dir(app)?;
+    let _guard = VAULT_LOCK.lock().unwrap();
+    let entries = read_entries(&dir)?;
+    let Some(sealed) = entries.get(provider_id) else {
+        return Ok(None);
+    };
+    open(&cached_cipher(&dir)?, provider_id, sealed).map(Some)
+}
+
+/// 确定本次请求使用的 API Key
+///
+/// 传入 provider_id 时只使用凭据库中对应的 Key，未保存时报错而不回退到前端传入的 Key；
+/// 未传 provider_id 时使用前端传入的 Key（内置供应商与 Web 模式仍直接传 Key）。
+/// 读取凭据涉及文件与钥匙串访问，在阻塞线程池中执行。
+pub async fn resolve_api_key(
+    app: &AppHandle,
+    provider_id: Option<&str>,
+    inline_key: &str,
+) -> Result<String, ApiError> {
+    if let Some(provider_id) = provider_id.filter(|id| !id.is_empty()) {
+        let (app, provider_id) = (app.clone(), provider_id.to_string());
+        return tauri::async_runtime::spawn_blocking(move || get_api_key(&app, &provider_id))
+            .await
+            .map_err(|e| ApiError::storage(format!("读取凭据失败: {}", e)))??
+            .ok_or_else(|| ApiError::new(ErrorCode::InvalidApiKey, "凭据库中没有该供应商的 API Key，请重新设置"));
+    }
+    if !inline_key.is_empty() {
+        return Ok(inline_key.to_string());
+    }
+    Err(ApiError::new(ErrorCode::InvalidApiKey, "供应商 API Key 未配置"))
+}
+
+// 写入或覆盖一条凭据
+fn store_api_key(app: &AppHandle, provider_id: &str, api_key: &str, require_existing: bool) -> Result<(), ApiError> {
+    let api_key = api_key.trim();
+    if provider_id.is_empty() || api_key.is_empty() {
+        return Err(ApiError::invalid_request("供应商 ID 与 API Key 不能为空"));
+    }
+
+    let dir = get_vault_dir(app)?;
+    let _guard = VAULT_LOCK.lock().unwrap();
+    let mut entries = read_entries(&dir)?;
+    if require_existing && !entries.contains_key(provider_id) {
+        return Err(ApiError::new(ErrorCode::NotFound, "该供应商尚未保存 API Key"));
+    }
+
+    let sealed = seal(&cached_cipher(&dir)?, provider_id, api_key)?;
+    entries.insert(provider_id.to_string(), sealed);
+    write_entries(&dir, &entries)
+}
+
+// ==================== Tauri 命令 ====================
+
+/// 保存供应商的 API Key（已存在时覆盖）
+#[tauri::command]
+pub fn set_api_key(app: AppHandle, provider_id: String, api_key: String) -> Result<(), ApiError> {
+    info!(provider_id = %provider_id, "set_api_key");
+    store_api_key(&app, &provider_id, &api_key, false)
+}
+
+/// 轮换供应商的 API Key（要求已保存过）
+#[tauri::command]
+pub fn rotate_api_key(app: AppHandle, provider_id: String, api_key: String) -> Result<(), ApiError> {
+    info!(provider_id = %provider_id, "rotate_api_key");
+    store_api_key(&app, &provider_id, &api_key, true)
+}
+
+/// 删除供应商的 API Key，返回是否存在
+#[tauri::command]
+pub fn delete_api_key(app: AppHandle, provider_id: String) -> Result<bool, ApiError> {
+    info!(provider_id = %provider_id, "delete_api_key");
+    let dir = get_vault_dir(&app)?;
+    let _guard = VAULT_LOCK.lock().unwrap();
+    let mut entries = read_entries(&dir)?;
+    if entries.remove(&provider_id).is_none() {
+        return Ok(false);
+    }
+    write_entries(&dir, &entries)?;
+    Ok(true)
+}
+
+/// 列出已保存凭据的供应商（只返回末尾几位）
+#[tauri::command]
+pub fn list_api_keys(app: AppHandle) -> Result<Vec<StoredKeyInfo>, ApiError> {
+    let dir = get_vault_dir(&app)?;
+    let _guard = VAULT_LOCK.lock().unwrap();
+    Ok(read_entries(&dir)?
+        .into_iter()
+        .map(|(provider_id, sealed)| StoredKeyInfo {
+            provider_id,
+            hint: sealed.hint,
+            updated_at: sealed.updated_at,
+        })
+        .collect())
+}
@@ -1,7 +1,15 @@
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use tauri::AppHandle;
+use tracing::{debug, info, warn};
+
+use crate::cancellation::RequestRegistry;
+use crate::error::{ApiError, ErrorCode};
+use crate::http::{send_with_retry, HttpClientManager, Idempotency, RetryPolicy, TimeoutKey};
+use crate::logging::request_key;
+use crate::media::{prepare_input_images, ImageTarget, InputImage};
+use crate::usage::{record_usage, TokenUsage};
+use crate::vault::resolve_api_key;
 
 // ==================== 视频服务数据结构 ====================
 
@@ -10,12 +18,17 @@ use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 #[serde(rename_all = "camelCase")]
 pub struct VideoCreateParams {
     pub base_url: String,
-    pub api_key: String,
+    #[serde(default)]
+    pub api_key: String, // 未使用凭据库时由前端直接传入
+    pub provider_id: Option<String>, // 凭据库中的供应商 ID，优先于 api_key
     pub model: String,
     pub prompt: String,
     pub seconds: Option<String>,
     pub size: Option<String>,
     pub input_image: Option<String>,  // base64 编码的参考图片
+    pub request_id: Option<String>,   // 用于 cancel_request 取消请求
+    pub retry: Option<RetryPolicy>,   // 重试策略（创建任务只在确定未提交时重试）
+    pub canvas_id: Option<String>,    // 用量记账归属的画布
 }
 
 // 视频任务响应
@@ -29,7 +42,12 @@ pub struct VideoTaskResult {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub progress: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub error: Option<String>,
+    pub error: Option<ApiError>,
+    pub attempts: u32, // 实际发送请求的次数（含重试）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>, // 创建任务时按请求的视频时长记账
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>, // 参考图片被重新压缩等提示
 }
 
 // 视频内容结果
@@ -40,7 +58,8 @@ pub struct VideoContentResult {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub video_data: Option<String>,  // base64 编码的视频数据
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub error: Option<String>,
+    pub error: Option<ApiError>,
+    pub attempts: u32, // 实际发送请求的次数（含重试）
 }
 
 // 获取任务状态参数
@@ -48,8 +67,12 @@ pub struct VideoContentResult {
 #[serde(rename_all = "camelCase")]
 pub struct VideoStatusParams {
     pub base_url: String,
-    pub api_key: String,
+    #[serde(default)]
+    pub api_key: String, // 未使用凭据库时由前端直接传入
+    pub provider_id: Option<String>, // 凭据库中的供应商 ID，优先于 api_key
     pub task_id: String,
+    pub request_id: Option<String>, // 用于 cancel_request 取消请求
+    pub retry: Option<RetryPolicy>, // 重试策略（按供应商配置，默认重试 3 次）
 }
 
 // API 响应结构
@@ -63,101 +86,153 @@ struct VideoApiResponse {
 
 #[derive(Debug, Deserialize)]
 struct VideoApiError {
+    code: Option<serde_json::Value>, // 不同服务商可能返回字符串或数字
     message: Option<String>,
 }
 
+impl VideoApiError {
+    fn into_api_error(self) -> ApiError {
+        let provider_code = self.code.map(|code| match code {
+            serde_json::Value::String(code) => code,
+            other => other.to_string(),
+        });
+        // 视频任务因审核失败时 code 为 moderation_blocked 等
+        let code = match provider_code.as_deref() {
+            Some(code) if code.contains("moderation") || code.contains("content_policy") => {
+                ErrorCode::ContentFiltered
+            }
+            _ => ErrorCode::ProviderError,
+        };
+        ApiError::new(code, self.message.unwrap_or_else(|| "视频服务返回错误".to_string()))
+            .with_provider_type(provider_code)
+    }
+}
+
+impl VideoTaskResult {
+    // 失败结果，附带已发送的请求次数（未发出请求时为 0）
+    fn failure(error: ApiError, attempts: u32) -> Self {
+        VideoTaskResult {
+            success: false,
+            task_id: None,
+            status: None,
+            progress: None,
+            error: Some(error),
+            attempts,
+            usage: None,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl VideoContentResult {
+    fn failure(error: ApiError, attempts: u32) -> Self {
+        VideoContentResult {
+            success: false,
+            video_data: None,
+            error: Some(error),
+            attempts,
+        }
+    }
+}
+
 // ==================== 创建视频任务 ====================
 
 #[tauri::command]
-pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
-    println!("[Rust] video_create_task called");
-    println!("[Rust] base_url: {}", params.base_url);
-    println!("[Rust] model: {}", params.model);
-
-    // 创建 HTTP 客户端
-    let client = match Client::builder()
-        .timeout(Duration::from_secs(60))
-        .build()
-    {
-        Ok(c) => c,
-        Err(e) => {
-            return VideoTaskResult {
-                success: false,
-                task_id: None,
-                status: None,
-                progress: None,
-                error: Some(format!("创建 HTTP 客户端失败: {}", e)),
-            }
-        }
+#[tracing::instrument(name = "request", skip_all, fields(request_id = %request_key(params.request_id.as_deref())))]
+pub async fn video_create_task(app_handle: AppHandle, mut params: VideoCreateParams) -> VideoTaskResult {
+    params.api_key = match resolve_api_key(&app_handle, params.provider_id.as_deref(), &params.api_key).await {
+        Ok(api_key) => api_key,
+        Err(e) => return VideoTaskResult::failure(e, 0),
     };
+    let request_id = params.request_id.clone();
+    let canvas_id = params.canvas_id.clone();
+    let model = params.model.clone();
 
-    // 构建 multipart form
-    let mut form = reqwest::multipart::Form::new()
-        .text("model", params.model.clone())
-        .text("prompt", params.prompt.clone());
+    let result = RequestRegistry::from_app(&app_handle)
+        .run(request_id.as_deref(), create_task(HttpClientManager::from_app(&app_handle), params))
+        .await
+        .unwrap_or_else(|_| VideoTaskResult::failure(ApiError::cancelled(), 0));
 
-    if let Some(seconds) = params.seconds {
-        form = form.text("seconds", seconds);
+    if let Some(usage) = &result.usage {
+        record_usage(&app_handle, canvas_id.as_deref(), "Video", &model, usage);
     }
+    result
+}
 
-    if let Some(size) = params.size {
-        form = form.text("size", size);
-    }
+async fn create_task(http: HttpClientManager, mut params: VideoCreateParams) -> VideoTaskResult {
+    info!(model = %params.model, "video_create_task called");
 
-    // 添加参考图片
-    if let Some(image_base64) = params.input_image {
-        match BASE64.decode(&image_base64) {
-            Ok(image_bytes) => {
-                let part = reqwest::multipart::Part::bytes(image_bytes)
-                    .file_name("reference.png")
-                    .mime_str("image/png")
-                    .unwrap_or_else(|_| reqwest::multipart::Part::bytes(vec![]));
-                form = form.part("input_reference", part);
-            }
-            Err(e) => {
-                println!("[Rust] Failed to decode input image: {}", e);
-            }
+    // 解码参考图片并识别实际格式，超出大小限制时重新压缩（尺寸需与输出一致，不做缩放）
+    let prepared = match prepare_input_images(params.input_image.take().into_iter().collect(), ImageTarget::Video).await {
+        Ok(prepared) => prepared,
+        Err(e) => return VideoTaskResult::failure(e, 0),
+    };
+    let mut result = submit_task(http, params, prepared.images.into_iter().next()).await;
+    result.warnings = prepared.warnings;
+    result
+}
+
+async fn submit_task(http: HttpClientManager, params: VideoCreateParams, reference: Option<InputImage>) -> VideoTaskResult {
+    // 使用共享 HTTP 客户端
+    let client = http.client();
+    let timeout = http.timeout(TimeoutKey::VideoCreate);
+
+    let video_seconds = params.seconds.as_deref().and_then(|seconds| seconds.trim().parse::<u64>().ok());
+
+    let build_form = || {
+        let mut form = reqwest::multipart::Form::new()
+            .text("model", params.model.clone())
+            .text("prompt", params.prompt.clone());
+
+        if let Some(seconds) = &params.seconds {
+            form = form.text("seconds", seconds.clone());
         }
-    }
+
+        if let Some(size) = &params.size {
+            form = form.text("size", size.clone());
+        }
+
+        // 添加参考图片
+        if let Some(reference) = &reference {
+            let part = reqwest::multipart::Part::bytes(reference.bytes.clone())
+                .file_name(format!("reference.{}", reference.extension()))
+                .mime_str(reference.mime_type())
+                .unwrap_or_else(|_| reqwest::multipart::Part::bytes(vec![]));
+            form = form.part("input_reference", part);
+        }
+        form
+    };
 
     // 构建 URL
     let url = format!(
         "{}/v1/videos",
         params.base_url.trim_end_matches('/')
     );
-    println!("[Rust] Request URL: {}", url);
+    debug!("Request URL: {}", url);
 
     // 发送请求
-    println!("[Rust] Sending video create request...");
     let start_time = std::time::Instant::now();
 
-    let response = match client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", params.api_key))
-        .multipart(form)
-        .send()
-        .await
-    {
+    // 创建任务不是幂等操作，服务端可能已经创建了任务，不能盲目重试
+    let policy = params.retry.clone().unwrap_or_default();
+    let outcome = send_with_retry(&policy, Idempotency::NonIdempotent, "Video create", || {
+        client
+            .post(&url)
+            .timeout(timeout)
+            .header("Authorization", format!("Bearer {}", params.api_key))
+            .multipart(build_form())
+    })
+    .await;
+    let attempts = outcome.attempts;
+
+    let response = match outcome.result {
         Ok(r) => {
-            println!("[Rust] Response received in {:?}", start_time.elapsed());
+            info!(status = %r.status(), "Response received in {:?}", start_time.elapsed());
             r
         },
         Err(e) => {
-            println!("[Rust] Request failed: {}", e);
-            let error_msg = if e.is_timeout() {
-                "请求超时，请稍后重试".to_string()
-            } else if e.is_connect() {
-                "无法连接到服务器，请检查网络".to_string()
-            } else {
-                format!("请求失败: {}", e)
-            };
-            return VideoTaskResult {
-                success: false,
-                task_id: None,
-                status: None,
-                progress: None,
-                error: Some(error_msg),
-            };
+            warn!("Request failed: {}", e);
+            return VideoTaskResult::failure(ApiError::from_request_error(&e), attempts);
         }
     };
 
@@ -166,25 +241,14 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
     let response_text = match response.text().await {
         Ok(t) => t,
         Err(e) => {
-            return VideoTaskResult {
-                success: false,
-                task_id: None,
-                status: None,
-                progress: None,
-                error: Some(format!("获取响应失败: {}", e)),
-            };
+            let error = ApiError::new(ErrorCode::Network, format!("获取响应失败: {}", e));
+            return VideoTaskResult::failure(error, attempts);
         }
     };
 
     if !status.is_success() {
-        println!("[Rust] Error response: {}", response_text);
-        return VideoTaskResult {
-            success: false,
-            task_id: None,
-            status: None,
-            progress: None,
-            error: Some(format!("API 返回错误 ({}): {}", status, response_text)),
-        };
+        warn!("Error response: {}", response_text);
+        return VideoTaskResult::failure(ApiError::from_response(status, &response_text), attempts);
     }
 
     // 解析响应
@@ -192,40 +256,28 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
     let api_response: VideoApiResponse = match serde_json::from_str(&response_text) {
         Ok(r) => r,
         Err(e) => {
-            println!("[Rust] Failed to parse JSON: {}", e);
-            return VideoTaskResult {
-                success: false,
-                task_id: None,
-                status: None,
-                progress: None,
-                error: Some(format!("解析响应失败: {}", e)),
-            };
+            warn!("Failed to parse JSON: {}", e);
+            return VideoTaskResult::failure(ApiError::malformed(format!("解析响应失败: {}", e)), attempts);
         }
     };
 
     // 检查 API 错误
     if let Some(err) = api_response.error {
-        return VideoTaskResult {
-            success: false,
-            task_id: None,
-            status: None,
-            progress: None,
-            error: err.message,
-        };
+        return VideoTaskResult::failure(err.into_api_error(), attempts);
     }
 
     let task_id = api_response.id;
     if task_id.is_none() {
-        return VideoTaskResult {
-            success: false,
-            task_id: None,
-            status: None,
-            progress: None,
-            error: Some("API 未返回任务 ID".to_string()),
-        };
+        return VideoTaskResult::failure(ApiError::malformed("API 未返回任务 ID"), attempts);
     }
 
-    println!("[Rust] Video task created: {:?}", task_id);
+    info!("Video task created: {:?}", task_id);
+
+    // 任务创建成功即按请求的时长计费
+    let usage = TokenUsage {
+        video_seconds: video_seconds.unwrap_or(0),
+        ..Default::default()
+    };
 
     VideoTaskResult {
         success: true,
@@ -233,31 +285,34 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
         status: api_response.status,
         progress: api_response.progress,
         error: None,
+        attempts,
+        usage: Some(usage),
+        warnings: Vec::new(),
     }
 }
 
 // ==================== 获取视频任务状态 ====================
 
 #[tauri::command]
-pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
-    println!("[Rust] video_get_status called, task_id: {}", params.task_id);
-
-    // 创建 HTTP 客户端
-    let client = match Client::builder()
-        .timeout(Duration::from_secs(30))
-        .build()
-    {
-        Ok(c) => c,
-        Err(e) => {
-            return VideoTaskResult {
-                success: false,
-                task_id: None,
-                status: None,
-                progress: None,
-                error: Some(format!("创建 HTTP 客户端失败: {}", e)),
-            }
-        }
+#[tracing::instrument(name = "request", skip_all, fields(request_id = %request_key(params.request_id.as_deref())))]
+pub async fn video_get_status(app_handle: AppHandle, mut params: VideoStatusParams) -> VideoTaskResult {
+    params.api_key = match resolve_api_key(&app_handle, params.provider_id.as_deref(), &params.api_key).await {
+        Ok(api_key) => api_key,
+        Err(e) => return VideoTaskResult::failure(e, 0),
     };
+    let request_id = params.request_id.clone();
+    RequestRegistry::from_app(&app_handle)
+        .run(request_id.as_deref(), get_status(HttpClientManager::from_app(&app_handle), params))
+        .await
+        .unwrap_or_else(|_| VideoTaskResult::failure(ApiError::cancelled(), 0))
+}
+
+async fn get_status(http: HttpClientManager, params: VideoStatusParams) -> VideoTaskResult {
+    info!(task_id = %params.task_id, "video_get_status called");
+
+    // 使用共享 HTTP 客户端
+    let client = http.client();
+    let timeout = http.timeout(TimeoutKey::VideoStatus);
 
     // 构建 URL
     let url = format!(
@@ -266,13 +321,18 @@ pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
         params.task_id
     );
 
-    // 发送请求
-    let response = match client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", params.api_key))
-        .send()
-        .await
-    {
+    // 发送请求（查询状态可安全重试）
+    let policy = params.retry.clone().unwrap_or_default();
+    let outcome = send_with_retry(&policy, Idempotency::Idempotent, "Video status", || {
+        client
+            .get(&url)
+            .timeout(timeout)
+            .header("Authorization", format!("Bearer {}", params.api_key))
+    })
+    .await;
+    let attempts = outcome.attempts;
+
+    let response = match outcome.result {
         Ok(r) => r,
         Err(e) => {
             let error_msg = if e.is_timeout() {
@@ -282,13 +342,8 @@ pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
             } else {
                 format!("请求失败: {}", e)
             };
-            return VideoTaskResult {
-                success: false,
-                task_id: None,
-                status: None,
-                progress: None,
-                error: Some(error_msg),
-            };
+            let error = ApiError::from_request_error(&e).with_message(error_msg);
+            return VideoTaskResult::failure(error, attempts);
         }
     };
 
@@ -297,48 +352,28 @@ pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
     let response_text = match response.text().await {
         Ok(t) => t,
         Err(e) => {
-            return VideoTaskResult {
-                success: false,
-                task_id: None,
-                status: None,
-                progress: None,
-                error: Some(format!("获取响应失败: {}", e)),
-            };
+            let error = ApiError::new(ErrorCode::Network, format!("获取响应失败: {}", e));
+            return VideoTaskResult::failure(error, attempts);
         }
     };
 
     if !status.is_success() {
-        return VideoTaskResult {
-            success: false,
-            task_id: None,
-            status: None,
-            progress: None,
-            error: Some(format!("API 返回错误 ({}): {}", status, response_text)),
-        };
+        return VideoTaskResult::failure(ApiError::from_response(status, &response_text), attempts);
     }
 
     // 解析响应
     let api_response: VideoApiResponse = match serde_json::from_str(&response_text) {
         Ok(r) => r,
-        Err(e) => {
-            return VideoTaskResult {
-                success: false,
-                task_id: None,
-                status: None,
-                progress: None,
-                error: Some(format!("解析响应失败: {}", e)),
-            };
-        }
+        Err(e) => return VideoTaskResult::failure(ApiError::malformed(format!("解析响应失败: {}", e)), attempts),
     };
 
     // 检查 API 错误
     if let Some(err) = api_response.error {
         return VideoTaskResult {
-            success: false,
             task_id: Some(params.task_id),
             status: api_response.status,
             progress: api_response.progress,
-            error: err.message,
+            ..VideoTaskResult::failure(err.into_api_error(), attempts)
         };
     }
 
@@ -348,29 +383,34 @@ pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
         status: api_response.status,
         progress: api_response.progress,
         error: None,
+        attempts,
+        usage: None,
+        warnings: Vec::new(),
     }
 }
 
 // ==================== 获取视频内容 ====================
 
 #[tauri::command]
-pub async fn video_get_content(params: VideoStatusParams) -> VideoContentResult {
-    println!("[Rust] video_get_content called, task_id: {}", params.task_id);
-
-    // 创建 HTTP 客户端（视频下载可能需要更长时间）
-    let client = match Client::builder()
-        .timeout(Duration::from_secs(300))
-        .build()
-    {
-        Ok(c) => c,
-        Err(e) => {
-            return VideoContentResult {
-                success: false,
-                video_data: None,
-                error: Some(format!("创建 HTTP 客户端失败: {}", e)),
-            }
-        }
+#[tracing::instrument(name = "request", skip_all, fields(request_id = %request_key(params.request_id.as_deref())))]
+pub async fn video_get_content(app_handle: AppHandle, mut params: VideoStatusParams) -> VideoContentResult {
+    params.api_key = match resolve_api_key(&app_handle, params.provider_id.as_deref(), &params.api_key).await {
+        Ok(api_key) => api_key,
+        Err(e) => return VideoContentResult::failure(e, 0),
     };
+    let request_id = params.request_id.clone();
+    RequestRegistry::from_app(&app_handle)
+        .run(request_id.as_deref(), get_content(HttpClientManager::from_app(&app_handle), params))
+        .await
+        .unwrap_or_else(|_| VideoContentResult::failure(ApiError::cancelled(), 0))
+}
+
+async fn get_content(http: HttpClientManager, params: VideoStatusParams) -> VideoContentResult {
+    info!(task_id = %params.task_id, "video_get_content called");
+
+    // 使用共享 HTTP 客户端
+    let client = http.client();
+    let timeout = http.timeout(TimeoutKey::VideoContent);
 
     // 构建 URL
     let url = format!(
@@ -378,18 +418,23 @@ pub async fn video_get_content(params: VideoStatusParams) -> VideoContentResult
         params.base_url.trim_end_matches('/'),
         params.task_id
     );
-    println!("[Rust] Fetching video content from: {}", url);
+    debug!("Fetching video content from: {}", url);
 
     // 发送请求
     let start_time = std::time::Instant::now();
-    let response = match client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", params.api_key))
-        .send()
-        .await
-    {
+    let policy = params.retry.clone().unwrap_or_default();
+    let outcome = send_with_retry(&policy, Idempotency::Idempotent, "Video content", || {
+        client
+            .get(&url)
+            .timeout(timeout)
+            .header("Authorization", format!("Bearer {}", params.api_key))
+    })
+    .await;
+    let attempts = outcome.attempts;
+
+    let response = match outcome.result {
         Ok(r) => {
-            println!("[Rust] Response headers received in {:?}", start_time.elapsed());
+            info!(status = %r.status(), "Response headers received in {:?}", start_time.elapsed());
             r
         },
         Err(e) => {
@@ -400,11 +445,8 @@ pub async fn video_get_content(params: VideoStatusParams) -> VideoContentResult
             } else {
                 format!("请求失败: {}", e)
             };
-            return VideoContentResult {
-                success: false,
-                video_data: None,
-                error: Some(error_msg),
-            };
+            let error = ApiError::from_request_error(&e).with_message(error_msg);
+            return VideoContentResult::failure(error, attempts);
         }
     };
 
@@ -412,26 +454,20 @@ pub async fn video_get_content(params: VideoStatusParams) -> VideoContentResult
     let status = response.status();
     if !status.is_success() {
         let error_text = response.text().await.unwrap_or_default();
-        return VideoContentResult {
-            success: false,
-            video_data: None,
-            error: Some(format!("获取视频失败 ({}): {}", status, error_text)),
-        };
+        let error = ApiError::from_response(status, &error_text).with_message(format!("获取视频失败 ({}): {}", status, error_text));
+        return VideoContentResult::failure(error, attempts);
     }
 
     // 获取视频数据
     let video_bytes = match response.bytes().await {
         Ok(b) => b,
         Err(e) => {
-            return VideoContentResult {
-                success: false,
-                video_data: None,
-                error: Some(format!("下载视频失败: {}", e)),
-            };
+            let error = ApiError::new(ErrorCode::Network, format!("下载视频失败: {}", e));
+            return VideoContentResult::failure(error, attempts);
         }
     };
 
-    println!("[Rust] Video downloaded: {} bytes in {:?}", video_bytes.len(), start_time.elapsed());
+    info!("Video downloaded: {} bytes in {:?}", video_bytes.len(), start_time.elapsed());
 
     // 转换为 base64
     let video_base64 = BASE64.encode(&video_bytes);
@@ -440,5 +476,6 @@ pub async fn video_get_content(params: VideoStatusParams) -> VideoContentResult
         success: true,
         video_data: Some(video_base64),
         error: None,
+        attempts,
     }
 }
@@ -9,7 +9,7 @@ import { generateLLMContent } from "@/services/llmService";
 import { useLoadingDots } from "@/hooks/useLoadingDots";
 import { useLLMPresetModels } from "@/config/presetModels";
 import { ErrorDetailModal } from "@/components/ui/ErrorDetailModal";
-import type { LLMContentNodeData } from "@/types";
+import type { LLMContentNodeData, ReasoningEffort } from "@/types";
 
 // 定义节点类型
 type LLMContentNode = Node<LLMContentNodeData>;
@@ -141,6 +141,7 @@ export const LLMContentNode = memo(({ id, data, selected }: NodeProps<LLMContent
       status: "loading",
       error: undefined,
       outputContent: "",
+      outputThinking: undefined,
     });
 
     try {
@@ -160,12 +161,14 @@ export const LLMContentNode = memo(({ id, data, selected }: NodeProps<LLMContent
         temperature: data.temperature,
         maxTokens: data.maxTokens,
         files: allFiles.length > 0 ? allFiles : undefined,
+        reasoning: data.reasoningEffort ? { effort: data.reasoningEffort } : undefined,
       });
 
       if (response.content) {
         updateNodeDataWithCanvas(id, {
           status: "success",
           outputContent: response.content,
+          outputThinking: response.thinking,
           error: undefined,
           errorDetails: undefined,
         });
@@ -187,7 +190,7 @@ export const LLMContentNode = memo(({ id, data, selected }: NodeProps<LLMContent
         error: "生成失败",
       });
     }
-  }, [id, data.model, data.systemPrompt, data.temperature, data.maxTokens, updateNodeDataWithCanvas, getConnectedInputDataAsync]);
+  }, [id, data.model, data.systemPrompt, data.temperature, data.maxTokens, data.reasoningEffort, updateNodeDataWithCanvas, getConnectedInputDataAsync]);
 
   // 复制内容
   const handleCopy = useCallback(() => {
@@ -424,6 +427,13 @@ export const LLMContentNode = memo(({ id, data, selected }: NodeProps<LLMContent
             </div>
             {/* 弹窗内容 */}
             <div className="flex-1 overflow-y-auto p-6 select-text">
+              {/* 思考过程（默认折叠） */}
+              {data.outputThinking && (
+                <details className="mb-4 bg-base-200 rounded-lg">
+                  <summary className="px-3 py-2 text-sm text-base-content/60 cursor-pointer">思考过程</summary>
+                  <div className="px-3 pb-3 text-sm text-base-content/70 whitespace-pre-wrap">{data.outputThinking}</div>
+                </details>
+              )}
               <div className="prose prose-base max-w-none">
                 <ReactMarkdown
                   components={{
@@ -669,6 +679,24 @@ function LLMSettingsModal({ data, presetModels, onClose, onUpdateData }: LLMSett
               onChange={(e) => onUpdateData({ maxTokens: parseInt(e.target.value) || 8192 })}
             />
           </div>
+
+          {/* 推理强度 */}
+          <div>
+            <label className="text-sm font-medium text-base-content mb-2 block">推理强度</label>
+            <select
+              className="select select-sm select-bordered w-full"
+              value={data.reasoningEffort ?? ""}
+              onChange={(e) =>
+                onUpdateData({ reasoningEffort: (e.target.value || undefined) as ReasoningEffort | undefined })
+              }
+            >
+              <option value="">模型默认</option>
+              <option value="low">低</option>
+              <option value="medium">中</option>
+              <option value="high">高</option>
+            </select>
+            <p className="text-xs text-base-content/50 mt-1">仅对支持推理 / 扩展思考的模型生效，Claude 开启后忽略温度设置</p>
+          </div>
         </div>
 
         {/* 底部 */}
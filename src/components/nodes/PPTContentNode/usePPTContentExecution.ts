@@ -168,10 +168,10 @@ export function usePPTContentExecution({
           return;
         }
 
-        // 使用结构化输出后，返回的内容应该是有效的 JSON，但仍然验证以确保安全
+        // 后端已按 Schema 校验并解析时直接使用，否则在前端解析
         let outline: PPTOutline;
         try {
-          outline = JSON.parse(response.content) as PPTOutline;
+          outline = (response.parsed ?? JSON.parse(response.content)) as PPTOutline;
         } catch {
           // 如果解析失败，尝试使用原有的验证逻辑
           const validation = validateJsonOutput(response.content);
@@ -1,11 +1,12 @@
 import { useState, useEffect } from "react";
 
-import { X, Plus, Pencil, Trash2, Save, Server, AlertTriangle } from "lucide-react";
+import { X, Plus, Pencil, Trash2, Save, Server, AlertTriangle, PlugZap } from "lucide-react";
 import { useSettingsStore } from "@/stores/settingsStore";
 import { Select } from "@/components/ui/Select";
 import { Input } from "@/components/ui/Input";
 import { useModal, getModalAnimationClasses } from "@/hooks/useModal";
 import type { Provider, NodeProviderMapping, ProviderProtocol } from "@/types";
+import { testProviderConnection, type ProviderTestResult } from "@/services/modelService";
 
 // 协议类型配置
 const protocolConfig: { key: ProviderProtocol; label: string }[] = [
@@ -268,6 +269,8 @@ function ProviderEditModal({ provider, onSave, onClose }: ProviderEditModalProps
   const [apiKey, setApiKey] = useState(provider?.apiKey || "");
   const [baseUrl, setBaseUrl] = useState(provider?.baseUrl || "");
   const [protocol, setProtocol] = useState<ProviderProtocol>(provider?.protocol || "google");
+  const [testing, setTesting] = useState(false);
+  const [testResult, setTestResult] = useState<ProviderTestResult | null>(null);
 
   // 使用统一的 modal hook
   const { isVisible, isClosing, handleClose, handleBackdropClick } = useModal({
@@ -279,7 +282,33 @@ function ProviderEditModal({ provider, onSave, onClose }: ProviderEditModalProps
   const { backdropClasses, contentClasses } = getModalAnimationClasses(isVisible, isClosing);
 
   const isEditing = !!provider;
-  const canSave = name.trim() && apiKey.trim() && baseUrl.trim();
+  // Key 已保存在凭据库时可留空，表示不修改
+  const hasStoredKey = !!provider?.hasStoredKey;
+  const canSave = name.trim() && (apiKey.trim() || hasStoredKey) && baseUrl.trim();
+
+  // 使用表单中的值测试连接；Key 留空时使用凭据库中已保存的 Key
+  const handleTest = async () => {
+    if (!canSave) return;
+    setTesting(true);
+    setTestResult(null);
+    try {
+      const result = await testProviderConnection({
+        id: apiKey.trim() ? undefined : provider?.id,
+        apiKey: apiKey.trim(),
+        baseUrl: baseUrl.trim(),
+        protocol,
+      });
+      setTestResult(result);
+    } catch (error) {
+      setTestResult({
+        success: false,
+        reachable: false,
+        message: error instanceof Error ? error.message : String(error),
+      });
+    } finally {
+      setTesting(false);
+    }
+  };
 
   const handleSave = () => {
     if (!canSave) return;
@@ -365,7 +394,7 @@ function ProviderEditModal({ provider, onSave, onClose }: ProviderEditModalProps
             </label>
             <Input
               isPassword
-              placeholder="输入 API Key"
+              placeholder={hasStoredKey ? "已保存（留空则不修改）" : "输入 API Key"}
               value={apiKey}
               onChange={(e) => setApiKey(e.target.value)}
             />
@@ -387,10 +416,30 @@ function ProviderEditModal({ provider, onSave, onClose }: ProviderEditModalProps
               </span>
             </label>
           </div>
+
+          {/* 连接测试结果 */}
+          {testResult && (
+            <div className={`text-xs rounded-lg px-3 py-2 ${testResult.success ? "bg-success/10 text-success" : "bg-error/10 text-error"}`}>
+              <div>{testResult.message}</div>
+              {testResult.responseBody && (
+                <pre className="mt-1 max-h-24 overflow-auto whitespace-pre-wrap break-all text-base-content/60">
+                  {testResult.responseBody}
+                </pre>
+              )}
+            </div>
+          )}
         </div>
 
         {/* 底部 */}
         <div className="flex items-center justify-end gap-2 px-5 py-3 border-t border-base-300 bg-base-200/50">
+          <button
+            className="btn btn-ghost btn-sm gap-1 mr-auto"
+            onClick={handleTest}
+            disabled={!canSave || testing}
+          >
+            {testing ? <span className="loading loading-spinner loading-xs" /> : <PlugZap className="w-4 h-4" />}
+            测试连接
+          </button>
           <button className="btn btn-ghost btn-sm" onClick={handleClose}>
             取消
           </button>
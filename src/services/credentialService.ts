@@ -0,0 +1,38 @@
+import { invokeCommand } from "@/utils/apiError";
+
+// 已保存在后端凭据库中的 Key 摘要
+export interface StoredKeyInfo {
+  providerId: string;
+  hint: string; // Key 末尾几位
+  updatedAt: number;
+}
+
+// 检测是否在 Tauri 环境中（Web 模式没有凭据库，Key 仍保存在前端）
+export const isCredentialVaultAvailable = () => {
+  return typeof window !== "undefined" && "__TAURI_INTERNALS__" in window;
+};
+
+// 保存供应商的 API Key（已存在时覆盖）
+export async function setApiKey(providerId: string, apiKey: string): Promise<void> {
+  await invokeCommand("set_api_key", { providerId, apiKey });
+}
+
+// 轮换供应商的 API Key（要求已保存过）
+export async function rotateApiKey(providerId: string, apiKey: string): Promise<void> {
+  await invokeCommand("rotate_api_key", { providerId, apiKey });
+}
+
+// 删除供应商的 API Key，返回是否存在
+export async function deleteApiKey(providerId: string): Promise<boolean> {
+  return await invokeCommand<boolean>("delete_api_key", { providerId });
+}
+
+// 列出已保存 Key 的供应商
+export async function listApiKeys(): Promise<StoredKeyInfo[]> {
+  return await invokeCommand<StoredKeyInfo[]>("list_api_keys");
+}
+
+// 请求中携带的供应商 ID：只有 Key 保存在凭据库时才传，后端按 ID 找不到 Key 会直接报错
+export function vaultProviderId(provider: { id: string; hasStoredKey?: boolean }): string | undefined {
+  return provider.hasStoredKey ? provider.id : undefined;
+}
@@ -3,8 +3,8 @@
  * 使用 Tauri 命令将图片存储为独立文件，而不是 base64 存储在 IndexedDB 中
  */
 
-import { invoke } from "@tauri-apps/api/core";
 import { convertFileSrc } from "@tauri-apps/api/core";
+import { invokeCommand } from "@/utils/apiError";
 
 // 图片类型枚举
 export type ImageType = "input" | "generated";
@@ -73,7 +73,7 @@ export async function saveImage(
   inputImages?: InputImageInfo[],
   imageType?: ImageType
 ): Promise<ImageInfo> {
-  return await invoke<ImageInfo>("save_image", {
+  return await invokeCommand<ImageInfo>("save_image", {
     base64Data,
     canvasId,
     nodeId,
@@ -89,7 +89,7 @@ export async function saveImage(
  * @returns base64 编码的图片数据
  */
 export async function readImage(path: string): Promise<string> {
-  return await invoke<string>("read_image", { path });
+  return await invokeCommand<string>("read_image", { path });
 }
 
 /**
@@ -107,7 +107,7 @@ export function getImageUrl(path: string): string {
  * @param path - 图片文件路径
  */
 export async function deleteImage(path: string): Promise<void> {
-  await invoke("delete_image", { path });
+  await invokeCommand("delete_image", { path });
 }
 
 /**
@@ -116,7 +116,7 @@ export async function deleteImage(path: string): Promise<void> {
  * @returns 删除的总大小（字节）
  */
 export async function deleteCanvasImages(canvasId: string): Promise<number> {
-  return await invoke<number>("delete_canvas_images", { canvasId });
+  return await invokeCommand<number>("delete_canvas_images", { canvasId });
 }
 
 /**
@@ -124,7 +124,7 @@ export async function deleteCanvasImages(canvasId: string): Promise<number> {
  * @returns 存储统计数据
  */
 export async function getStorageStats(): Promise<StorageStats> {
-  return await invoke<StorageStats>("get_storage_stats");
+  return await invokeCommand<StorageStats>("get_storage_stats");
 }
 
 /**
@@ -132,7 +132,7 @@ export async function getStorageStats(): Promise<StorageStats> {
  * @returns 清理的大小（字节）
  */
 export async function clearCache(): Promise<number> {
-  return await invoke<number>("clear_cache");
+  return await invokeCommand<number>("clear_cache");
 }
 
 /**
@@ -140,7 +140,7 @@ export async function clearCache(): Promise<number> {
  * @returns 清理的大小（字节）
  */
 export async function clearAllImages(): Promise<number> {
-  return await invoke<number>("clear_all_images");
+  return await invokeCommand<number>("clear_all_images");
 }
 
 /**
@@ -148,7 +148,7 @@ export async function clearAllImages(): Promise<number> {
  * @returns 存储目录路径
  */
 export async function getStoragePath(): Promise<string> {
-  return await invoke<string>("get_storage_path");
+  return await invokeCommand<string>("get_storage_path");
 }
 
 /**
@@ -157,7 +157,7 @@ export async function getStoragePath(): Promise<string> {
  * @returns 图片信息列表（包含元数据）
  */
 export async function listCanvasImages(canvasId: string): Promise<ImageInfoWithMetadata[]> {
-  return await invoke<ImageInfoWithMetadata[]>("list_canvas_images", { canvasId });
+  return await invokeCommand<ImageInfoWithMetadata[]>("list_canvas_images", { canvasId });
 }
 
 /**
@@ -166,7 +166,7 @@ export async function listCanvasImages(canvasId: string): Promise<ImageInfoWithM
  * @returns 图片元数据（如果存在）
  */
 export async function readImageMetadata(imagePath: string): Promise<ImageMetadata | null> {
-  return await invoke<ImageMetadata | null>("read_image_metadata", { imagePath });
+  return await invokeCommand<ImageMetadata | null>("read_image_metadata", { imagePath });
 }
 
 /**
@@ -2,9 +2,12 @@ import { GoogleGenAI } from "@google/genai";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import { isPermissionGranted, requestPermission, sendNotification } from '@tauri-apps/plugin-notification';
-import type { ImageGenerationParams, ImageEditParams, GenerationResponse, ProviderProtocol, ErrorDetails } from "@/types";
+import type { ImageGenerationParams, ImageEditParams, GenerationResponse, ProviderProtocol, ErrorDetails, GeneratedImage, GenerationCandidate, GenerationBlock, ApiError } from "@/types";
 import { useSettingsStore } from "@/stores/settingsStore";
 import { LEMON_API_CONFIG, PROXY_PATH } from "@/config/lemonApi";
+import { getActiveCanvasId } from "@/services/usageService";
+import { vaultProviderId } from "@/services/credentialService";
+import { toCommandError } from "@/utils/apiError";
 
 // 图片节点类型
 type ImageNodeType = "imageGeneratorPro" | "imageGeneratorFast";
@@ -60,7 +63,7 @@ function getProviderConfig(nodeType: ImageNodeType) {
     throw new Error("供应商不存在，请重新配置");
   }
 
-  if (!provider.apiKey) {
+  if (!provider.apiKey && !provider.hasStoredKey) {
     throw new Error("供应商 API Key 未配置");
   }
 
@@ -84,11 +87,13 @@ function createClient(nodeType: ImageNodeType) {
 interface TauriGeminiParams {
   baseUrl: string;
   apiKey: string;
+  providerId?: string; // 凭据库中的供应商 ID，Key 已保存时 apiKey 为空
   model: string;
   prompt: string;
   inputImages?: string[];
   aspectRatio?: string;
   imageSize?: string;
+  candidateCount?: number; // 候选数量，默认 1
 }
 
 // Tauri 后端代理响应
@@ -96,11 +101,80 @@ interface TauriGeminiResult {
   success: boolean;
   imageData?: string;
   text?: string;
-  error?: string;
+  thinking?: string;                   // 第一个候选的思考摘要
+  images?: GeneratedImage[];           // 所有候选返回的全部图片
+  candidates?: GenerationCandidate[];  // 按候选分组的图片与文本
+  error?: ApiError;
+  block?: GenerationBlock;             // 被拦截时的结构化原因
+  attempts?: number; // 实际请求次数（含重试）
+  warnings?: string[]; // 输入图片被缩放或重新压缩等提示
 }
 
 
 
+// 将后端图片生成结果（Gemini 与 OpenAI Images 结构相同）转换为前端响应
+function toGenerationResponse(
+  result: TauriGeminiResult,
+  context: { model: string; provider: string; requestUrl: string; requestBody: Record<string, unknown> }
+): GenerationResponse {
+  if (!result.success) {
+    const errorMessage = result.error?.message || "请求失败";
+
+    // 构建详细错误信息
+    const errorDetails: ErrorDetails = {
+      name: "API_Error",
+      message: errorMessage,
+      code: result.error?.code,
+      retryable: result.error?.retryable,
+      providerErrorType: result.error?.providerErrorType,
+      statusCode: result.error?.status,
+      timestamp: new Date().toISOString(),
+      model: context.model,
+      provider: context.provider,
+      requestUrl: context.requestUrl,
+      requestBody: context.requestBody,
+      block: result.block,
+    };
+
+    // 尝试提取状态码
+    const statusCodeMatch = errorMessage.match(/\((\d{3})\)/);
+    if (!errorDetails.statusCode && statusCodeMatch) {
+      errorDetails.statusCode = parseInt(statusCodeMatch[1], 10);
+    }
+
+    // 尝试提取响应内容
+    const responseMatch = errorMessage.match(/API 返回错误\s*\(\d{3}\)[：:]\s*([\s\S]*)/);
+    if (responseMatch) {
+      const responseContent = responseMatch[1].trim();
+      try {
+        errorDetails.responseBody = JSON.parse(responseContent);
+      } catch {
+        if (responseContent) {
+          errorDetails.responseBody = responseContent;
+        }
+      }
+    }
+
+    return {
+      error: errorMessage,
+      errorDetails,
+    };
+  }
+
+  if (result.warnings?.length) {
+    console.warn("[imageService] 输入图片提示:", result.warnings);
+  }
+
+  return {
+    imageData: result.imageData,
+    text: result.text,
+    thinking: result.thinking,
+    images: result.images,
+    candidates: result.candidates,
+    warnings: result.warnings,
+  };
+}
+
 // 通过 Tauri 后端代理发送请求
 async function invokeGemini(params: TauriGeminiParams, provider?: { name: string; protocol: string }): Promise<GenerationResponse> {
   console.log("[imageService] invokeGemini called, sending to Tauri backend...");
@@ -125,55 +199,20 @@ async function invokeGemini(params: TauriGeminiParams, provider?: { name: string
 
   try {
     const startTime = Date.now();
-    const result = await invoke<TauriGeminiResult>("gemini_generate_content", { params });
+    const result = await invoke<TauriGeminiResult>("gemini_generate_content", {
+      params: { ...params, canvasId: getActiveCanvasId() },
+    });
     const elapsed = Date.now() - startTime;
 
     console.log("[imageService] Tauri backend response received in", elapsed, "ms");
     console.log("[imageService] result:", { success: result.success, hasImage: !!result.imageData, error: result.error });
 
-    if (!result.success) {
-      const errorMessage = result.error || "请求失败";
-
-      // 构建详细错误信息
-      const errorDetails: ErrorDetails = {
-        name: "API_Error",
-        message: errorMessage,
-        timestamp: new Date().toISOString(),
-        model: params.model,
-        provider: provider?.name || "未知",
-        requestUrl: fullRequestUrl,
-        requestBody,
-      };
-
-      // 尝试提取状态码
-      const statusCodeMatch = errorMessage.match(/\((\d{3})\)/);
-      if (statusCodeMatch) {
-        errorDetails.statusCode = parseInt(statusCodeMatch[1], 10);
-      }
-
-      // 尝试提取响应内容
-      const responseMatch = errorMessage.match(/API 返回错误\s*\(\d{3}\)[：:]\s*([\s\S]*)/);
-      if (responseMatch) {
-        const responseContent = responseMatch[1].trim();
-        try {
-          errorDetails.responseBody = JSON.parse(responseContent);
-        } catch {
-          if (responseContent) {
-            errorDetails.responseBody = responseContent;
-          }
-        }
-      }
-
-      return {
-        error: errorMessage,
-        errorDetails,
-      };
-    }
-
-    return {
-      imageData: result.imageData,
-      text: result.text,
-    };
+    return toGenerationResponse(result, {
+      model: params.model,
+      provider: provider?.name || "未知",
+      requestUrl: fullRequestUrl,
+      requestBody,
+    });
   } catch (error) {
     console.error("[imageService] Tauri invoke error:", error);
     const message = error instanceof Error ? error.message : String(error);
@@ -196,11 +235,83 @@ async function invokeGemini(params: TauriGeminiParams, provider?: { name: string
   }
 }
 
+// OpenAI Images API 请求参数（有输入图片时调用 /v1/images/edits）
+interface TauriOpenAIImageParams {
+  baseUrl: string;
+  apiKey: string;
+  providerId?: string;
+  model: string;
+  prompt: string;
+  inputImages?: string[];
+  mask?: string;
+  n?: number;
+  size?: string;
+  quality?: string;
+  background?: string;
+}
+
+// 将节点的宽高比映射到 gpt-image 支持的尺寸
+function openAIImageSize(aspectRatio?: ImageGenerationParams["aspectRatio"]): string {
+  if (!aspectRatio || aspectRatio === "1:1") return "1024x1024";
+  const [width, height] = aspectRatio.split(":").map(Number);
+  return width > height ? "1536x1024" : "1024x1536";
+}
+
+// 通过 Tauri 后端调用 OpenAI Images API（返回结构与 Gemini 相同）
+async function invokeOpenAIImage(params: TauriOpenAIImageParams, provider: { name: string }): Promise<GenerationResponse> {
+  console.log("[imageService] invokeOpenAIImage called:", { ...params, inputImages: params.inputImages?.length || 0, mask: !!params.mask, apiKey: "***" });
+
+  const isEdit = !!(params.inputImages && params.inputImages.length > 0);
+  const requestUrl = `${params.baseUrl}/v1/images/${isEdit ? "edits" : "generations"}`;
+  const requestBody = {
+    model: params.model,
+    prompt: params.prompt.slice(0, 500),
+    n: params.n,
+    size: params.size,
+    quality: params.quality,
+    background: params.background,
+    inputImagesCount: params.inputImages?.length || 0,
+    hasMask: !!params.mask,
+  };
+
+  try {
+    const result = await invoke<TauriGeminiResult>("openai_image_generate", {
+      params: { ...params, canvasId: getActiveCanvasId() },
+    });
+    return toGenerationResponse(result, { model: params.model, provider: provider.name, requestUrl, requestBody });
+  } catch (error) {
+    console.error("[imageService] Tauri invoke error:", error);
+    const message = error instanceof Error ? error.message : String(error);
+    const errorDetails: ErrorDetails = {
+      name: error instanceof Error ? error.name : "Error",
+      message,
+      stack: error instanceof Error ? error.stack : undefined,
+      timestamp: new Date().toISOString(),
+      model: params.model,
+      provider: provider.name,
+      requestUrl,
+      requestBody,
+    };
+    return { error: message, errorDetails };
+  }
+}
+
+// Rust 后端推送的流式事件（stream://{channelId}）
+type TauriStreamEvent =
+  | { type: "textDelta"; text: string }
+  | { type: "reasoningDelta"; text: string }
+  | { type: "imageUrl"; url: string }
+  | { type: "finish"; reason?: string }
+  | { type: "usage"; inputTokens?: number; outputTokens?: number; cachedTokens?: number }
+  | { type: "warning"; message: string }
+  | { type: "error"; message: string }
+  | { type: "cancelled" };
+
 // 专门用于处理 Lemon API 的图像生成（通过 OpenAI Chat 接口返回 Markdown 图片）
 // 专门用于处理 Lemon API 的图像生成（通过 OpenAI Chat 接口返回 Markdown 图片）
 async function invokeLemonImageGeneration(
   params: { prompt: string; inputImages?: string[]; model: string },
-  provider: { baseUrl: string; apiKey: string },
+  provider: { id: string; baseUrl: string; apiKey: string },
   onProgress?: (text: string) => void
 ): Promise<GenerationResponse> {
   console.log("[imageService] 调用 Lemon API 进行生图 (Streaming)...");
@@ -212,22 +323,24 @@ async function invokeLemonImageGeneration(
     let accumulatedText = "";
 
     return new Promise(async (resolve) => {
-      // 1. 设置事件监听
+      // 1. 设置事件监听（SSE 已由 Rust 后端解析为类型化事件）
       let unlistenData: (() => void) | undefined;
-      let unlistenError: (() => void) | undefined;
       let unlistenDone: (() => void) | undefined;
-      let buffer = ""; // 添加缓冲区处理跨包数据
+      let imageUrl: string | undefined;
+      let streamError: string | undefined;
 
       const cleanup = () => {
         unlistenData?.();
-        unlistenError?.();
         unlistenDone?.();
       };
 
       const finish = async () => {
-        const match = accumulatedText.match(/!\[.*?\]\((.*?)\)/);
-        if (match && match[1]) {
-          let imageData = match[1];
+        if (streamError) {
+          resolve({ error: `流式传输中断: ${streamError}`, text: accumulatedText });
+          return;
+        }
+        if (imageUrl) {
+          let imageData = imageUrl;
           if (imageData.startsWith("data:")) imageData = imageData.split(",")[1];
 
           // 发送原生通知
@@ -253,43 +366,33 @@ async function invokeLemonImageGeneration(
         }
       };
 
-      unlistenData = await listen<string>(`stream://${channelId}`, (event) => {
-        const chunk = event.payload;
-        buffer += chunk; // 追加到缓冲区
-
-        const lines = buffer.split("\n");
-        buffer = lines.pop() || ""; // 保留最后一个不完整的行
-
-        for (const line of lines) {
-          const trimmed = line.trim();
-          if (!trimmed || trimmed === "data: [DONE]") continue;
-
-          if (trimmed.startsWith("data: ")) {
-            try {
-              const jsonStr = trimmed.slice(6);
-              const data = JSON.parse(jsonStr);
-              const delta = data.choices?.[0]?.delta;
-              if (delta) {
-                const reasoning = delta.reasoning_content || "";
-                const content = delta.content || "";
-                if (reasoning) accumulatedText += `[Thinking] ${reasoning}`;
-                if (content) accumulatedText += content;
-                onProgress?.(accumulatedText);
-              }
-            } catch (e) {
-              // JSON parse error usually means incomplete chunk in line, but we split by \n so it should be a full frame. 
-              // However, sometimes data can be malformed.
-            }
-          }
+      unlistenData = await listen<TauriStreamEvent>(`stream://${channelId}`, (event) => {
+        const payload = event.payload;
+        switch (payload.type) {
+          case "reasoningDelta":
+            accumulatedText += `[Thinking] ${payload.text}`;
+            onProgress?.(accumulatedText);
+            break;
+          case "textDelta":
+            accumulatedText += payload.text;
+            onProgress?.(accumulatedText);
+            break;
+          case "imageUrl":
+            if (!imageUrl) imageUrl = payload.url;
+            break;
+          case "error":
+            console.error("[imageService] Rust stream error:", payload.message);
+            streamError = payload.message;
+            break;
+          case "warning":
+            console.warn("[imageService] Rust stream warning:", payload.message);
+            break;
+          case "cancelled":
+            streamError = "请求已取消";
+            break;
         }
       });
 
-      unlistenError = await listen<string>(`stream-error://${channelId}`, (event) => {
-        console.error("[imageService] Rust stream error:", event.payload);
-        cleanup();
-        resolve({ error: `流式传输中断: ${event.payload}` });
-      });
-
       unlistenDone = await listen<void>(`stream-done://${channelId}`, () => {
         console.log("[imageService] Rust stream done");
         cleanup();
@@ -303,13 +406,15 @@ async function invokeLemonImageGeneration(
             ...params,
             baseUrl: provider.baseUrl,
             apiKey: provider.apiKey,
-            channelId
+            providerId: vaultProviderId(provider),
+            channelId,
+            canvasId: getActiveCanvasId()
           }
         });
       } catch (err) {
         console.error("[imageService] Failed to invoke Rust command:", err);
         cleanup();
-        resolve({ error: `启动请求失败: ${err}` });
+        resolve({ error: `启动请求失败: ${toCommandError(err).message}` });
       }
     });
   }
@@ -449,14 +554,34 @@ export async function generateImage(
         }, provider, onProgress);
       }
 
+      // 其他 OpenAI 兼容供应商使用 Images API
+      if (provider.protocol === "openai") {
+        return await invokeOpenAIImage(
+          {
+            baseUrl: provider.baseUrl.replace(/\/+$/, ""),
+            apiKey: provider.apiKey,
+            providerId: vaultProviderId(provider),
+            model: params.model,
+            prompt: params.prompt,
+            n: params.candidateCount,
+            size: openAIImageSize(params.aspectRatio),
+            quality: params.quality,
+            background: params.background,
+          },
+          provider
+        );
+      }
+
       return await invokeGemini(
         {
           baseUrl: apiBaseUrl,
           apiKey: provider.apiKey,
+          providerId: vaultProviderId(provider),
           model: params.model,
           prompt: params.prompt,
           aspectRatio: params.aspectRatio || "1:1",
           imageSize: isPro ? params.imageSize : undefined,
+          candidateCount: params.candidateCount,
         },
         { name: provider.name, protocol: provider.protocol }
       );
@@ -541,15 +666,37 @@ export async function editImage(
         }, provider, onProgress);
       }
 
+      // 其他 OpenAI 兼容供应商使用 Images API 编辑接口
+      if (provider.protocol === "openai") {
+        return await invokeOpenAIImage(
+          {
+            baseUrl: provider.baseUrl.replace(/\/+$/, ""),
+            apiKey: provider.apiKey,
+            providerId: vaultProviderId(provider),
+            model: params.model,
+            prompt: params.prompt,
+            inputImages: params.inputImages,
+            mask: params.mask,
+            n: params.candidateCount,
+            size: openAIImageSize(params.aspectRatio),
+            quality: params.quality,
+            background: params.background,
+          },
+          provider
+        );
+      }
+
       return await invokeGemini(
         {
           baseUrl: apiBaseUrl,
           apiKey: provider.apiKey,
+          providerId: vaultProviderId(provider),
           model: params.model,
           prompt: params.prompt,
           inputImages: params.inputImages,
           aspectRatio: params.aspectRatio || "1:1",
           imageSize: isPro ? params.imageSize : undefined,
+          candidateCount: params.candidateCount,
         },
         { name: provider.name, protocol: provider.protocol }
       );
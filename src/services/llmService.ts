@@ -1,7 +1,11 @@
 import { invoke } from "@tauri-apps/api/core";
-import type { LLMModelType, Provider, ErrorDetails } from "@/types";
+import { listen } from "@tauri-apps/api/event";
+import type { LLMModelType, Provider, ErrorDetails, ApiError, ReasoningEffort } from "@/types";
 import { useSettingsStore } from "@/stores/settingsStore";
 import { LEMON_API_CONFIG, PROXY_PATH } from "@/config/lemonApi";
+import { getActiveCanvasId, type TokenUsage } from "@/services/usageService";
+import { vaultProviderId } from "@/services/credentialService";
+import { CommandError, toCommandError } from "@/utils/apiError";
 
 // LLM 节点类型
 type LLMNodeType = "llm" | "llmContent";
@@ -11,20 +15,67 @@ const isTauri = () => {
   return typeof window !== "undefined" && "__TAURI_INTERNALS__" in window;
 };
 
+// 工具调用
+export interface LLMToolCall {
+  id: string;
+  name: string;
+  arguments: unknown;
+}
+
+// 工具定义（parameters 为 JSON Schema）
+export interface LLMToolDefinition {
+  name: string;
+  description?: string;
+  parameters: Record<string, unknown>;
+}
+
+export type LLMToolChoice =
+  | { type: "auto" }
+  | { type: "none" }
+  | { type: "required" }
+  | { type: "tool"; name: string };
+
+// 历史对话中的一轮消息
+export interface LLMChatMessage {
+  role: "user" | "assistant" | "tool";
+  content: string;
+  files?: Array<{ data: string; mimeType: string; fileName?: string }>;
+  toolCalls?: LLMToolCall[]; // 助手消息中的工具调用
+  toolCallId?: string; // 工具结果对应的调用 id
+  toolName?: string; // 工具结果对应的工具名
+}
+
+// 推理 / 扩展思考设置：OpenAI 使用 effort，Claude 与 Gemini 使用 token 预算（未指定时互相换算）
+export interface LLMReasoning {
+  effort?: ReasoningEffort;
+  budgetTokens?: number; // 0 表示关闭思考
+}
+
 // LLM 生成参数
 export interface LLMGenerationParams {
   prompt: string;
+  messages?: LLMChatMessage[]; // 历史对话（多轮），prompt 作为本轮用户输入追加在最后
   model: LLMModelType;
   systemPrompt?: string;
   temperature?: number;
   maxTokens?: number;
   files?: Array<{ data: string; mimeType: string; fileName?: string }>; // 文件数据（base64）
   responseJsonSchema?: Record<string, unknown>; // 结构化输出的 JSON Schema
+  tools?: LLMToolDefinition[];
+  toolChoice?: LLMToolChoice;
+  documentMode?: "native" | "extractText"; // PDF 附件发送方式：原生文档或本地提取文本
+  reasoning?: LLMReasoning;
+  onProgress?: (text: string) => void; // 传入时使用流式输出，回调累计的正文（不支持工具调用）
 }
 
 // LLM 响应
 export interface LLMResponse {
   content?: string;
+  parsed?: unknown; // 结构化输出：后端提取并通过 Schema 校验的 JSON
+  thinking?: string; // 思考过程或推理摘要，不包含在 content 中
+  toolCalls?: LLMToolCall[];
+  usage?: TokenUsage;
+  warnings?: string[]; // 被忽略或降级处理的附件
   error?: string;
   errorDetails?: ErrorDetails;  // 详细错误信息
 }
@@ -33,20 +84,32 @@ export interface LLMResponse {
 interface TauriLLMParams {
   baseUrl: string;
   apiKey: string;
+  providerId?: string; // 凭据库中的供应商 ID，Key 已保存时 apiKey 为空
   model: string;
   prompt: string;
+  messages?: LLMChatMessage[];
   systemPrompt?: string;
   temperature?: number;
   maxTokens?: number;
   files?: Array<{ data: string; mimeType: string; fileName?: string }>; // 文件数据（base64）
   responseJsonSchema?: Record<string, unknown>; // 结构化输出的 JSON Schema
+  tools?: LLMToolDefinition[];
+  toolChoice?: LLMToolChoice;
+  documentMode?: "native" | "extractText"; // PDF 附件发送方式：原生文档或本地提取文本
+  reasoning?: LLMReasoning;
 }
 
 // Tauri 后端响应
 interface TauriLLMResult {
   success: boolean;
   content?: string;
-  error?: string;
+  toolCalls?: LLMToolCall[];
+  parsed?: unknown;
+  thinking?: string;
+  error?: ApiError;
+  attempts?: number; // 实际请求次数（含重试）
+  usage?: TokenUsage;
+  warnings?: string[];
 }
 
 // 获取供应商配置
@@ -75,27 +138,13 @@ function getProviderConfig(nodeType: LLMNodeType) {
     throw new Error("供应商不存在，请重新配置");
   }
 
-  if (!provider.apiKey) {
+  if (!provider.apiKey && !provider.hasStoredKey) {
     throw new Error("供应商 API Key 未配置");
   }
 
   return provider;
 }
 
-// 根据协议获取对应的 Tauri 命令名称
-function getCommandByProtocol(protocol: string): string {
-  switch (protocol) {
-    case "google":
-      return "gemini_generate_text";
-    case "openai":
-      return "openai_chat_completion";
-    case "claude":
-      return "claude_chat_completion";
-    default:
-      return "gemini_generate_text";
-  }
-}
-
 // 根据协议获取正确的 baseUrl
 function getBaseUrlByProtocol(baseUrl: string, protocol: string): string {
   const cleanUrl = baseUrl.replace(/\/+$/, "");
@@ -126,11 +175,15 @@ function buildErrorDetails(
   // 解析错误消息，尝试提取状态码
   const message = isError ? error.message : String(error);
   const statusCodeMatch = message.match(/\((\d{3})\)/);
-  const statusCode = statusCodeMatch ? parseInt(statusCodeMatch[1], 10) : undefined;
+  const commandError = error instanceof CommandError ? error : undefined;
+  const statusCode = commandError?.status ?? (statusCodeMatch ? parseInt(statusCodeMatch[1], 10) : undefined);
 
   return {
     name: isError ? error.name : "Error",
     message,
+    code: commandError?.code,
+    retryable: commandError?.retryable,
+    providerErrorType: commandError?.providerErrorType,
     stack: isError ? error.stack : undefined,
     cause: isError && "cause" in error ? error.cause : undefined,
     statusCode,
@@ -150,9 +203,7 @@ async function invokeLLMByProtocol(params: TauriLLMParams, provider: Provider):
   // Let's use INSERT strategy for invokeWebLLM and REPLACE strategy for generate functions.
   // Actually, I will replace the generateText function block.
   // First, let's insert invokeWebLLM.
-  const command = getCommandByProtocol(protocol);
-
-  console.log(`[llmService] invokeLLMByProtocol called, protocol: ${protocol}, command: ${command}`);
+  console.log(`[llmService] invokeLLMByProtocol called, protocol: ${protocol}`);
 
   // 根据协议构建完整的请求 URL
   let fullRequestUrl = params.baseUrl;
@@ -177,17 +228,21 @@ async function invokeLLMByProtocol(params: TauriLLMParams, provider: Provider):
 
   try {
     const startTime = Date.now();
-    const result = await invoke<TauriLLMResult>(command, { params });
+    // 统一的对话命令，由 Rust 后端按协议分发
+    const result = await invoke<TauriLLMResult>("chat_completion", {
+      protocol,
+      params: { ...params, canvasId: getActiveCanvasId() },
+    });
     const elapsed = Date.now() - startTime;
 
     console.log("[llmService] Tauri backend response received in", elapsed, "ms");
 
     if (!result.success) {
-      const errorMessage = result.error || "请求失败";
+      const error = toCommandError(result.error, "请求失败");
 
       return {
-        error: errorMessage,
-        errorDetails: buildErrorDetails(new Error(errorMessage), {
+        error: error.message,
+        errorDetails: buildErrorDetails(error, {
           model: params.model,
           provider: provider.name,
           requestUrl: fullRequestUrl,
@@ -196,7 +251,18 @@ async function invokeLLMByProtocol(params: TauriLLMParams, provider: Provider):
       };
     }
 
-    return { content: result.content };
+    if (result.warnings?.length) {
+      console.warn("[llmService] 附件提示:", result.warnings);
+    }
+
+    return {
+      content: result.content,
+      parsed: result.parsed,
+      thinking: result.thinking,
+      toolCalls: result.toolCalls,
+      usage: result.usage,
+      warnings: result.warnings,
+    };
   } catch (error) {
     console.error("[llmService] Tauri invoke error:", error);
     const message = error instanceof Error ? error.message : String(error);
@@ -212,6 +278,114 @@ async function invokeLLMByProtocol(params: TauriLLMParams, provider: Provider):
   }
 }
 
+// Rust 后端推送的流式事件（stream://{channelId}）
+type TauriStreamEvent =
+  | { type: "textDelta"; text: string }
+  | { type: "reasoningDelta"; text: string }
+  | { type: "imageUrl"; url: string }
+  | { type: "finish"; reason?: string }
+  | { type: "usage"; inputTokens?: number; outputTokens?: number; cachedTokens?: number }
+  | { type: "warning"; message: string }
+  | { type: "error"; message: string }
+  | { type: "cancelled" };
+
+// 通过 Tauri 后端流式发送 LLM 请求，正文增量通过 onProgress 回调
+async function invokeLLMStream(
+  params: TauriLLMParams,
+  provider: Provider,
+  onProgress: (text: string) => void
+): Promise<LLMResponse> {
+  const protocol = provider.protocol || "google";
+  const channelId = crypto.randomUUID();
+  console.log(`[llmService] invokeLLMStream called, protocol: ${protocol}`);
+
+  let content = "";
+  let thinking = "";
+  let streamError: string | undefined;
+  const warnings: string[] = [];
+  const usage: TokenUsage = { inputTokens: 0, outputTokens: 0, cachedTokens: 0, images: 0, videoSeconds: 0 };
+
+  return new Promise(async (resolve) => {
+    let unlistenData: (() => void) | undefined;
+    let unlistenDone: (() => void) | undefined;
+    const cleanup = () => {
+      unlistenData?.();
+      unlistenDone?.();
+    };
+
+    unlistenData = await listen<TauriStreamEvent>(`stream://${channelId}`, (event) => {
+      const payload = event.payload;
+      switch (payload.type) {
+        case "textDelta":
+          content += payload.text;
+          onProgress(content);
+          break;
+        case "reasoningDelta":
+          thinking += payload.text;
+          break;
+        case "usage":
+          // 用量为累计值
+          usage.inputTokens = payload.inputTokens ?? usage.inputTokens;
+          usage.outputTokens = payload.outputTokens ?? usage.outputTokens;
+          usage.cachedTokens = payload.cachedTokens ?? usage.cachedTokens;
+          break;
+        case "warning":
+          console.warn("[llmService] 流式提示:", payload.message);
+          warnings.push(payload.message);
+          break;
+        case "error":
+          streamError = payload.message;
+          break;
+        case "cancelled":
+          streamError = "请求已取消";
+          break;
+      }
+    });
+
+    unlistenDone = await listen<void>(`stream-done://${channelId}`, () => {
+      cleanup();
+      if (streamError) {
+        resolve({
+          content: content || undefined,
+          error: streamError,
+          errorDetails: buildErrorDetails(new Error(streamError), {
+            model: params.model,
+            provider: provider.name,
+            requestUrl: params.baseUrl,
+          }),
+        });
+        return;
+      }
+      resolve({
+        content,
+        thinking: thinking || undefined,
+        usage,
+        warnings: warnings.length ? warnings : undefined,
+      });
+    });
+
+    try {
+      await invoke("chat_completion_stream", {
+        protocol,
+        channelId,
+        params: { ...params, canvasId: getActiveCanvasId() },
+      });
+    } catch (error) {
+      console.error("[llmService] Failed to start stream:", error);
+      cleanup();
+      const commandError = toCommandError(error);
+      resolve({
+        error: commandError.message,
+        errorDetails: buildErrorDetails(commandError, {
+          model: params.model,
+          provider: provider.name,
+          requestUrl: params.baseUrl,
+        }),
+      });
+    }
+  });
+}
+
 // Web 环境下的 LLM 调用 (支持 Proxy)
 async function invokeWebLLM(params: TauriLLMParams, provider: Provider): Promise<LLMResponse> {
   // 目前仅支持 OpenAI 协议 (Lemon API) 的 Web 回退
@@ -280,13 +454,19 @@ export async function generateText(params: LLMGenerationParams): Promise<LLMResp
     const requestParams: TauriLLMParams = {
       baseUrl,
       apiKey: provider.apiKey,
+      providerId: vaultProviderId(provider),
       model: params.model,
       prompt: params.prompt,
+      messages: params.messages,
       systemPrompt: params.systemPrompt,
       temperature: params.temperature,
       maxTokens: params.maxTokens,
       files: params.files,
       responseJsonSchema: params.responseJsonSchema,
+      tools: params.tools,
+      toolChoice: params.toolChoice,
+      documentMode: params.documentMode,
+      reasoning: params.reasoning,
     };
 
     // 检查是否在 Tauri 环境
@@ -294,6 +474,10 @@ export async function generateText(params: LLMGenerationParams): Promise<LLMResp
       return await invokeWebLLM(requestParams, provider);
     }
 
+    if (params.onProgress && !params.tools?.length) {
+      return await invokeLLMStream(requestParams, provider, params.onProgress);
+    }
+
     return await invokeLLMByProtocol(requestParams, provider);
   } catch (error) {
     const message = error instanceof Error ? error.message : "生成失败";
@@ -315,13 +499,19 @@ export async function generateLLMContent(params: LLMGenerationParams): Promise<L
     const requestParams: TauriLLMParams = {
       baseUrl,
       apiKey: provider.apiKey,
+      providerId: vaultProviderId(provider),
       model: params.model,
       prompt: params.prompt,
+      messages: params.messages,
       systemPrompt: params.systemPrompt,
       temperature: params.temperature,
       maxTokens: params.maxTokens,
       files: params.files,
       responseJsonSchema: params.responseJsonSchema,
+      tools: params.tools,
+      toolChoice: params.toolChoice,
+      documentMode: params.documentMode,
+      reasoning: params.reasoning,
     };
 
     // 检查是否在 Tauri 环境
@@ -329,6 +519,10 @@ export async function generateLLMContent(params: LLMGenerationParams): Promise<L
       return await invokeWebLLM(requestParams, provider);
     }
 
+    if (params.onProgress && !params.tools?.length) {
+      return await invokeLLMStream(requestParams, provider, params.onProgress);
+    }
+
     return await invokeLLMByProtocol(requestParams, provider);
   } catch (error) {
     const message = error instanceof Error ? error.message : "生成失败";
@@ -0,0 +1,37 @@
+import { invokeCommand } from "@/utils/apiError";
+
+export type LogLevel = "error" | "warn" | "info" | "debug" | "trace";
+
+// get_logs 查询条件
+export interface LogQuery {
+  limit?: number; // 最多返回条数，默认 500
+  level?: LogLevel; // 最低级别
+  requestId?: string; // 只返回该请求的日志
+}
+
+// 后端日志（已脱敏）
+export interface LogEntry {
+  timestamp: string;
+  level: string; // "INFO" / "WARN" 等
+  target: string; // Rust 模块路径
+  message: string;
+  requestId?: string;
+  fields: Record<string, unknown>;
+}
+
+// 读取最近的后端日志
+export async function getLogs(query?: LogQuery): Promise<LogEntry[]> {
+  return await invokeCommand<LogEntry[]>("get_logs", { query });
+}
+
+// 选择保存位置并导出诊断信息，用户取消时返回 null
+export async function exportDiagnostics(): Promise<string | null> {
+  const { save } = await import("@tauri-apps/plugin-dialog");
+  const outputPath = await save({
+    defaultPath: `nextlemon-diagnostics-${Date.now()}.log`,
+    filters: [{ name: "诊断日志", extensions: ["log", "txt"] }],
+  });
+  if (!outputPath) return null;
+
+  return await invokeCommand<string>("export_diagnostics", { outputPath });
+}
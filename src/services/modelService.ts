@@ -0,0 +1,67 @@
+import type { ApiError, Provider, ProviderProtocol } from "@/types";
+import { invokeCommand } from "@/utils/apiError";
+import { vaultProviderId } from "@/services/credentialService";
+
+export type ModelCapability = "text" | "imageOutput" | "vision" | "video";
+
+// 后端归一化后的模型信息
+export interface ModelInfo {
+  id: string;
+  displayName: string;
+  contextWindow?: number; // 输入 token 上限
+  maxOutputTokens?: number;
+  capabilities: ModelCapability[];
+}
+
+// 查询供应商可用的模型（后端缓存一小时，refresh 为 true 时重新拉取）
+export async function listModels(provider: Provider, refresh = false): Promise<ModelInfo[]> {
+  const baseUrl = provider.baseUrl.replace(/\/+$/, "");
+  return await invokeCommand<ModelInfo[]>("list_models", {
+    params: {
+      protocol: provider.protocol,
+      // Gemini 与对话命令一致，需带上版本路径
+      baseUrl: provider.protocol === "google" ? `${baseUrl}/v1beta` : baseUrl,
+      apiKey: provider.apiKey,
+      providerId: vaultProviderId(provider),
+      refresh,
+    },
+  });
+}
+
+// 按能力筛选模型，供模型选择器使用
+export function filterModels(models: ModelInfo[], capability: ModelCapability): ModelInfo[] {
+  return models.filter((model) => model.capabilities.includes(capability));
+}
+
+export type ConnectionTarget = ProviderProtocol | "video";
+
+// 供应商连接测试结果
+export interface ProviderTestResult {
+  success: boolean;
+  reachable: boolean;
+  authenticated?: boolean;
+  modelFound?: boolean;
+  latencyMs?: number;
+  status?: number;
+  message: string;
+  error?: ApiError;
+  responseBody?: string; // 网关返回的错误内容（已脱敏）
+}
+
+// 测试供应商的连通性、凭据与模型（只调用免费的模型接口）
+export async function testProviderConnection(
+  provider: Pick<Provider, "baseUrl" | "apiKey" | "protocol"> & { id?: string },
+  options: { target?: ConnectionTarget; model?: string } = {}
+): Promise<ProviderTestResult> {
+  const target = options.target ?? provider.protocol;
+  const baseUrl = provider.baseUrl.replace(/\/+$/, "");
+  return await invokeCommand<ProviderTestResult>("test_provider_connection", {
+    params: {
+      target,
+      baseUrl: target === "google" ? `${baseUrl}/v1beta` : baseUrl,
+      apiKey: provider.apiKey,
+      providerId: provider.id,
+      model: options.model,
+    },
+  });
+}
@@ -0,0 +1,18 @@
+import { invokeCommand } from "@/utils/apiError";
+import type { NetworkSettings } from "@/types";
+
+// 检测是否在 Tauri 环境中
+const isTauri = () => {
+  return typeof window !== "undefined" && "__TAURI_INTERNALS__" in window;
+};
+
+// 将网络设置同步到 Rust 端的共享 HTTP 客户端（代理、根证书、超时等）
+export async function applyNetworkSettings(network?: NetworkSettings): Promise<void> {
+  if (!isTauri()) return;
+
+  try {
+    await invokeCommand("configure_http_client", { config: network ?? {} });
+  } catch (error) {
+    console.error("[networkService] 应用网络设置失败:", error instanceof Error ? error.message : error);
+  }
+}
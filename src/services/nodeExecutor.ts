@@ -360,6 +360,7 @@ async function executeLLMContentNode(
       temperature: data.temperature,
       maxTokens: data.maxTokens,
       files: files.length > 0 ? files : undefined,
+      reasoning: data.reasoningEffort ? { effort: data.reasoningEffort } : undefined,
     });
 
     // 检查中断
@@ -383,6 +384,7 @@ async function executeLLMContentNode(
     updateNodeDataWithCanvas<LLMContentNodeData>(node.id, canvasId, {
       status: "success",
       outputContent: response.content,
+      outputThinking: response.thinking,
       error: undefined,
     });
 
@@ -5,6 +5,8 @@
 
 import { invoke } from "@tauri-apps/api/core";
 import type { PPTPageData } from "@/components/nodes/PPTAssemblerNode/types";
+import type { ApiError } from "@/types";
+import { toCommandError } from "@/utils/apiError";
 
 // ==================== 类型定义 ====================
 
@@ -46,7 +48,8 @@ interface ProcessPageResult {
   success: boolean;
   backgroundImage: string | null;
   textBoxes: TextBox[];
-  error: string | null;
+  error: ApiError | null;
+  attempts: number;
 }
 
 /** 连接测试结果 */
@@ -88,7 +91,7 @@ export async function processPageForEditable(
   });
 
   if (!result.success || !result.backgroundImage) {
-    throw new Error(result.error || "处理失败");
+    throw toCommandError(result.error, "处理失败");
   }
 
   return {
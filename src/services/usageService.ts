@@ -0,0 +1,65 @@
+import { useCanvasStore } from "@/stores/canvasStore";
+import { invokeCommand } from "@/utils/apiError";
+import type { ApiError } from "@/types";
+
+// 单次调用或汇总的用量
+export interface TokenUsage {
+  inputTokens: number;
+  outputTokens: number;
+  cachedTokens: number; // 命中缓存的输入 token（已包含在 inputTokens 中）
+  images: number;
+  videoSeconds: number;
+}
+
+// 模型单价（美元），token 按每百万计价
+export interface ModelPrice {
+  inputPerMillion?: number;
+  outputPerMillion?: number;
+  cachedInputPerMillion?: number;
+  perImage?: number;
+  perVideoSecond?: number;
+}
+
+export type UsageGroupBy = "day" | "canvas" | "model";
+
+export interface UsageQuery {
+  from?: string; // YYYY-MM-DD
+  to?: string; // YYYY-MM-DD
+  canvasId?: string;
+  model?: string;
+  groupBy?: UsageGroupBy;
+}
+
+export interface UsageSummary extends TokenUsage {
+  key: string;
+  requests: number;
+  cost: number;
+  unpricedRequests: number; // 未配置价格、未计入 cost 的调用次数
+}
+
+export interface UsageQueryResult {
+  success: boolean;
+  groups: UsageSummary[];
+  total: UsageSummary;
+  error?: ApiError;
+}
+
+// 当前画布 ID，随请求传给后端用于用量记账
+export function getActiveCanvasId(): string | undefined {
+  return useCanvasStore.getState().activeCanvasId ?? undefined;
+}
+
+// 按日期、画布或模型汇总用量
+export async function queryUsage(params: UsageQuery): Promise<UsageQueryResult> {
+  return await invokeCommand<UsageQueryResult>("query_usage", { params });
+}
+
+// 获取价格表（键为模型名或模型名前缀）
+export async function getUsagePrices(): Promise<Record<string, ModelPrice>> {
+  return await invoke<Record<string, ModelPrice>>("get_usage_prices");
+}
+
+// 保存价格表（只影响之后记录的调用）；后端只保存与内置价格不同的条目，省略的模型恢复为内置价格
+export async function setUsagePrices(prices: Record<string, ModelPrice>): Promise<void> {
+  await invokeCommand("set_usage_prices", { prices });
+}
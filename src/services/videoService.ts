@@ -1,8 +1,11 @@
 import { invoke } from "@tauri-apps/api/core";
-import type { VideoGenerationParams, VideoGenerationResponse, ErrorDetails } from "@/types";
+import type { VideoGenerationParams, VideoGenerationResponse, ErrorDetails, ApiError } from "@/types";
 import { useSettingsStore } from "@/stores/settingsStore";
 import { isTauriEnvironment } from "@/services/fileStorageService";
 import { toast } from "@/stores/toastStore";
+import { getActiveCanvasId } from "@/services/usageService";
+import { vaultProviderId } from "@/services/credentialService";
+import { CommandError, toCommandError } from "@/utils/apiError";
 
 // 任务阶段类型
 export type VideoTaskStage = "queued" | "in_progress" | "completed" | "failed";
@@ -18,16 +21,19 @@ export interface VideoProgressInfo {
 interface TauriVideoCreateParams {
   baseUrl: string;
   apiKey: string;
+  providerId?: string;  // 凭据库中的供应商 ID，Key 已保存时 apiKey 为空
   model: string;
   prompt: string;
   seconds?: string;
   size?: string;
   inputImage?: string;  // base64
+  canvasId?: string;    // 用量记账归属的画布
 }
 
 interface TauriVideoStatusParams {
   baseUrl: string;
   apiKey: string;
+  providerId?: string;
   taskId: string;
 }
 
@@ -37,13 +43,15 @@ interface TauriVideoTaskResult {
   taskId?: string;
   status?: string;
   progress?: number;
-  error?: string;
+  error?: ApiError;
+  attempts?: number; // 实际请求次数（含重试）
+  warnings?: string[]; // 参考图片被重新压缩等提示
 }
 
 interface TauriVideoContentResult {
   success: boolean;
   videoData?: string;  // base64
-  error?: string;
+  error?: ApiError;
 }
 
 // 获取 API 配置
@@ -60,7 +68,7 @@ function getApiConfig() {
     throw new Error("供应商不存在，请重新配置");
   }
 
-  if (!provider.apiKey) {
+  if (!provider.apiKey && !provider.hasStoredKey) {
     throw new Error("供应商 API Key 未配置");
   }
 
@@ -68,6 +76,7 @@ function getApiConfig() {
   const baseUrl = provider.baseUrl.replace(/\/+$/, "");
 
   return {
+    providerId: vaultProviderId(provider),
     apiKey: provider.apiKey,
     baseUrl,
     name: provider.name,
@@ -90,11 +99,15 @@ function buildErrorDetails(
   // 解析错误消息，尝试提取状态码
   const message = isError ? error.message : String(error);
   const statusCodeMatch = message.match(/\((\d{3})\)/);
-  const statusCode = statusCodeMatch ? parseInt(statusCodeMatch[1], 10) : undefined;
+  const commandError = error instanceof CommandError ? error : undefined;
+  const statusCode = commandError?.status ?? (statusCodeMatch ? parseInt(statusCodeMatch[1], 10) : undefined);
 
   const details: ErrorDetails = {
     name: isError ? error.name : "Error",
     message,
+    code: commandError?.code,
+    retryable: commandError?.retryable,
+    providerErrorType: commandError?.providerErrorType,
     stack: isError ? error.stack : undefined,
     cause: isError && "cause" in error ? error.cause : undefined,
     statusCode,
@@ -138,7 +151,7 @@ export async function createVideoTask(
     }
 
     const config = getApiConfig();
-    const { apiKey, baseUrl, name: providerName } = config;
+    const { providerId, apiKey, baseUrl, name: providerName } = config;
 
     // 构建完整的请求 URL
     const fullRequestUrl = `${baseUrl}/v1/video/generations`;
@@ -155,11 +168,13 @@ export async function createVideoTask(
     const tauriParams: TauriVideoCreateParams = {
       baseUrl,
       apiKey,
+      providerId,
       model: params.model,
       prompt: params.prompt,
       seconds: params.seconds,
       size: params.size,
       inputImage: params.inputImage,
+      canvasId: getActiveCanvasId(),
     };
 
     console.log("[videoService] Creating video task via Tauri backend...");
@@ -180,10 +195,10 @@ export async function createVideoTask(
     }
 
     if (!result.success) {
-      const errorMessage = result.error || "创建任务失败";
+      const error = toCommandError(result.error, "创建任务失败");
       return {
-        error: errorMessage,
-        errorDetails: buildErrorDetails(new Error(errorMessage), {
+        error: error.message,
+        errorDetails: buildErrorDetails(error, {
           model: params.model,
           provider: providerName,
           requestUrl: fullRequestUrl,
@@ -196,6 +211,7 @@ export async function createVideoTask(
       taskId: result.taskId,
       status: result.status as VideoGenerationResponse["status"],
       progress: result.progress,
+      warnings: result.warnings,
     };
   } catch (error) {
     const message = error instanceof Error ? error.message : "创建视频任务失败";
@@ -226,25 +242,26 @@ export async function getVideoTaskStatus(taskId: string): Promise<VideoGeneratio
       return { error: "此功能仅在桌面应用中可用" };
     }
 
-    const { apiKey, baseUrl } = getApiConfig();
+    const { providerId, apiKey, baseUrl } = getApiConfig();
 
     const tauriParams: TauriVideoStatusParams = {
       baseUrl,
       apiKey,
+      providerId,
       taskId,
     };
 
     const result = await invoke<TauriVideoTaskResult>("video_get_status", { params: tauriParams });
 
     if (!result.success) {
-      return { error: result.error || "获取状态失败" };
+      return { error: result.error?.message || "获取状态失败" };
     }
 
     return {
       taskId: result.taskId,
       status: result.status as VideoGenerationResponse["status"],
       progress: result.progress,
-      error: result.error,
+      error: result.error?.message,
     };
   } catch (error) {
     const message = error instanceof Error ? error.message : "获取任务状态失败";
@@ -268,11 +285,12 @@ export async function getVideoContentBlobUrl(taskId: string): Promise<{ url?: st
       return { error: "此功能仅在桌面应用中可用" };
     }
 
-    const { apiKey, baseUrl } = getApiConfig();
+    const { providerId, apiKey, baseUrl } = getApiConfig();
 
     const tauriParams: TauriVideoStatusParams = {
       baseUrl,
       apiKey,
+      providerId,
       taskId,
     };
 
@@ -280,7 +298,7 @@ export async function getVideoContentBlobUrl(taskId: string): Promise<{ url?: st
     const result = await invoke<TauriVideoContentResult>("video_get_content", { params: tauriParams });
 
     if (!result.success || !result.videoData) {
-      return { error: result.error || "获取视频失败" };
+      return { error: result.error?.message || "获取视频失败" };
     }
 
     // 将 base64 转换为 Blob URL
@@ -308,12 +326,13 @@ export async function downloadVideo(taskId: string, filename?: string): Promise<
       return { success: false, error: "此功能仅在桌面应用中可用" };
     }
 
-    const { apiKey, baseUrl } = getApiConfig();
+    const { providerId, apiKey, baseUrl } = getApiConfig();
     const defaultFileName = filename || `sora-video-${Date.now()}.mp4`;
 
     const tauriParams: TauriVideoStatusParams = {
       baseUrl,
       apiKey,
+      providerId,
       taskId,
     };
 
@@ -321,7 +340,7 @@ export async function downloadVideo(taskId: string, filename?: string): Promise<
     const result = await invoke<TauriVideoContentResult>("video_get_content", { params: tauriParams });
 
     if (!result.success || !result.videoData) {
-      const errorMsg = result.error || "下载视频失败";
+      const errorMsg = result.error?.message || "下载视频失败";
       toast.error(`下载失败: ${errorMsg}`);
       return { success: false, error: errorMsg };
     }
@@ -2,6 +2,8 @@ import { create } from "zustand";
 import { persist, createJSONStorage } from "zustand/middleware";
 import type { AppSettings, SettingsState, Provider, NodeProviderMapping, ProviderProtocol } from "@/types";
 import { tauriStorage } from "@/utils/tauriStorage";
+import { applyNetworkSettings } from "@/services/networkService";
+import { deleteApiKey, isCredentialVaultAvailable, setApiKey } from "@/services/credentialService";
 
 // 默认设置
 const defaultSettings: AppSettings = {
@@ -39,6 +41,26 @@ function migrateProviders(providers: Provider[]): Provider[] {
   });
 }
 
+// 将 API Key 移入后端凭据库，保存成功后再清除前端持久化的明文
+async function moveKeyToVault(providerId: string, apiKey: string) {
+  if (!isCredentialVaultAvailable() || !apiKey) return;
+
+  try {
+    await setApiKey(providerId, apiKey);
+    useSettingsStore.setState((state) => ({
+      settings: {
+        ...state.settings,
+        providers: state.settings.providers.map((p) =>
+          // 保存期间 Key 可能又被修改，只清除本次保存的那个
+          p.id === providerId && p.apiKey === apiKey ? { ...p, apiKey: "", hasStoredKey: true } : p
+        ),
+      },
+    }));
+  } catch (error) {
+    console.error("[settingsStore] 保存 API Key 到凭据库失败:", error);
+  }
+}
+
 export type SettingsTab = "general" | "providers" | "storage" | "shortcuts" | "about";
 
 interface SettingsStore extends SettingsState {
@@ -82,13 +104,19 @@ export const useSettingsStore = create<SettingsStore>()(
       isSettingsOpen: false,
       settingsTab: "general",
 
-      updateSettings: (newSettings) =>
+      updateSettings: (newSettings) => {
         set((state) => ({
           settings: { ...state.settings, ...newSettings },
-        })),
+        }));
+        if ("network" in newSettings) {
+          applyNetworkSettings(newSettings.network);
+        }
+      },
 
-      resetSettings: () =>
-        set({ settings: defaultSettings }),
+      resetSettings: () => {
+        set({ settings: defaultSettings });
+        applyNetworkSettings(defaultSettings.network);
+      },
 
       openSettings: (tab = "general") =>
         set({ isSettingsOpen: true, settingsTab: tab }),
@@ -105,21 +133,33 @@ export const useSettingsStore = create<SettingsStore>()(
             providers: [...state.settings.providers, { ...provider, id }],
           },
         }));
+        moveKeyToVault(id, provider.apiKey);
         return id;
       },
 
-      updateProvider: (id, updates) =>
+      updateProvider: (id, updates) => {
         set((state) => ({
           settings: {
             ...state.settings,
-            providers: state.settings.providers.map((p) =>
-              p.id === id ? { ...p, ...updates } : p
-            ),
+            providers: state.settings.providers.map((p) => {
+              if (p.id !== id) return p;
+              // Key 已在凭据库中且未重新填写时保持不变
+              const keepStoredKey = p.hasStoredKey && !updates.apiKey;
+              return keepStoredKey ? { ...p, ...updates, apiKey: p.apiKey } : { ...p, ...updates };
+            }),
           },
-        })),
+        }));
+        if (updates.apiKey) {
+          moveKeyToVault(id, updates.apiKey);
+        }
+      },
 
       removeProvider: (id) =>
         set((state) => {
+          if (isCredentialVaultAvailable() && state.settings.providers.some((p) => p.id === id && p.hasStoredKey)) {
+            deleteApiKey(id).catch((error) => console.error("[settingsStore] 删除 API Key 失败:", error));
+          }
+
           // 移除供应商时，同时清除相关的节点映射
           const newNodeProviders = { ...state.settings.nodeProviders };
           for (const key of Object.keys(newNodeProviders) as (keyof NodeProviderMapping)[]) {
@@ -176,6 +216,12 @@ export const useSettingsStore = create<SettingsStore>()(
             console.error("[settingsStore] 数据恢复失败:", error);
             return;
           }
+          // 启动时同步网络设置到 Rust 端
+          applyNetworkSettings(state?.settings.network);
+          // 旧版本保存在前端的 Key 迁移到凭据库
+          state?.settings.providers
+            .filter((p) => p.apiKey && !p.hasStoredKey)
+            .forEach((p) => moveKeyToVault(p.id, p.apiKey));
           if (state && state.settings.providers.length > 0) {
             // 执行供应商数据迁移
             const migratedProviders = migrateProviders(state.settings.providers);
@@ -1,9 +1,39 @@
 import type { Node, Edge } from "@xyflow/react";
 
 // 详细错误信息结构
+// 后端错误类别（与 Rust ErrorCode 保持一致）
+export type ApiErrorCode =
+  | "invalid_api_key"
+  | "permission_denied"
+  | "quota_exceeded"
+  | "rate_limited"
+  | "invalid_request"
+  | "not_found"
+  | "content_filtered"
+  | "output_truncated"
+  | "timeout"
+  | "network"
+  | "service_unavailable"
+  | "malformed_response"
+  | "provider_error"
+  | "cancelled"
+  | "storage";
+
+// 后端命令返回的结构化错误
+export interface ApiError {
+  code: ApiErrorCode;
+  status?: number;             // HTTP 状态码
+  providerErrorType?: string;  // 供应商错误类型，如 insufficient_quota
+  retryable: boolean;
+  message: string;
+}
+
 export interface ErrorDetails {
   name?: string;           // 错误名称（如 API_Error, NetworkError）
   message: string;         // 错误消息
+  code?: ApiErrorCode;     // 错误类别
+  retryable?: boolean;     // 重试是否可能成功
+  providerErrorType?: string; // 供应商错误类型
   stack?: string;          // 堆栈信息
   cause?: unknown;         // 错误原因
   statusCode?: number;     // HTTP 状态码
@@ -15,6 +45,15 @@ export interface ErrorDetails {
   nodeId?: string;         // 发生错误的节点 ID
   model?: string;          // 使用的模型
   provider?: string;       // 使用的供应商
+  block?: GenerationBlock; // 被安全策略等拦截时的原因
+}
+
+// 生成被拦截的原因（Gemini promptFeedback / finishReason）
+export interface GenerationBlock {
+  stage: "prompt" | "candidate";
+  reason: string;          // 如 SAFETY、RECITATION、MAX_TOKENS、IMAGE_SAFETY、BLOCKLIST
+  category?: string;       // 触发拦截的安全类别
+  message?: string;
 }
 
 // 模型类型（图片生成）- 支持自定义模型名称
@@ -66,6 +105,7 @@ export interface VideoGenerationResponse {
   progress?: number;
   error?: string;
   errorDetails?: ErrorDetails;  // 详细错误信息
+  warnings?: string[];          // 参考图片被重新压缩等提示
 }
 
 // 图片生成参数
@@ -75,19 +115,42 @@ export interface ImageGenerationParams {
   aspectRatio?: "1:1" | "16:9" | "9:16" | "4:3" | "3:4" | "3:2" | "2:3" | "5:4" | "4:5" | "21:9";
   imageSize?: "1K" | "2K" | "4K";
   responseModalities?: ("TEXT" | "IMAGE")[];
+  candidateCount?: number; // 候选数量（Gemini 协议；OpenAI 协议对应 n）
+  quality?: "low" | "medium" | "high" | "auto"; // 仅 OpenAI Images API
+  background?: "transparent" | "opaque" | "auto"; // 仅 OpenAI Images API
 }
 
 // 图片编辑参数
 export interface ImageEditParams extends ImageGenerationParams {
   inputImages?: string[]; // base64 编码的图片数组（支持多图输入）
+  mask?: string; // 编辑蒙版（PNG，透明区域为待修改部分，仅 OpenAI Images API）
 }
 
 // API 响应
+// 生成的单张图片
+export interface GeneratedImage {
+  data: string; // base64 编码的图片数据
+  mimeType: string;
+}
+
+// 单个候选结果
+export interface GenerationCandidate {
+  images: GeneratedImage[];
+  text?: string;
+  thinking?: string; // 思考摘要，不包含在 text 中
+  finishReason?: string;
+  block?: GenerationBlock;
+}
+
 export interface GenerationResponse {
-  imageData?: string; // base64 编码的图片数据
+  imageData?: string; // base64 编码的图片数据（第一张）
   text?: string;
+  thinking?: string;                     // 第一个候选的思考摘要
+  images?: GeneratedImage[];             // 全部图片
+  candidates?: GenerationCandidate[];    // 全部候选
   error?: string;
   errorDetails?: ErrorDetails;  // 详细错误信息
+  warnings?: string[];          // 输入图片被缩放或重新压缩等提示
 }
 
 // 节点数据类型 - 添加索引签名以满足 React Flow 的 Record<string, unknown> 约束
@@ -141,6 +204,9 @@ export interface VideoGeneratorNodeData {
 }
 
 // LLM 内容生成节点数据
+// 推理强度（Claude / Gemini 按对应的思考 token 预算换算）
+export type ReasoningEffort = "low" | "medium" | "high";
+
 export interface LLMContentNodeData {
   [key: string]: unknown;
   label: string;
@@ -148,8 +214,10 @@ export interface LLMContentNodeData {
   systemPrompt: string;
   temperature: number;
   maxTokens: number;
+  reasoningEffort?: ReasoningEffort; // 未设置时沿用模型默认行为
   status: "idle" | "loading" | "success" | "error";
   outputContent?: string;
+  outputThinking?: string; // 模型的思考过程（与正文分开保存）
   error?: string;
   errorDetails?: ErrorDetails;  // 详细错误信息
 }
@@ -209,9 +277,10 @@ export type ProviderProtocol = 'openai' | 'google' | 'claude';
 export interface Provider {
   id: string;           // 唯一标识 (uuid)
   name: string;         // 供应商名称
-  apiKey: string;       // API Key
+  apiKey: string;       // API Key（桌面端保存到后端凭据库后为空）
   baseUrl: string;      // Base URL（不包含版本路径如 /v1beta）
   protocol: ProviderProtocol;  // API 协议类型
+  hasStoredKey?: boolean;      // API Key 已保存在后端凭据库
 }
 
 // 节点类型到供应商的映射
@@ -223,12 +292,36 @@ export interface NodeProviderMapping {
   llmContent?: string;          // LLM 内容生成节点使用的供应商 ID
 }
 
+// 网络设置（Rust 端共享 HTTP 客户端）
+export interface NetworkSettings {
+  proxyUrl?: string;                  // 代理地址（http/https/socks5）
+  rootCertificates?: string[];        // 额外信任的根证书（PEM）
+  acceptInvalidCerts?: boolean;       // 跳过证书校验（仅用于自签名的内网服务）
+  userAgent?: string;
+  connectTimeoutSecs?: number;
+  timeouts?: Partial<Record<
+    | "chat"
+    | "chatStream"
+    | "geminiImage"
+    | "lemonStream"
+    | "openaiImage"
+    | "videoCreate"
+    | "videoStatus"
+    | "videoContent"
+    | "ocr"
+    | "healthCheck"
+    | "modelList",
+    number
+  >>;                                 // 按服务覆盖请求超时（秒）
+}
+
 // 应用设置
 export interface AppSettings {
   providers: Provider[];              // 供应商列表
   nodeProviders: NodeProviderMapping; // 节点类型 -> 供应商映射
   enableCustomProviders: boolean;     // 是否启用自定义供应商管理
   theme: "light" | "dark" | "system";
+  network?: NetworkSettings;          // 网络设置
 }
 
 // Store 状态
@@ -0,0 +1,46 @@
+import { invoke, type InvokeArgs } from "@tauri-apps/api/core";
+import type { ApiError, ApiErrorCode } from "@/types";
+
+// 携带后端错误类别的 Error，便于 catch 后按 code 处理
+export class CommandError extends Error {
+  code: ApiErrorCode;
+  status?: number;
+  providerErrorType?: string;
+  retryable: boolean;
+
+  constructor(apiError: ApiError) {
+    super(apiError.message);
+    this.name = "CommandError";
+    this.code = apiError.code;
+    this.status = apiError.status;
+    this.providerErrorType = apiError.providerErrorType;
+    this.retryable = apiError.retryable;
+  }
+}
+
+// 判断是否为后端返回的结构化错误
+export function isApiError(value: unknown): value is ApiError {
+  return (
+    typeof value === "object" &&
+    value !== null &&
+    typeof (value as ApiError).code === "string" &&
+    typeof (value as ApiError).message === "string"
+  );
+}
+
+// 将后端结果中的 error 字段或 invoke 抛出的错误统一转换为 Error
+export function toCommandError(error: unknown, fallback = "请求失败"): Error {
+  if (error instanceof Error) return error;
+  if (isApiError(error)) return new CommandError(error);
+  if (typeof error === "string" && error) return new Error(error);
+  return new Error(fallback);
+}
+
+// 调用 Tauri 命令，失败时抛出 CommandError 而不是原始对象
+export async function invokeCommand<T>(command: string, args?: InvokeArgs): Promise<T> {
+  try {
+    return await invoke<T>(command, args);
+  } catch (error) {
+    throw toCommandError(error);
+  }
+}